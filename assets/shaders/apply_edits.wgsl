@@ -0,0 +1,107 @@
+#import compute::common::{Params, DataGrid, DataStrip, TerrainEdits, Edit, GRID_EDIT_PASS, EDIT_ADD, EDIT_SUBTRACT, EDIT_SMOOTH, BRUSH_CIRCLE}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var itex_1: texture_storage_2d<rgba32float, read>;
+@group(0) @binding(2) var otex_1: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(3) var itex_2: texture_storage_2d<rgba32float, read>;
+@group(0) @binding(4) var otex_2: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(5) var itex_3: texture_storage_2d<rgba32float, read>;
+@group(0) @binding(6) var otex_3: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(7) var<storage, read_write> grid_a: DataGrid;
+@group(0) @binding(8) var<storage, read_write> grid_b: DataGrid;
+@group(0) @binding(9) var<storage, read_write> strip_a: DataStrip;
+@group(0) @binding(10) var<storage, read_write> strip_b: DataStrip;
+@group(0) @binding(11) var grad_tex: texture_storage_2d<rgba32float, read>;
+@group(0) @binding(12) var<storage, read_write> rock_mask: array<f32>;
+@group(0) @binding(13) var<storage, read_write> edits: TerrainEdits;
+
+/*
+Replay the recorded terrain edits over the generated rock, so they survive regeneration.
+
+Each pass applies one batch of edits. Smooth edits read their neighbours so they always get a
+batch to themselves, everything else is applied in order within a batch.
+*/
+
+// 0 outside the brush, 1 inside, with a one texel soft edge on circles
+fn coverage(edit: Edit, p: vec2f) -> f32 {
+    if (edit.brush == BRUSH_CIRCLE) {
+        return 1. - smoothstep(edit.radius - 1., edit.radius, distance(p, edit.centre));
+    }
+
+    // even-odd test against the polygon
+    var inside = false;
+    var j = edit.vertex_count - 1u;
+    for (var i = 0u; i < edit.vertex_count; i++) {
+        let a = edits.vertices[edit.vertex_start + i];
+        let b = edits.vertices[edit.vertex_start + j];
+        if ((a.y > p.y) != (b.y > p.y) && p.x < (b.x - a.x) * (p.y - a.y) / (b.y - a.y) + a.x) {
+            inside = !inside;
+        }
+        j = i;
+    }
+    return select(0., 1., inside);
+}
+
+fn neighbourhood_average(upos: vec2<i32>) -> f32 {
+    let dim = i32(params.dimensions);
+    var total = 0.;
+    var count = 0.;
+    for (var dy = -1; dy <= 1; dy++) {
+        for (var dx = -1; dx <= 1; dx++) {
+            let n = upos + vec2(dx, dy);
+            if (all(n >= vec2(0)) && all(n < vec2(dim))) {
+                total += textureLoad(itex_1, n).r;
+                count += 1.;
+            }
+        }
+    }
+    return total / count;
+}
+
+@compute @workgroup_size(16, 16)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let x = global_id.x;
+    let y = global_id.y;
+
+    if (x >= params.dimensions || y >= params.dimensions) {
+        return;
+    }
+
+    let upos = vec2<i32>(i32(x), i32(y));
+    // texel centre, matching the contours. Edits are in texels of the whole planet, which a
+    // tile covers only part of.
+    let p = params.tile_origin * f32(params.dimensions) + (vec2f(upos) + 0.5) * params.tile_scale;
+
+    var rock = textureLoad(itex_1, upos).r;
+
+    let pass_index = u32(grid_a.ints[x][y][GRID_EDIT_PASS]);
+    if (pass_index < edits.batch_count) {
+        let batch = edits.batches[pass_index];
+        for (var e = batch.x; e < batch.y; e++) {
+            let edit = edits.edits[e];
+            if (any(p < edit.bounds_min) || any(p > edit.bounds_max)) {
+                continue;
+            }
+
+            let amount = coverage(edit, p) * edit.strength;
+            switch edit.op {
+                case EDIT_ADD: {
+                    rock = mix(rock, 1., amount);
+                }
+                case EDIT_SUBTRACT: {
+                    rock = mix(rock, 0., amount);
+                }
+                case EDIT_SMOOTH: {
+                    rock = mix(rock, neighbourhood_average(upos), amount);
+                }
+                default: {}
+            }
+        }
+    }
+    grid_a.ints[x][y][GRID_EDIT_PASS] = i32(pass_index + 1u);
+
+    textureStore(otex_1, upos, vec4f(rock, 0., 0., 1.));
+    textureStore(otex_2, upos, textureLoad(itex_2, upos));
+    textureStore(otex_3, upos, textureLoad(itex_3, upos));
+    rock_mask[y * params.dimensions + x] = rock;
+}
@@ -1,6 +1,6 @@
 #import compute::noise
 #import compute::utils
-#import compute::common::{Params, BUFFER_LEN, DataGrid}
+#import compute::common::{Params, BUFFER_LEN, DataGrid, SEED_CAVES, planet_texel}
 
 @group(0) @binding(0) var<uniform> params: Params;
 @group(0) @binding(1) var itex_1: texture_storage_2d<rgba32float, read>;
@@ -11,7 +11,7 @@
 @group(0) @binding(6) var otex_3: texture_storage_2d<rgba32float, write>;
 @group(0) @binding(7) var<storage, read_write> grid_a: DataGrid;
 @group(0) @binding(8) var<storage, read_write> grid_b: DataGrid;
-@group(0) @binding(9) var grad_tex: texture_storage_2d<rgba32float, read>;
+@group(0) @binding(11) var grad_tex: texture_storage_2d<rgba32float, read>;
 
 // /*
 // Generate the initial noise which is the starting point for the cellular automata,
@@ -69,7 +69,9 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
 
     let pos = vec2f(f32(x), f32(y));
     let upos = vec2<i32>(i32(x), i32(y));
-    let v = noise::rand11(f32(x * y * y));
+    // seeded from the planet texel so neighbouring tiles agree
+    let t = vec2<u32>(planet_texel(upos, params.dimensions, params.tile_origin, params.tile_scale));
+    let v = noise::hash_texel(t, noise::derive_seed(params.seed, SEED_CAVES));
     let s = select(0.,1.,v <= params.noise_weight);
     var current = textureLoad(itex_1, upos);
 
@@ -2,6 +2,7 @@
 // #import compute::utils
 #import compute::anoise::{psrdnoise2, fbma, terrain_gpt, terrain_claude, terrain_corrected, generate_varied_terrain}
 #import compute::common::{Params, BUFFER_LEN, STRIP_SIZE, DataGrid, DataStrip}
+#import compute::pass::{converged, count_change}
 
 
 @group(0) @binding(0) var<uniform> params: Params;
@@ -58,6 +59,43 @@ fn get_weighted_neighbor_count(x: i32, y: i32, radius: f32) -> f32 {
     return normed;
 }
 
+// The rules set from src/ca_rules.rs, a texel is live from 0.5 and out of bounds is live too
+fn is_live(x: i32, y: i32) -> bool {
+    let dim = i32(params.dimensions);
+    if (x < 0 || x >= dim || y < 0 || y >= dim) {
+        return true;
+    }
+    return textureLoad(itex_2, vec2<i32>(x, y)).r >= 0.5;
+}
+
+fn in_neighbourhood(i: i32, j: i32) -> bool {
+#ifdef CA_VON_NEUMANN
+    return abs(i) + abs(j) == 1;
+#else
+    return i != 0 || j != 0;
+#endif
+}
+
+fn neighbourhood_size() -> u32 {
+#ifdef CA_VON_NEUMANN
+    return 4u;
+#else
+    return 8u;
+#endif
+}
+
+fn live_neighbours(x: i32, y: i32) -> u32 {
+    var live = 0u;
+    for(var i = -1; i <= 1; i++) {
+        for(var j = -1; j <= 1; j++) {
+            if (in_neighbourhood(i, j) && is_live(x + i, y + j)) {
+                live += 1u;
+            }
+        }
+    }
+    return live;
+}
+
 fn remap(value: f32, from_min: f32, from_max: f32, to_min: f32, to_max: f32) -> f32 {
     let from_range = from_max - from_min;
     let to_range = to_max - to_min;
@@ -75,7 +113,23 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
     }
 
     let upos = vec2<i32>(i32(x), i32(y));
+    let previous = textureLoad(itex_2, upos);
+
+    if (converged()) {
+        textureStore(otex_2, upos, previous);
+        return;
+    }
     
+#ifdef CA_RULE_CLASSIC
+    // birth with a dead texel, survival with a live one
+    let live = live_neighbours(i32(x), i32(y));
+    let mask = select(params.ca_birth, params.ca_survive, is_live(i32(x), i32(y)));
+    let caves = f32((mask >> live) & 1u);
+#else ifdef CA_RULE_SMOOTH
+    // the majority of the texel and its neighbours, both sizes are odd so there are no ties
+    let total = live_neighbours(i32(x), i32(y)) + u32(is_live(i32(x), i32(y)));
+    let caves = select(0., 1., total * 2u > neighbourhood_size() + 1u);
+#else
     let scaled_radius = params.ca_search_radius * (8.0 / (f32(params.dimensions) / 128.0));
     let nbs = get_weighted_neighbor_count(i32(x), i32(y), scaled_radius);
     var thresh = params.ca_thresh; 
@@ -93,8 +147,12 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
         1.,
         nbs > thresh,
     );
+#endif
 
     
+    if (caves != previous.r) {
+        count_change();
+    }
     textureStore(otex_2, upos, vec4f(caves, 0., 0., 0.));
 
 }
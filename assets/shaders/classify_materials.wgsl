@@ -0,0 +1,59 @@
+#import compute::common::{Params, DataGrid, DataStrip, Ores, MAX_ORES, GRID_PLANET_MASK, GRID_PLANET_SDF, GRID_MATERIAL, MATERIAL_SPACE, MATERIAL_CRUST, MATERIAL_DEEP_ROCK, MATERIAL_CAVE_AIR}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var itex_1: texture_storage_2d<rgba32float, read>;
+@group(0) @binding(2) var otex_1: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(3) var itex_2: texture_storage_2d<rgba32float, read>;
+@group(0) @binding(4) var otex_2: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(5) var itex_3: texture_storage_2d<rgba32float, read>;
+@group(0) @binding(6) var otex_3: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(7) var<storage, read_write> grid_a: DataGrid;
+@group(0) @binding(8) var<storage, read_write> grid_b: DataGrid;
+@group(0) @binding(9) var<storage, read_write> strip_a: DataStrip;
+@group(0) @binding(10) var<storage, read_write> strip_b: DataStrip;
+@group(0) @binding(11) var grad_tex: texture_storage_2d<rgba32float, read>;
+@group(0) @binding(14) var<storage, read_write> ores: Ores;
+
+/*
+Assign every cell a material id from its depth below the planet surface and whether it's rock.
+The ids go in to grid_a.ints, see MaterialTable in src/materials.rs for what they mean.
+place_ores runs next and swaps some of the rock for ore, then the liquid stages fill some of
+the cave air.
+*/
+
+@compute @workgroup_size(16, 16)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let x = global_id.x;
+    let y = global_id.y;
+
+    if (x >= params.dimensions || y >= params.dimensions) {
+        return;
+    }
+
+    // place_ores counts in to these, it's a separate dispatch so this finishes first
+    if (x == 0u && y == 0u) {
+        for (var i = 0u; i < MAX_ORES; i++) {
+            atomicStore(&ores.counts[i], 0u);
+        }
+    }
+
+    let upos = vec2<i32>(i32(x), i32(y));
+
+    let rock = textureLoad(itex_1, upos).r > 0.5;
+    let in_planet = grid_a.floats[x][y][GRID_PLANET_MASK] > 0.5;
+    // the sdf is in texels of this texture, a tile has more of them across the planet
+    let depth = -grid_a.floats[x][y][GRID_PLANET_SDF] * params.tile_scale;
+
+    var material = MATERIAL_SPACE;
+    if (rock) {
+        material = select(MATERIAL_DEEP_ROCK, MATERIAL_CRUST, depth < params.crust_depth);
+    } else if (in_planet) {
+        material = MATERIAL_CAVE_AIR;
+    }
+
+    grid_a.ints[x][y][GRID_MATERIAL] = i32(material);
+
+    textureStore(otex_1, upos, textureLoad(itex_1, upos));
+    textureStore(otex_2, upos, textureLoad(itex_2, upos));
+    textureStore(otex_3, upos, textureLoad(itex_3, upos));
+}
@@ -0,0 +1,301 @@
+#define_import_path compute::common
+
+struct Params {
+    dimensions: u32,
+
+    // circle generator
+    radius: f32,
+    // the master seed, each stage draws its own from it with noise::derive_seed
+    seed: u32,
+    noise_freq: f32,
+    noise_amplitude: f32,
+    noise_offset: f32,
+    noise_octaves: i32,
+    noise_lacunarity:f32,
+
+    // naga_oil rejects struct members that end in a digit, bevy renames them when composing
+    noise_params: NoiseParams,
+
+    power_bias: f32,
+    flatness: f32,
+    steepness: f32,
+    mix: f32,
+    // noise_warp_amount: f32,
+    // noise_warp_scale: f32,
+
+
+    domain_warp_1_settings: DomainWarpParams,
+
+    // domain warp 1
+    domain_warp_1_amount_a: f32,
+    domain_warp_1_scale_a: f32,
+    domain_warp_1_amount_b: f32,
+    domain_warp_1_scale_b: f32,
+    
+    // cellular automata
+    noise_weight: f32,
+    ca_thresh: f32,
+    ca_search_radius: f32,
+    ca_edge_pow: f32,
+    edge_suppress_mix: f32,
+
+    // cave domain warp
+    domain_warp_2_amount_a: f32,
+    domain_warp_2_scale_a: f32,
+    domain_warp_2_amount_b: f32,
+    domain_warp_2_scale_b: f32,
+
+    misc_f: f32,
+    misc_i: i32,
+    botty: f32,
+
+    // jump flood step per pass, four to a vec4, see jfa_schedule in src/parameters.rs
+    jfa_steps: array<vec4<u32>, 4>,
+    jfa_pass_count: u32,
+    jfa_refinements: u32,
+    jfa_padding_a: u32,
+    jfa_padding_b: u32,
+
+    // the part of the planet this texture covers, in planet uv, see src/chunks.rs
+    tile_origin: vec2<f32>,
+    tile_scale: f32,
+    // texels a warp iteration can move a texel at most, 0 for no limit, so chunks agree
+    tile_warp_limit: f32,
+
+    // material classification, depths are in texels of the untiled planet
+    crust_depth: f32,
+    lava_depth: f32,
+    material_padding_a: u32,
+    material_padding_b: u32,
+
+    // liquids, see liquid_prepare and liquid_run
+    liquid_fill: f32,
+    water_viscosity: f32,
+    lava_viscosity: f32,
+    liquid_padding: u32,
+
+    // erosion of the height profile, see erode_thermal and erode_hydraulic
+    talus_angle: f32,
+    rain: f32,
+    evaporation: f32,
+    sediment_capacity: f32,
+
+    // the classic ca rule, bit n of each mask is n live neighbours, see ca_run
+    ca_birth: u32,
+    ca_survive: u32,
+    ca_padding_a: u32,
+    ca_padding_b: u32,
+}
+
+const BUFFER_LEN = 1024u;
+const GRID_SIZE = 8u;
+
+const STRIP_SIZE = 8192u;
+const STRIP_COUNT = 3u;
+
+// grid_a float channels
+const GRID_DIST_TO_CENTER = 0u;
+const GRID_DIST_TO_EDGE = 1u;
+const GRID_NORMALIZED_DIST_TO_EDGE = 2u;
+const GRID_DEFORMED_RADIUS = 3u;
+// solid planet before the caves are subtracted
+const GRID_PLANET_MASK = 4u;
+// signed distance in texels, negative inside the rock
+const GRID_PLANET_SDF = 5u;
+const GRID_CAVE_SDF = 6u;
+
+// grid_a int channels, 0 and 3 are free, the jump flood and liquid_run take their pass from
+// compute::pass
+// edit batch counter for apply_edits
+const GRID_EDIT_PASS = 1u;
+// material id written by classify_materials
+const GRID_MATERIAL = 2u;
+
+// these need to match Material in src/materials.rs
+const MATERIAL_SPACE = 0u;
+const MATERIAL_CRUST = 1u;
+const MATERIAL_DEEP_ROCK = 2u;
+const MATERIAL_CAVE_AIR = 3u;
+const MATERIAL_WATER = 4u;
+const MATERIAL_LAVA = 5u;
+// ore definition i is material MATERIAL_FIRST_ORE + i
+const MATERIAL_FIRST_ORE = 6u;
+
+// what the liquid stages keep in texture 1 green
+const LIQUID_NONE = 0u;
+const LIQUID_WATER = 1u;
+const LIQUID_LAVA = 2u;
+
+// the stages' salts for noise::derive_seed, these need to match the SEED_ constants in
+// src/cpu_reference.rs
+const SEED_HEIGHTS = 1u;
+const SEED_WARP_1 = 2u;
+const SEED_WARP_2 = 3u;
+const SEED_CAVES = 4u;
+const SEED_ORES = 5u;
+const SEED_LIQUIDS = 6u;
+
+const MAX_MATERIALS = 16u;
+
+const MAX_ORES = 8u;
+
+const MAX_CONVERGENCE_ITERATIONS = 2048u;
+
+const ORE_NOISE_SIMPLEX = 0u;
+const ORE_NOISE_FBM = 1u;
+const ORE_NOISE_VORONOI = 2u;
+const ORE_NOISE_PSRD = 3u;
+
+// see GpuOre in src/ores.rs, depths are in texels of the untiled planet
+struct OreDefinition {
+    min_depth: f32,
+    max_depth: f32,
+    // noise above this is ore
+    threshold: f32,
+    // noise frequency in planet uv
+    scale: f32,
+    noise: u32,
+    seed: u32,
+    padding_a: u32,
+    padding_b: u32,
+}
+
+struct Ores {
+    count: u32,
+    padding_a: u32,
+    padding_b: u32,
+    padding_c: u32,
+    ores: array<OreDefinition, MAX_ORES>,
+    // texels of each ore, reset by classify_materials and read back for OreCounts
+    counts: array<atomic<u32>, MAX_ORES>,
+}
+
+// colour per material id, see MaterialTable in src/materials.rs
+struct MaterialColours {
+    colours: array<vec4<f32>, MAX_MATERIALS>,
+}
+
+const MAX_EDITS = 1024u;
+const MAX_EDIT_VERTICES = 8192u;
+
+const EDIT_ADD = 0u;
+const EDIT_SUBTRACT = 1u;
+const EDIT_SMOOTH = 2u;
+
+const BRUSH_CIRCLE = 0u;
+const BRUSH_POLYGON = 1u;
+
+// see GpuEdit in src/edits.rs, positions are in texels
+struct Edit {
+    op: u32,
+    brush: u32,
+    strength: f32,
+    radius: f32,
+    centre: vec2<f32>,
+    vertex_start: u32,
+    vertex_count: u32,
+    bounds_min: vec2<f32>,
+    bounds_max: vec2<f32>,
+}
+
+struct TerrainEdits {
+    edit_count: u32,
+    batch_count: u32,
+    // first and one past the last edit applied by each pass of apply_edits
+    batches: array<vec2<u32>, MAX_EDITS>,
+    edits: array<Edit, MAX_EDITS>,
+    vertices: array<vec2<f32>, MAX_EDIT_VERTICES>,
+}
+
+// Position on the whole planet, 0 to 1 across it. Without tiling this is just the texel over
+// the dimensions.
+fn planet_pos(upos: vec2<i32>, dimensions: u32, tile_origin: vec2<f32>, tile_scale: f32) -> vec2<f32> {
+    return tile_origin + vec2<f32>(upos) / f32(dimensions) * tile_scale;
+}
+
+// Texel coordinate on the whole planet, the same for a texel whichever tile generates it
+fn planet_texel(upos: vec2<i32>, dimensions: u32, tile_origin: vec2<f32>, tile_scale: f32) -> vec2<i32> {
+    return vec2<i32>(round(tile_origin * f32(dimensions) / tile_scale)) + upos;
+}
+
+const PI = 3.14159265359;
+const TAU = 6.283185307179586;
+
+struct DataGrid{
+    floats: array<array<array<f32, GRID_SIZE>, BUFFER_LEN>, BUFFER_LEN>,
+    ints: array<array<array<i32, GRID_SIZE>, BUFFER_LEN>, BUFFER_LEN>,
+};
+
+// strip channels, the erosion stages ping-pong the height profile, water and sediment between
+// copy 0 in strip_b and copy 1 in strip_a, the pass counter says which one is current
+const STRIP_HEIGHT = 0u;
+const STRIP_WATER = 1u;
+const STRIP_SEDIMENT = 2u;
+// strip_a int channel
+const STRIP_EROSION_PASS = 0u;
+
+struct DataStrip{
+    floats: array<array<f32, STRIP_SIZE>, STRIP_COUNT>,
+    ints: array<array<i32, STRIP_SIZE>, STRIP_COUNT>,
+};
+
+struct NoiseParams{
+    seed: i32,
+    x: f32,
+    y: f32,
+    amplitude: f32,
+    freq: f32,
+    offset: f32,
+    lacunarity: f32,  
+    octaves: i32,
+}
+
+struct DomainWarpParams{
+    amount_a: f32,
+    scale_a: f32,
+    amount_b: f32,
+    scale_b: f32
+}
+
+// controls what the extract pass displays, see ViewParams in src/parameters.rs
+struct ViewParams{
+    stop_stage: i32,
+    stop_iteration: u32,
+    source: u32,
+    grid_channel: u32,
+    channel: u32,
+    false_colour: u32,
+    range_min: f32,
+    range_max: f32,
+
+    // lit view, see ViewParams in src/parameters.rs
+    light_angle: f32,
+    light_elevation: f32,
+    light_intensity: f32,
+    ambient: f32,
+    relief: f32,
+    bevel_width: f32,
+    shadow_softness: f32,
+    shadow_length: f32,
+    ao_radius: f32,
+    ao_strength: f32,
+    atmosphere_width: f32,
+    atmosphere_strength: f32,
+    atmosphere_colour: vec4<f32>,
+}
+
+const VIEW_TEXTURE_1 = 0u;
+const VIEW_TEXTURE_2 = 1u;
+const VIEW_TEXTURE_3 = 2u;
+const VIEW_GRID_A_FLOATS = 3u;
+const VIEW_GRID_A_INTS = 4u;
+const VIEW_GRID_B_FLOATS = 5u;
+const VIEW_GRID_B_INTS = 6u;
+const VIEW_MATERIALS = 7u;
+const VIEW_LIT = 8u;
+
+const VIEW_CHANNEL_RGB = 0u;
+const VIEW_CHANNEL_R = 1u;
+const VIEW_CHANNEL_G = 2u;
+const VIEW_CHANNEL_B = 3u;
+const VIEW_CHANNEL_A = 4u;
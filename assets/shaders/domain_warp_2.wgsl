@@ -1,6 +1,6 @@
 #import compute::noise
 #import compute::utils
-#import compute::common::{Params, BUFFER_LEN, DataGrid}
+#import compute::common::{Params, BUFFER_LEN, DataGrid, SEED_WARP_2, planet_pos}
 
 @group(0) @binding(0) var<uniform> params: Params;
 @group(0) @binding(1) var itex_1: texture_storage_2d<rgba32float, read>;
@@ -11,7 +11,7 @@
 @group(0) @binding(6) var otex_3: texture_storage_2d<rgba32float, write>;
 @group(0) @binding(7) var<storage, read_write> grid_a: DataGrid;
 @group(0) @binding(8) var<storage, read_write> grid_b: DataGrid;
-@group(0) @binding(9) var grad_tex: texture_storage_2d<rgba32float, read>;
+@group(0) @binding(11) var grad_tex: texture_storage_2d<rgba32float, read>;
 
 
 struct DomainWarpParams {
@@ -34,9 +34,14 @@ fn apply_domain_warp(pos: vec2<f32>, params: DomainWarpParams) -> vec2<f32> {
 
 fn sample_with_offset(pos: vec2<i32>, offset: vec2<f32>) -> vec4<f32> {
     let dim = f32(params.dimensions);
+    // the offset is in planet uv, a tile has more texels per unit
+    var texels = offset * dim / params.tile_scale;
+    if (params.tile_warp_limit > 0.0) {
+        texels = clamp(texels, vec2f(-params.tile_warp_limit), vec2f(params.tile_warp_limit));
+    }
     let new_pos = vec2<i32>(
-        i32(clamp(f32(pos.x) + offset.x * dim, 0.0, dim - 1.0)),
-        i32(clamp(f32(pos.y) + offset.y * dim, 0.0, dim - 1.0))
+        i32(clamp(f32(pos.x) + texels.x, 0.0, dim - 1.0)),
+        i32(clamp(f32(pos.y) + texels.y, 0.0, dim - 1.0))
     );
     return textureLoad(itex_2, new_pos);
 }
@@ -55,11 +60,13 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
     let dim = f32(params.dimensions);
     
     // Convert position to 0-1 range for noise generation
-    let pos = vec2<f32>(f32(x) / dim, f32(y) / dim);
+    let pos = planet_pos(upos, params.dimensions, params.tile_origin, params.tile_scale);
     
 
-    var noiseParam1 = noise::noise2(pos * params.domain_warp_2_scale_a);
-    var noiseParam2 = noise::noise2(vec2f(pos.x, params.domain_warp_2_amount_a));
+    let offset = noise::seed_offset(noise::derive_seed(params.seed, SEED_WARP_2), 0u);
+
+    var noiseParam1 = noise::noise2(pos * params.domain_warp_2_scale_a + offset);
+    var noiseParam2 = noise::noise2(vec2f(pos.x, params.domain_warp_2_amount_a) + offset);
 
 
     // First domain warp
@@ -67,8 +74,8 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
         // params.domain_warp_2_scale_a,
         noiseParam1,
         noiseParam2,
-        0.0,
-        0.0
+        offset.x,
+        offset.y
     );
     let offset1 = apply_domain_warp(pos, warp1_params);
     
@@ -76,8 +83,8 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
     let warp2_params = DomainWarpParams(
         params.domain_warp_2_scale_b,
         params.domain_warp_2_amount_b,
-        1.234, // Different offset for variety
-        5.678  // Different offset for variety
+        1.234 + offset.x, // Different offset for variety
+        5.678 + offset.y  // Different offset for variety
     );
     let offset2 = apply_domain_warp(pos + offset1, warp2_params);
     
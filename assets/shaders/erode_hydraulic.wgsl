@@ -0,0 +1,115 @@
+#import compute::common::{Params, STRIP_SIZE, DataGrid, DataStrip, STRIP_HEIGHT, STRIP_WATER, STRIP_SEDIMENT, STRIP_EROSION_PASS}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var itex_1: texture_storage_2d<rgba32float, read>;
+@group(0) @binding(2) var otex_1: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(3) var itex_2: texture_storage_2d<rgba32float, read>;
+@group(0) @binding(4) var otex_2: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(5) var itex_3: texture_storage_2d<rgba32float, read>;
+@group(0) @binding(6) var otex_3: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(7) var<storage, read_write> grid_a: DataGrid;
+@group(0) @binding(8) var<storage, read_write> grid_b: DataGrid;
+@group(0) @binding(9) var<storage, read_write> strip_a: DataStrip;
+@group(0) @binding(10) var<storage, read_write> strip_b: DataStrip;
+@group(0) @binding(11) var grad_tex: texture_storage_2d<rgba32float, read>;
+
+/*
+One step of hydraulic erosion on the height profile. Rain falls everywhere, water runs to
+lower neighbours carrying its sediment with it, and then each cell picks up sediment where
+the flow out of it could carry more than it has or drops it where it can carry less. Some of
+the water evaporates every step, dropping what it can no longer carry.
+
+Like erode_thermal every cell gathers the flows between itself and its neighbours from the
+last copy, so water and rock plus sediment are only changed by the rain and evaporation.
+*/
+
+// share of the difference from the carrying capacity picked up or dropped per step
+const ERODE_RATE = 0.3;
+const DEPOSIT_RATE = 0.3;
+
+// erosion state of strip cell i in copy 0 (strip_b) or copy 1 (strip_a), height, water, sediment
+fn load_state(copy: i32, i: u32) -> vec3f {
+    if (copy == 0) {
+        return vec3f(strip_b.floats[STRIP_HEIGHT][i], strip_b.floats[STRIP_WATER][i], strip_b.floats[STRIP_SEDIMENT][i]);
+    }
+    return vec3f(strip_a.floats[STRIP_HEIGHT][i], strip_a.floats[STRIP_WATER][i], strip_a.floats[STRIP_SEDIMENT][i]);
+}
+
+fn store_state(copy: i32, i: u32, state: vec3f) {
+    if (copy == 0) {
+        strip_b.floats[STRIP_HEIGHT][i] = state.x;
+        strip_b.floats[STRIP_WATER][i] = state.y;
+        strip_b.floats[STRIP_SEDIMENT][i] = state.z;
+    } else {
+        strip_a.floats[STRIP_HEIGHT][i] = state.x;
+        strip_a.floats[STRIP_WATER][i] = state.y;
+        strip_a.floats[STRIP_SEDIMENT][i] = state.z;
+    }
+}
+
+// neighbours wrap, the profile goes all the way round the planet
+fn wrap(i: u32, offset: i32) -> u32 {
+    return u32(i32(i + STRIP_SIZE) + offset) % STRIP_SIZE;
+}
+
+// water from a to b, at most half of a's water goes each way so it never runs dry twice
+fn water_flow(a: vec3f, b: vec3f) -> f32 {
+    let drop = (a.x + a.y) - (b.x + b.y);
+    return clamp(drop * 0.5, 0.0, a.y * 0.5);
+}
+
+// sediment moves in the same share as the water
+fn sediment_flow(a: vec3f, flow: f32) -> f32 {
+    return select(0.0, a.z * flow / a.y, a.y > 0.0);
+}
+
+@compute @workgroup_size(256, 1, 1)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let x = global_id.x;
+    if (x >= STRIP_SIZE) {
+        return;
+    }
+
+    let pass_index = strip_a.ints[STRIP_EROSION_PASS][x];
+    let copy = pass_index % 2;
+
+    // rain first, everywhere alike, so the neighbours can add it for themselves
+    let rain = vec3f(0.0, params.rain, 0.0);
+    let here = load_state(copy, x) + rain;
+    let left = load_state(copy, wrap(x, -1)) + rain;
+    let right = load_state(copy, wrap(x, 1)) + rain;
+
+    let to_left = water_flow(here, left);
+    let to_right = water_flow(here, right);
+    let from_left = water_flow(left, here);
+    let from_right = water_flow(right, here);
+
+    let out_water = to_left + to_right;
+    var water = here.y - out_water + from_left + from_right;
+    var sediment = here.z
+        - sediment_flow(here, to_left) - sediment_flow(here, to_right)
+        + sediment_flow(left, from_left) + sediment_flow(right, from_right);
+    var height = here.x;
+
+    // faster flow carries more
+    let capacity = params.sediment_capacity * out_water;
+    if (sediment > capacity) {
+        let deposit = (sediment - capacity) * DEPOSIT_RATE;
+        sediment -= deposit;
+        height += deposit;
+    } else {
+        let erode = (capacity - sediment) * ERODE_RATE;
+        sediment += erode;
+        height -= erode;
+    }
+
+    // what the evaporated water was carrying is left behind
+    let evaporated = water * clamp(params.evaporation, 0.0, 1.0);
+    let dropped = select(0.0, sediment * evaporated / water, water > 0.0);
+    water -= evaporated;
+    sediment -= dropped;
+    height += dropped;
+
+    store_state(1 - copy, x, vec3f(height, water, sediment));
+    strip_a.ints[STRIP_EROSION_PASS][x] = pass_index + 1;
+}
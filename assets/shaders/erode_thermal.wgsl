@@ -0,0 +1,83 @@
+#import compute::common::{Params, STRIP_SIZE, DataGrid, DataStrip, STRIP_HEIGHT, STRIP_WATER, STRIP_SEDIMENT, STRIP_EROSION_PASS, TAU}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var itex_1: texture_storage_2d<rgba32float, read>;
+@group(0) @binding(2) var otex_1: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(3) var itex_2: texture_storage_2d<rgba32float, read>;
+@group(0) @binding(4) var otex_2: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(5) var itex_3: texture_storage_2d<rgba32float, read>;
+@group(0) @binding(6) var otex_3: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(7) var<storage, read_write> grid_a: DataGrid;
+@group(0) @binding(8) var<storage, read_write> grid_b: DataGrid;
+@group(0) @binding(9) var<storage, read_write> strip_a: DataStrip;
+@group(0) @binding(10) var<storage, read_write> strip_b: DataStrip;
+@group(0) @binding(11) var grad_tex: texture_storage_2d<rgba32float, read>;
+
+/*
+One step of thermal erosion on the height profile. Wherever the slope to a neighbour is
+steeper than the talus angle, material slides down until it isn't. Every cell works out the
+flow in and out of itself from the last copy, so nothing is lost and no atomics are needed.
+*/
+
+// share of the excess above the talus slope that moves each step, a half per side at most
+const SLIDE_RATE = 0.25;
+
+// erosion state of strip cell i in copy 0 (strip_b) or copy 1 (strip_a), height, water, sediment
+fn load_state(copy: i32, i: u32) -> vec3f {
+    if (copy == 0) {
+        return vec3f(strip_b.floats[STRIP_HEIGHT][i], strip_b.floats[STRIP_WATER][i], strip_b.floats[STRIP_SEDIMENT][i]);
+    }
+    return vec3f(strip_a.floats[STRIP_HEIGHT][i], strip_a.floats[STRIP_WATER][i], strip_a.floats[STRIP_SEDIMENT][i]);
+}
+
+fn store_state(copy: i32, i: u32, state: vec3f) {
+    if (copy == 0) {
+        strip_b.floats[STRIP_HEIGHT][i] = state.x;
+        strip_b.floats[STRIP_WATER][i] = state.y;
+        strip_b.floats[STRIP_SEDIMENT][i] = state.z;
+    } else {
+        strip_a.floats[STRIP_HEIGHT][i] = state.x;
+        strip_a.floats[STRIP_WATER][i] = state.y;
+        strip_a.floats[STRIP_SEDIMENT][i] = state.z;
+    }
+}
+
+// neighbours wrap, the profile goes all the way round the planet
+fn wrap(i: u32, offset: i32) -> u32 {
+    return u32(i32(i + STRIP_SIZE) + offset) % STRIP_SIZE;
+}
+
+// the height difference between neighbours at the talus angle, init_generate_circle turns a
+// unit of height in to 0.02 * amplitude of planet uv and the cells are 1 / STRIP_SIZE of the
+// way round the circumference
+fn talus_height() -> f32 {
+    let cell = TAU * params.radius / f32(STRIP_SIZE);
+    let height_to_uv = max(0.02 * params.noise_amplitude, 0.00001);
+    return tan(params.talus_angle) * cell / height_to_uv;
+}
+
+fn slide(from_height: f32, to_height: f32, talus: f32) -> f32 {
+    return max(from_height - to_height - talus, 0.0) * SLIDE_RATE;
+}
+
+@compute @workgroup_size(256, 1, 1)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let x = global_id.x;
+    if (x >= STRIP_SIZE) {
+        return;
+    }
+
+    let pass_index = strip_a.ints[STRIP_EROSION_PASS][x];
+    let copy = pass_index % 2;
+
+    let here = load_state(copy, x);
+    let left = load_state(copy, wrap(x, -1)).x;
+    let right = load_state(copy, wrap(x, 1)).x;
+    let talus = talus_height();
+
+    let out_flow = slide(here.x, left, talus) + slide(here.x, right, talus);
+    let in_flow = slide(left, here.x, talus) + slide(right, here.x, talus);
+
+    store_state(1 - copy, x, vec3f(here.x - out_flow + in_flow, here.yz));
+    strip_a.ints[STRIP_EROSION_PASS][x] = pass_index + 1;
+}
@@ -0,0 +1,221 @@
+// extract
+
+#import compute::noise
+#import compute::utils
+#import compute::common::{Params, ViewParams, MaterialColours, GRID_MATERIAL, GRID_PLANET_SDF, GRID_CAVE_SDF, MAX_MATERIALS, MATERIAL_SPACE, MATERIAL_LAVA, BUFFER_LEN, DataGrid, DataStrip, VIEW_TEXTURE_2, VIEW_TEXTURE_3, VIEW_GRID_A_FLOATS, VIEW_GRID_A_INTS, VIEW_GRID_B_FLOATS, VIEW_GRID_B_INTS, VIEW_MATERIALS, VIEW_LIT, VIEW_CHANNEL_RGB, VIEW_CHANNEL_R, VIEW_CHANNEL_G, VIEW_CHANNEL_B}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var itex_1: texture_storage_2d<rgba32float, read>;
+@group(0) @binding(2) var itex_2: texture_storage_2d<rgba32float, read>;
+@group(0) @binding(3) var itex_3: texture_storage_2d<rgba32float, read>;
+@group(0) @binding(4) var otex: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(5) var<storage, read_write> grid_a: DataGrid;
+@group(0) @binding(6) var<storage, read_write> grid_b: DataGrid;
+@group(0) @binding(7) var<storage, read_write> strip_a: DataStrip;
+@group(0) @binding(8) var<storage, read_write> strip_b: DataStrip;
+@group(0) @binding(9) var grad_texture: texture_storage_2d<rgba32float, read>;
+@group(0) @binding(10) var<uniform> view: ViewParams;
+@group(0) @binding(11) var<uniform> materials: MaterialColours;
+
+
+fn load_texture(upos: vec2<i32>) -> vec4f {
+    switch view.source {
+        case VIEW_TEXTURE_2: {
+            return textureLoad(itex_2, upos);
+        }
+        case VIEW_TEXTURE_3: {
+            return textureLoad(itex_3, upos);
+        }
+        default: {
+            return textureLoad(itex_1, upos);
+        }
+    }
+}
+
+fn load_grid(x: u32, y: u32) -> f32 {
+    let ch = min(view.grid_channel, 7u);
+    switch view.source {
+        case VIEW_GRID_A_INTS: {
+            return f32(grid_a.ints[x][y][ch]);
+        }
+        case VIEW_GRID_B_FLOATS: {
+            return grid_b.floats[x][y][ch];
+        }
+        case VIEW_GRID_B_INTS: {
+            return f32(grid_b.ints[x][y][ch]);
+        }
+        default: {
+            return grid_a.floats[x][y][ch];
+        }
+    }
+}
+
+// remap from the selected value range to 0..1
+fn remap_range(v: vec3f) -> vec3f {
+    let range = max(view.range_max - view.range_min, 0.00001);
+    return clamp((v - view.range_min) / range, vec3f(0.), vec3f(1.));
+}
+
+fn false_colour(v: f32) -> vec3f {
+    let gx = i32(clamp(v, 0., 1.) * 255.);
+    return textureLoad(grad_texture, vec2<i32>(gx, 0)).rgb;
+}
+
+fn material_colour(x: u32, y: u32) -> vec3f {
+    let id = min(u32(grid_a.ints[x][y][GRID_MATERIAL]), MAX_MATERIALS - 1u);
+    return materials.colours[id].rgb;
+}
+
+/*
+Lit view. The rock is treated as a height field raised by relief over bevel_width of its edge,
+using the cave sdf, which gives normals for a directional light and height field shadows that
+soften with the distance to the occluder. Open cells darken near the walls for ambient
+occlusion and space gets a glow around the planet. Distances in the sdfs are in texels of this
+texture, the view parameters are in texels of the untiled planet.
+*/
+
+fn clamp_texel(p: vec2<i32>) -> vec2<u32> {
+    return vec2<u32>(clamp(p, vec2(0), vec2(i32(params.dimensions) - 1)));
+}
+
+fn cave_sdf(p: vec2<i32>) -> f32 {
+    let c = clamp_texel(p);
+    return grid_a.floats[c.x][c.y][GRID_CAVE_SDF] * params.tile_scale;
+}
+
+fn planet_sdf(p: vec2<i32>) -> f32 {
+    let c = clamp_texel(p);
+    return grid_a.floats[c.x][c.y][GRID_PLANET_SDF] * params.tile_scale;
+}
+
+fn height(p: vec2<i32>) -> f32 {
+    return view.relief * smoothstep(0.0, view.bevel_width, -cave_sdf(p));
+}
+
+fn light_dir() -> vec3f {
+    let flat_dir = vec2f(cos(view.light_angle), sin(view.light_angle));
+    return vec3f(flat_dir * cos(view.light_elevation), sin(view.light_elevation));
+}
+
+fn surface_normal(p: vec2<i32>) -> vec3f {
+    // central differences are over two texels, which are 2 * tile_scale untiled texels
+    let dx = height(p + vec2(1, 0)) - height(p - vec2(1, 0));
+    let dy = height(p + vec2(0, 1)) - height(p - vec2(0, 1));
+    return normalize(vec3f(-dx, -dy, 2.0 * params.tile_scale));
+}
+
+// 0 in shadow to 1 lit, marching the height field towards the light
+fn soft_shadow(p: vec2<i32>) -> f32 {
+    let l = light_dir();
+    let flat_dir = normalize(l.xy);
+    let rise = l.z / max(length(l.xy), 0.0001);
+    let start = height(p);
+
+    var lit = 1.0;
+    var t = 1.0;
+    for (var i = 0; i < 64 && t < view.shadow_length; i++) {
+        let q = p + vec2<i32>(round(flat_dir * t / params.tile_scale));
+        let above = start + rise * t - height(q);
+        if (above < 0.0) {
+            return 0.0;
+        }
+        lit = min(lit, view.shadow_softness * above / t);
+        t += max(view.shadow_length / 64.0, 1.0);
+    }
+    return clamp(lit, 0.0, 1.0);
+}
+
+fn lit_colour(x: u32, y: u32) -> vec3f {
+    let upos = vec2<i32>(i32(x), i32(y));
+    let id = u32(grid_a.ints[x][y][GRID_MATERIAL]);
+    let l = light_dir();
+
+    // space, black with the atmosphere on the side of the planet facing the light
+    if (id == MATERIAL_SPACE) {
+        let d = planet_sdf(upos);
+        let grad = vec2f(
+            planet_sdf(upos + vec2(1, 0)) - planet_sdf(upos - vec2(1, 0)),
+            planet_sdf(upos + vec2(0, 1)) - planet_sdf(upos - vec2(0, 1)),
+        );
+        let outward = normalize(grad + vec2f(0.00001));
+        let facing = mix(0.25, 1.0, max(dot(outward, normalize(l.xy)), 0.0));
+        let glow = exp(-max(d, 0.0) / max(view.atmosphere_width, 0.0001));
+        return view.atmosphere_colour.rgb * glow * facing * view.atmosphere_strength;
+    }
+
+    let albedo = material_colour(x, y);
+    let diffuse = max(dot(surface_normal(upos), l), 0.0) * soft_shadow(upos);
+
+    // only the open cells, the rock is solid
+    var ao = 1.0;
+    let sdf = cave_sdf(upos);
+    if (sdf > 0.0) {
+        ao = mix(1.0, smoothstep(0.0, view.ao_radius, sdf), view.ao_strength);
+    }
+
+    var colour = albedo * (view.ambient * ao + view.light_intensity * diffuse);
+    if (id == MATERIAL_LAVA) {
+        colour = max(colour, albedo);
+    }
+    return colour;
+}
+
+@compute @workgroup_size(16, 16)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let x = global_id.x;
+    let y = global_id.y;
+    
+    if (x >= params.dimensions || y >= params.dimensions) {
+        return;
+    }
+
+    let upos = vec2<i32>(i32(x), i32(y));
+
+    // material ids straight to their colours, no remapping
+    if (view.source == VIEW_MATERIALS) {
+        textureStore(otex, upos, vec4f(material_colour(x, y), 1.0));
+        return;
+    }
+
+    if (view.source == VIEW_LIT) {
+        textureStore(otex, upos, vec4f(lit_colour(x, y), 1.0));
+        return;
+    }
+    
+    // isolate a single channel, or keep rgb
+    var value: vec3f;
+    var single = true;
+    if (view.source >= VIEW_GRID_A_FLOATS) {
+        value = vec3f(load_grid(x, y));
+    } else {
+        let current = load_texture(upos);
+        switch view.channel {
+            case VIEW_CHANNEL_R: {
+                value = vec3f(current.r);
+            }
+            case VIEW_CHANNEL_G: {
+                value = vec3f(current.g);
+            }
+            case VIEW_CHANNEL_B: {
+                value = vec3f(current.b);
+            }
+            case VIEW_CHANNEL_RGB: {
+                value = current.rgb;
+                single = false;
+            }
+            default: {
+                value = vec3f(current.a);
+            }
+        }
+    }
+
+    value = remap_range(value);
+
+    if (view.false_colour != 0u && single) {
+        value = false_colour(value.r);
+    }
+
+    let out = vec4f(value, 1.0);
+
+    textureStore(otex, upos, out);
+}
@@ -1,6 +1,6 @@
 #import compute::noise
 #import compute::utils
-#import compute::common::{Params, BUFFER_LEN, STRIP_SIZE, DataGrid, DataStrip, PI, TAU}
+#import compute::common::{Params, BUFFER_LEN, STRIP_SIZE, DataGrid, DataStrip, STRIP_HEIGHT, STRIP_EROSION_PASS, PI, TAU, planet_pos}
 
 
 @group(0) @binding(0) var<uniform> params: Params;
@@ -82,10 +82,7 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
     let dim = params.dimensions;
     
     // normalize the coordinates
-    var pos = vec2f(
-        f32(x) / f32(dim),
-        f32(y) / f32(dim)
-    );
+    var pos = planet_pos(upos, params.dimensions, params.tile_origin, params.tile_scale);
     
 
 
@@ -123,7 +120,9 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
     // Clamp index to valid range
     // let clamped_index = clamp(index, 0, STRIP_SIZE - 1);
 
-    let nze = strip_b.floats[0][index];
+    // whichever copy the erosion stages finished on
+    let eroded_copy = strip_a.ints[STRIP_EROSION_PASS][index] % 2;
+    let nze = select(strip_b.floats[STRIP_HEIGHT][index], strip_a.floats[STRIP_HEIGHT][index], eroded_copy == 1);
     
     // Distance from center
     let dist_to_center = length(centered);
@@ -1,7 +1,7 @@
 #import compute::noise
 #import compute::utils
 #import compute::anoise::{psrdnoise2, fbma, terrain_gpt, terrain_claude, terrain_corrected, generate_varied_terrain}
-#import compute::common::{Params, BUFFER_LEN, STRIP_SIZE, DataGrid, DataStrip}
+#import compute::common::{Params, BUFFER_LEN, STRIP_SIZE, DataGrid, DataStrip, STRIP_HEIGHT, STRIP_WATER, STRIP_SEDIMENT, STRIP_EROSION_PASS, SEED_HEIGHTS}
 
 
 @group(0) @binding(0) var<uniform> params: Params;
@@ -74,10 +74,16 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
     let coord = linearToCircle(fx, f32(STRIP_SIZE));
 
     
-    let voroPos = coord * params.noise_freq * 10.;
+    let seed = noise::derive_seed(params.seed, SEED_HEIGHTS);
+
+#ifdef USE_VORONOI
+    let voroPos = coord * params.noise_freq * 10. + noise::seed_offset(seed, 0u);
     var vorro = voroNoise2(voroPos, 0.5, 0.3);
     vorro = vorro * 2. -1.;
     vorro = clamp(vorro, -1., 1.);
+#else
+    let vorro = 0.0;
+#endif
 
     let base_period = 10000.0; // Example period
 
@@ -93,10 +99,15 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
     // // strip_a.floats[1][x] = nze2;
     // // strip_a.floats[2][x] = nze3;
     // strip_b.floats[0][x] = nze4;
-    let npos = coord * params.noise_freq * 0.1;
+    let npos = coord * params.noise_freq * 0.1 + noise::seed_offset(seed, 1u);
     let base_settings = vec4<f32>(lanc, 0.5, 10000., 0.0); // lacunarity, gain, period, rot
     let variation_settings = vec3<f32>(flat, steep, mix);   // ridge, warp, erosion
     let terrain = generate_varied_terrain(npos, 8u, base_settings, variation_settings) * 5.;
     
-    strip_b.floats[0][x] = vorro + terrain;
+    strip_b.floats[STRIP_HEIGHT][x] = vorro + terrain;
+
+    // dry, and the erosion stages start from copy 0
+    strip_b.floats[STRIP_WATER][x] = 0.0;
+    strip_b.floats[STRIP_SEDIMENT][x] = 0.0;
+    strip_a.ints[STRIP_EROSION_PASS][x] = 0;
 }
\ No newline at end of file
@@ -1,6 +1,5 @@
-#import compute::noise
-#import compute::utils
-#import compute::common::{Params, BUFFER_LEN, DataGrid}
+#import compute::common::{Params, DataGrid, DataStrip, GRID_PLANET_MASK, GRID_PLANET_SDF, GRID_CAVE_SDF}
+#import compute::pass::pass_info
 
 @group(0) @binding(0) var<uniform> params: Params;
 @group(0) @binding(1) var itex_1: texture_storage_2d<rgba32float, read>;
@@ -11,66 +10,94 @@
 @group(0) @binding(6) var otex_3: texture_storage_2d<rgba32float, write>;
 @group(0) @binding(7) var<storage, read_write> grid_a: DataGrid;
 @group(0) @binding(8) var<storage, read_write> grid_b: DataGrid;
-@group(0) @binding(9) var grad_tex: texture_storage_2d<rgba32float, read>;
+@group(0) @binding(9) var<storage, read_write> strip_a: DataStrip;
+@group(0) @binding(10) var<storage, read_write> strip_b: DataStrip;
+@group(0) @binding(11) var grad_tex: texture_storage_2d<rgba32float, read>;
 
+/*
+One jump flood pass. The step for each pass comes from params.jfa_steps, indexed by the stage's
+iteration.
 
-fn is_valid_point(p: vec2<i32>) -> bool {
-    return p.x >= 0 && 
-           p.y >= 0 && 
-           p.x < i32(params.dimensions) && 
-           p.y < i32(params.dimensions);
+Each pass writes the signed distances so far:
+texture 2 g: planet surface, b: cave walls
+grid_a floats GRID_PLANET_SDF and GRID_CAVE_SDF
+Distances are in texels and negative inside the rock.
+*/
+
+fn valid_seed(seed: vec2f) -> bool {
+    return seed.x >= 0.;
+}
+
+// keep whichever seed is closer to p
+fn closer(p: vec2f, best: vec2f, candidate: vec2f) -> vec2f {
+    if (!valid_seed(candidate)) {
+        return best;
+    }
+    if (!valid_seed(best) || distance(p, candidate) < distance(p, best)) {
+        return candidate;
+    }
+    return best;
+}
+
+// seeds sit on both sides of the boundary, so half a texel puts the zero crossing between them
+fn signed_distance(p: vec2f, seed: vec2f, inside: bool) -> f32 {
+    if (!valid_seed(seed)) {
+        return select(1e6, -1e6, inside);
+    }
+    let d = distance(p, seed) + 0.5;
+    return select(d, -d, inside);
 }
 
 @compute @workgroup_size(16, 16)
 fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
     let x = global_id.x;
     let y = global_id.y;
-    
+
     if (x >= params.dimensions || y >= params.dimensions) {
         return;
     }
 
     let upos = vec2<i32>(i32(x), i32(y));
-    
-    let current_1 = textureLoad(itex_1, upos);
-    
-    // get current step from the g channel
-    // starts at 512 and is reduced each iteration (usually 2, but can be more for better quality)
-    let step = i32(current_1.g);
-    if(step < 2){
-        // according to log n, we should be done by now
+    let dim = i32(params.dimensions);
+    let here = vec2f(upos);
+
+    let pass_index = pass_info.iteration;
+    let seeds = textureLoad(itex_3, upos);
+    let rock = textureLoad(itex_1, upos);
+    let caves = textureLoad(itex_2, upos);
+
+    // passes past the end of the schedule just carry everything through
+    if (pass_index >= params.jfa_pass_count) {
+        textureStore(otex_1, upos, rock);
+        textureStore(otex_2, upos, caves);
+        textureStore(otex_3, upos, seeds);
         return;
     }
 
-    var min_distance = current_1.r;  // This was set to a high value (ideally inf, not poss in wgsl) in the prepare pass
+    let step = i32(params.jfa_steps[pass_index / 4u][pass_index % 4u]);
+
+    var planet_seed = seeds.rg;
+    var rock_seed = seeds.ba;
 
-    // jump flood
-    for(var dy = -1; dy <= 1; dy ++){
-        for(var dx = -1; dx <=1; dx++) {
+    for (var dy = -1; dy <= 1; dy++) {
+        for (var dx = -1; dx <= 1; dx++) {
             let sample_pos = upos + vec2<i32>(dx, dy) * step;
-            if (is_valid_point(sample_pos)) {
-                let sample = textureLoad(itex_1, sample_pos);
-                
-                if (sample.x < 1000000.0) {  // If this is a boundary point or has distance info
-                    let offset = vec2<f32>(upos - sample_pos);
-                    let dist = length(offset);
-                    if (sample.x < 1.0) {  // If it's a boundary point
-                        min_distance = min(min_distance, dist);
-                    } else {  // If it has distance info from previous passes
-                        min_distance = min(min_distance, sample.x + dist);
-                    }
-                }
+            if (any(sample_pos < vec2(0)) || any(sample_pos >= vec2(dim))) {
+                continue;
             }
+            let sample = textureLoad(itex_3, sample_pos);
+            planet_seed = closer(here, planet_seed, sample.rg);
+            rock_seed = closer(here, rock_seed, sample.ba);
         }
     }
 
-    textureStore(otex_1, upos, vec4<f32>(
-                                        min_distance, 
-                                        // f32(step)/1.25, 
-                                        f32(step)/2, 
-                                        0.0, 
-                                        1.0
-                                        ));
-                                        
-    textureStore(otex_2,upos, textureLoad(itex_2,upos)); // todo test using the storage buffer to avoid constantly swapping textures
-}
\ No newline at end of file
+    let planet_sdf = signed_distance(here, planet_seed, grid_a.floats[x][y][GRID_PLANET_MASK] > 0.5);
+    let cave_sdf = signed_distance(here, rock_seed, rock.r > 0.5);
+
+    textureStore(otex_1, upos, rock);
+    textureStore(otex_2, upos, vec4f(caves.r, planet_sdf, cave_sdf, caves.a));
+    textureStore(otex_3, upos, vec4f(planet_seed, rock_seed));
+
+    grid_a.floats[x][y][GRID_PLANET_SDF] = planet_sdf;
+    grid_a.floats[x][y][GRID_CAVE_SDF] = cave_sdf;
+}
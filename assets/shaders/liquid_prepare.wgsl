@@ -0,0 +1,51 @@
+#import compute::noise
+#import compute::common::{Params, DataGrid, DataStrip, GRID_PLANET_SDF, GRID_MATERIAL, MATERIAL_CAVE_AIR, MATERIAL_WATER, LIQUID_NONE, LIQUID_WATER, LIQUID_LAVA, SEED_LIQUIDS, planet_texel}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var itex_1: texture_storage_2d<rgba32float, read>;
+@group(0) @binding(2) var otex_1: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(3) var itex_2: texture_storage_2d<rgba32float, read>;
+@group(0) @binding(4) var otex_2: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(5) var itex_3: texture_storage_2d<rgba32float, read>;
+@group(0) @binding(6) var otex_3: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(7) var<storage, read_write> grid_a: DataGrid;
+@group(0) @binding(8) var<storage, read_write> grid_b: DataGrid;
+@group(0) @binding(9) var<storage, read_write> strip_a: DataStrip;
+@group(0) @binding(10) var<storage, read_write> strip_b: DataStrip;
+@group(0) @binding(11) var grad_tex: texture_storage_2d<rgba32float, read>;
+
+/*
+Scatter liquid through the cave air for liquid_run to settle. liquid_fill is the share of cave
+cells that start out liquid, so once it has fallen about that much of each cave is full.
+Liquid deeper than lava_depth is lava. The liquid goes in texture 1 green, see LIQUID_ in
+common.wgsl, and its material in to grid_a.
+*/
+
+@compute @workgroup_size(16, 16)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let x = global_id.x;
+    let y = global_id.y;
+
+    if (x >= params.dimensions || y >= params.dimensions) {
+        return;
+    }
+
+    let upos = vec2<i32>(i32(x), i32(y));
+    let rock = textureLoad(itex_1, upos);
+
+    var liquid = LIQUID_NONE;
+    if (u32(grid_a.ints[x][y][GRID_MATERIAL]) == MATERIAL_CAVE_AIR) {
+        // seeded from the planet texel so neighbouring tiles agree
+        let t = vec2<u32>(planet_texel(upos, params.dimensions, params.tile_origin, params.tile_scale));
+        let v = noise::hash_texel(t, noise::derive_seed(params.seed, SEED_LIQUIDS));
+        if (v < params.liquid_fill) {
+            let depth = -grid_a.floats[x][y][GRID_PLANET_SDF] * params.tile_scale;
+            liquid = select(LIQUID_WATER, LIQUID_LAVA, depth > params.lava_depth);
+            grid_a.ints[x][y][GRID_MATERIAL] = i32(MATERIAL_WATER + liquid - 1u);
+        }
+    }
+
+    textureStore(otex_1, upos, vec4f(rock.r, f32(liquid), rock.b, rock.a));
+    textureStore(otex_2, upos, textureLoad(itex_2, upos));
+    textureStore(otex_3, upos, textureLoad(itex_3, upos));
+}
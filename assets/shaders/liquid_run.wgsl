@@ -0,0 +1,157 @@
+#import compute::noise
+#import compute::common::{Params, DataGrid, DataStrip, GRID_MATERIAL, MATERIAL_CAVE_AIR, MATERIAL_WATER, MATERIAL_LAVA, LIQUID_NONE, LIQUID_LAVA, PI, SEED_LIQUIDS, planet_pos, planet_texel}
+#import compute::pass::{pass_info, converged, count_change}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var itex_1: texture_storage_2d<rgba32float, read>;
+@group(0) @binding(2) var otex_1: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(3) var itex_2: texture_storage_2d<rgba32float, read>;
+@group(0) @binding(4) var otex_2: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(5) var itex_3: texture_storage_2d<rgba32float, read>;
+@group(0) @binding(6) var otex_3: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(7) var<storage, read_write> grid_a: DataGrid;
+@group(0) @binding(8) var<storage, read_write> grid_b: DataGrid;
+@group(0) @binding(9) var<storage, read_write> strip_a: DataStrip;
+@group(0) @binding(10) var<storage, read_write> strip_b: DataStrip;
+@group(0) @binding(11) var grad_tex: texture_storage_2d<rgba32float, read>;
+
+/*
+One step of falling sand liquid under gravity towards the middle of the planet. Run it until
+the liquid has settled.
+
+Each liquid cell wants to move to the first free neighbour of: straight down, the two
+diagonals below it, then sideways unless its viscosity holds it this pass. Free cells pull
+from at most one neighbour that wants them, and a liquid cell only leaves if the cell it wants
+picks it, so both sides agree without atomics and no liquid is lost or made.
+
+Liquid can't leave the texture, so with tiling it stops at the edge of each tile's halo.
+*/
+
+fn ring(i: u32) -> vec2<i32> {
+    var offsets = array<vec2<i32>, 8>(
+        vec2(1, 0), vec2(1, 1), vec2(0, 1), vec2(-1, 1),
+        vec2(-1, 0), vec2(-1, -1), vec2(0, -1), vec2(1, -1),
+    );
+    return offsets[i % 8u];
+}
+
+fn in_bounds(p: vec2<i32>) -> bool {
+    let dim = i32(params.dimensions);
+    return p.x >= 0 && p.y >= 0 && p.x < dim && p.y < dim;
+}
+
+fn liquid_at(p: vec2<i32>) -> u32 {
+    return u32(textureLoad(itex_1, p).g);
+}
+
+// cave cells, full or not, this pass only moves liquid between them
+fn is_open(p: vec2<i32>) -> bool {
+    let m = u32(grid_a.ints[p.x][p.y][GRID_MATERIAL]);
+    return m == MATERIAL_CAVE_AIR || m == MATERIAL_WATER || m == MATERIAL_LAVA;
+}
+
+fn is_free(p: vec2<i32>) -> bool {
+    return in_bounds(p) && is_open(p) && liquid_at(p) == LIQUID_NONE;
+}
+
+fn hash(p: vec2<i32>, pass_index: u32) -> u32 {
+    let t = bitcast<vec2<u32>>(planet_texel(p, params.dimensions, params.tile_origin, params.tile_scale));
+    return noise::pcg3d(vec3<u32>(t, pass_index ^ noise::derive_seed(params.seed, SEED_LIQUIDS))).x;
+}
+
+// ring index pointing at the middle of the planet
+fn down_index(p: vec2<i32>) -> u32 {
+    let d = vec2f(0.5) - planet_pos(p, params.dimensions, params.tile_origin, params.tile_scale);
+    return u32(i32(round(atan2(d.y, d.x) / (PI / 4.0))) + 8) % 8u;
+}
+
+// ring index of the neighbour the liquid at p moves to, or -1 if it stays
+fn wanted(p: vec2<i32>, pass_index: u32) -> i32 {
+    let down = down_index(p);
+    let h = hash(p, pass_index);
+    // alternate which side goes first so the liquid doesn't drift one way
+    let side = select(1u, 7u, (h & 1u) == 1u);
+    let other = 8u - side;
+
+    let viscosity = select(params.water_viscosity, params.lava_viscosity, liquid_at(p) == LIQUID_LAVA);
+    let spreads = f32(h >> 8u) / 16777216.0 >= viscosity;
+
+    var candidates = array<u32, 5>(down, down + side, down + other, down + 2u * side, down + 2u * other);
+    let count = select(3u, 5u, spreads);
+    for (var i = 0u; i < count; i++) {
+        let k = candidates[i] % 8u;
+        if (is_free(p + ring(k))) {
+            return i32(k);
+        }
+    }
+    return -1;
+}
+
+// ring index of the neighbour the free cell p takes liquid from, or -1
+fn chosen(p: vec2<i32>, pass_index: u32) -> i32 {
+    let start = hash(p, pass_index) >> 3u;
+    for (var i = 0u; i < 8u; i++) {
+        let k = (start + i) % 8u;
+        let q = p + ring(k);
+        if (!in_bounds(q) || liquid_at(q) == LIQUID_NONE) {
+            continue;
+        }
+        if (wanted(q, pass_index) == i32((k + 4u) % 8u)) {
+            return i32(k);
+        }
+    }
+    return -1;
+}
+
+@compute @workgroup_size(16, 16)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let x = global_id.x;
+    let y = global_id.y;
+
+    if (x >= params.dimensions || y >= params.dimensions) {
+        return;
+    }
+
+    let upos = vec2<i32>(i32(x), i32(y));
+    let rock = textureLoad(itex_1, upos);
+    let pass_index = pass_info.iteration;
+
+    // settled, every texel carries through
+    if (converged()) {
+        textureStore(otex_1, upos, rock);
+        textureStore(otex_2, upos, textureLoad(itex_2, upos));
+        textureStore(otex_3, upos, textureLoad(itex_3, upos));
+        return;
+    }
+
+    let here = liquid_at(upos);
+    var next = here;
+    if (here != LIQUID_NONE) {
+        let w = wanted(upos, pass_index);
+        if (w >= 0) {
+            let dest = upos + ring(u32(w));
+            if (chosen(dest, pass_index) == i32((u32(w) + 4u) % 8u)) {
+                next = LIQUID_NONE;
+            }
+        }
+    } else if (is_open(upos)) {
+        let c = chosen(upos, pass_index);
+        if (c >= 0) {
+            next = liquid_at(upos + ring(u32(c)));
+        }
+    }
+
+    if (next != here) {
+        count_change();
+    }
+
+    // only ever between open materials, so the neighbours' is_open doesn't change under them
+    if (is_open(upos)) {
+        let material = select(MATERIAL_CAVE_AIR, MATERIAL_WATER + next - 1u, next != LIQUID_NONE);
+        grid_a.ints[x][y][GRID_MATERIAL] = i32(material);
+    }
+
+    textureStore(otex_1, upos, vec4f(rock.r, f32(next), rock.b, rock.a));
+    textureStore(otex_2, upos, textureLoad(itex_2, upos));
+    textureStore(otex_3, upos, textureLoad(itex_3, upos));
+}
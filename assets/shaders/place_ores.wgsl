@@ -0,0 +1,79 @@
+#import compute::noise
+#import compute::anoise::psrdnoise2
+#import compute::common::{Params, DataGrid, DataStrip, Ores, OreDefinition, GRID_PLANET_SDF, GRID_MATERIAL, MATERIAL_CRUST, MATERIAL_DEEP_ROCK, MATERIAL_FIRST_ORE, ORE_NOISE_SIMPLEX, ORE_NOISE_FBM, ORE_NOISE_VORONOI, ORE_NOISE_PSRD, SEED_ORES, planet_pos}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var itex_1: texture_storage_2d<rgba32float, read>;
+@group(0) @binding(2) var otex_1: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(3) var itex_2: texture_storage_2d<rgba32float, read>;
+@group(0) @binding(4) var otex_2: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(5) var itex_3: texture_storage_2d<rgba32float, read>;
+@group(0) @binding(6) var otex_3: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(7) var<storage, read_write> grid_a: DataGrid;
+@group(0) @binding(8) var<storage, read_write> grid_b: DataGrid;
+@group(0) @binding(9) var<storage, read_write> strip_a: DataStrip;
+@group(0) @binding(10) var<storage, read_write> strip_b: DataStrip;
+@group(0) @binding(11) var grad_tex: texture_storage_2d<rgba32float, read>;
+@group(0) @binding(14) var<storage, read_write> ores: Ores;
+
+/*
+Swap rock for ore where each ore definition's noise is above its threshold and the rock is in
+its depth range. Earlier definitions win where they overlap. Every ore texel is counted so the
+app can report how much of each there is.
+*/
+
+// roughly -1 to 1 for every kind of noise
+fn ore_noise(ore: OreDefinition, p: vec2f) -> f32 {
+    switch ore.noise {
+        case ORE_NOISE_FBM: {
+            return noise::fbm(p);
+        }
+        case ORE_NOISE_VORONOI: {
+            return noise::voroNoise2(p, 1.0, 0.0) * 2.0 - 1.0;
+        }
+        case ORE_NOISE_PSRD: {
+            // the hash repeats every 289 anyway, so this period adds no tiling of its own
+            return psrdnoise2(p, vec2f(289.0), 0.0).x;
+        }
+        default: {
+            return noise::noise2(p);
+        }
+    }
+}
+
+@compute @workgroup_size(16, 16)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let x = global_id.x;
+    let y = global_id.y;
+
+    if (x >= params.dimensions || y >= params.dimensions) {
+        return;
+    }
+
+    let upos = vec2<i32>(i32(x), i32(y));
+
+    let material = u32(grid_a.ints[x][y][GRID_MATERIAL]);
+    if (material == MATERIAL_CRUST || material == MATERIAL_DEEP_ROCK) {
+        let depth = -grid_a.floats[x][y][GRID_PLANET_SDF] * params.tile_scale;
+        let pos = planet_pos(upos, params.dimensions, params.tile_origin, params.tile_scale);
+
+        for (var i = 0u; i < ores.count; i++) {
+            let ore = ores.ores[i];
+            if (depth < ore.min_depth || depth > ore.max_depth) {
+                continue;
+            }
+
+            // move each ore somewhere else in the noise so they don't line up
+            let offset = noise::seed_offset(noise::derive_seed(params.seed, SEED_ORES), ore.seed) * 16.0;
+            if (ore_noise(ore, pos * ore.scale + offset) > ore.threshold) {
+                grid_a.ints[x][y][GRID_MATERIAL] = i32(MATERIAL_FIRST_ORE + i);
+                atomicAdd(&ores.counts[i], 1u);
+                break;
+            }
+        }
+    }
+
+    textureStore(otex_1, upos, textureLoad(itex_1, upos));
+    textureStore(otex_2, upos, textureLoad(itex_2, upos));
+    textureStore(otex_3, upos, textureLoad(itex_3, upos));
+}
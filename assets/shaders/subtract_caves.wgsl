@@ -1,6 +1,6 @@
 #import compute::noise
 #import compute::utils
-#import compute::common::{Params, BUFFER_LEN, DataGrid}
+#import compute::common::{Params, BUFFER_LEN, DataGrid, GRID_PLANET_MASK, GRID_EDIT_PASS}
 
 @group(0) @binding(0) var<uniform> params: Params;
 @group(0) @binding(1) var itex_1: texture_storage_2d<rgba32float, read>;
@@ -11,7 +11,7 @@
 @group(0) @binding(6) var otex_3: texture_storage_2d<rgba32float, write>;
 @group(0) @binding(7) var<storage, read_write> grid_a: DataGrid;
 @group(0) @binding(8) var<storage, read_write> grid_b: DataGrid;
-@group(0) @binding(9) var grad_tex: texture_storage_2d<rgba32float, read>;
+@group(0) @binding(11) var grad_tex: texture_storage_2d<rgba32float, read>;
 
 /*
 Determine the edge of the planet by comparing the warped radius against the distance field
@@ -39,6 +39,10 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
     
     var rock = textureLoad(itex_1, upos).r;
     let caves = textureLoad(itex_2, upos).r;
+
+    // keep the planet without caves around for the surface distance field
+    grid_a.floats[x][y][GRID_PLANET_MASK] = rock;
+
     rock = rock - caves;
     rock = clamp(rock, 0., 1.);
     
@@ -48,6 +52,9 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
 
     // textureStore(otex_1, upos, vec4f(1., 1., 0., 1.));
     textureStore(otex_1, upos, vec4f(rock, 0., 0., 1.));
-    // textureStore(otex_2,upos, textureLoad(itex_2,upos)); // todo test using the storage buffer to avoid constantly swapping textures
+    // apply_edits counts its passes from here
+    grid_a.ints[x][y][GRID_EDIT_PASS] = 0;
+    // carry the caves through for the jump flood
+    textureStore(otex_2,upos, textureLoad(itex_2,upos));
 
 }
\ No newline at end of file
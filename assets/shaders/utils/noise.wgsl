@@ -80,6 +80,7 @@ fn noise2(v: vec2f) -> f32 {
 //     return f / 0.9375;
 // }
 
+// fmb with fixed lacunarity
 fn fbm(p1: vec2f) -> f32 {
     var m2: mat2x2f = mat2x2f(vec2f(0.8, 0.6), vec2f(-0.6, 0.8));
     var f: f32 = 0.;
@@ -93,6 +94,7 @@ fn fbm(p1: vec2f) -> f32 {
     return f / 0.9844; // Updated normalization factor
 }
 
+// fmb with dynamic lacunarity
 fn fbml(p1: vec2f, l: f32) -> f32 {
     var m2: mat2x2f = mat2x2f(vec2f(0.8, 0.6), vec2f(-0.6, 0.8));
     var f: f32 = 0.;
@@ -146,4 +148,52 @@ fn pcg4d(p: vec4u) -> vec4u {
 fn rand11(f: f32) -> f32 { return f32(pcg(bitcast<u32>(f))) / f32(0xffffffff); }
 fn rand22(f: vec2f) -> vec2f { return vec2f(pcg2d(bitcast<vec2u>(f))) / f32(0xffffffff); }
 fn rand33(f: vec3f) -> vec3f { return vec3f(pcg3d(bitcast<vec3u>(f))) / f32(0xffffffff); }
-fn rand44(f: vec4f) -> vec4f { return vec4f(pcg4d(bitcast<vec4u>(f))) / f32(0xffffffff); }
\ No newline at end of file
+fn rand44(f: vec4f) -> vec4f { return vec4f(pcg4d(bitcast<vec4u>(f))) / f32(0xffffffff); }
+
+// Seeding, the same seed gives the same bits on every run
+
+// A stage's own seed from the master seed, so no two stages draw the same numbers
+fn derive_seed(seed: u32, stage: u32) -> u32 { return pcg(seed ^ pcg(stage)); }
+
+// Where to move a noise lookup to for a seed, 0..64 in steps of 1/256 so the offset position
+// keeps its precision through the octaves
+fn seed_offset(seed: u32, salt: u32) -> vec2f {
+    return vec2f(pcg2d(vec2u(seed, salt)) >> vec2u(18u)) / 256.0;
+}
+
+// 0..1 for an integer texel
+fn hash_texel(t: vec2u, seed: u32) -> f32 { return f32(pcg(pcg(t.x ^ seed) ^ t.y)) / f32(0xffffffff); }
+
+
+
+
+
+//  <https://www.shadertoy.com/view/Xd23Dh>
+//  by Inigo Quilez
+//
+fn hash23(p: vec2f) -> vec3f {
+    let q = vec3f(dot(p, vec2f(127.1, 311.7)),
+        dot(p, vec2f(269.5, 183.3)),
+        dot(p, vec2f(419.2, 371.9)));
+    return fract(sin(q) * 43758.5453);
+}
+
+fn voroNoise2(x: vec2f, u: f32, v: f32) -> f32 {
+    let p = floor(x);
+    let f = fract(x);
+    let k = 1. + 63. * pow(1. - v, 4.);
+    var va: f32 = 0.;
+    var wt: f32 = 0.;
+    for(var j: i32 = -2; j <= 2; j = j + 1) {
+        for(var i: i32 = -2; i <= 2; i = i + 1) {
+            let g = vec2f(f32(i), f32(j));
+            let o = hash23(p + g) * vec3f(u, u, 1.);
+            let r = g - f + o.xy;
+            let d = dot(r, r);
+            let ww = pow(1. - smoothstep(0., 1.414, sqrt(d)), k);
+            va = va + o.z * ww;
+            wt = wt + ww;
+        }
+    }
+    return va / wt;
+}
\ No newline at end of file
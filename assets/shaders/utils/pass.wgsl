@@ -0,0 +1,54 @@
+#define_import_path compute::pass
+
+#import compute::common::MAX_CONVERGENCE_ITERATIONS
+
+// Which dispatch of the chain is running, see PassInfo in src/parameters.rs. A push constant
+// where the device has them, otherwise one entry of a dynamic offset uniform in group 1.
+struct PassInfo {
+    // the stage's index in the chain
+    stage: u32,
+    // 0 on the stage's first dispatch
+    iteration: u32,
+    // the iterations the stage runs this time, fewer than configured when the view stops the
+    // chain in or before it
+    iterations: u32,
+    // stop once an iteration changes fewer texels than this, 0 runs every iteration
+    converge_below: u32,
+}
+
+#ifdef PASS_PUSH_CONSTANT
+var<push_constant> pass_info: PassInfo;
+#else
+@group(1) @binding(0) var<uniform> pass_info: PassInfo;
+#endif
+
+// The texels each iteration of each stage changed, zeroed before the chain runs and read back
+// for the gui, see src/convergence.rs
+struct Convergence {
+    changes: array<atomic<u32>>,
+}
+
+@group(0) @binding(15) var<storage, read_write> convergence: Convergence;
+
+fn changes_index(iteration: u32) -> u32 {
+    return pass_info.stage * MAX_CONVERGENCE_ITERATIONS + iteration;
+}
+
+// Whether the previous iteration changed fewer texels than the stage's threshold. A stage that
+// has converged carries its input through, and counts no changes so it stays converged. Its
+// remaining iterations are still dispatched, the counts are only read back after the chain ran,
+// so this saves the work of an iteration but not the dispatch and the copy.
+fn converged() -> bool {
+    if (pass_info.converge_below == 0u || pass_info.iteration == 0u || pass_info.iteration > MAX_CONVERGENCE_ITERATIONS) {
+        return false;
+    }
+    let previous = atomicLoad(&convergence.changes[changes_index(pass_info.iteration - 1u)]);
+    return previous < pass_info.converge_below;
+}
+
+// Count a texel this iteration changed
+fn count_change() {
+    if (pass_info.iteration < MAX_CONVERGENCE_ITERATIONS) {
+        atomicAdd(&convergence.changes[changes_index(pass_info.iteration)], 1u);
+    }
+}
@@ -11,8 +11,9 @@ use bevy::{
     utils::HashMap,
 };
 use bytemuck::bytes_of;
+use std::num::NonZeroU64;
 
-use crate::{parameters::ParamsUniform, pipeline::ComputePipelines, BindGroupSelection, GpuBufferBindGroups, ImageBufferContainer, ShaderConfigHolder};
+use crate::{materials::{MaterialColours, MaterialTable}, parameters::{ParamsUniform, PassInfo, ViewParams}, pipeline::{ComputePipelines, PassConstants}, BindGroupSelection, Comparison, GpuBufferBindGroups, ImageBufferContainer, ParamsChanged, PassUniforms, ShaderConfigHolder};
 
 pub fn prepare_bind_groups(
     mut commands: Commands,
@@ -22,22 +23,56 @@ pub fn prepare_bind_groups(
     images: Res<RenderAssets<GpuImage>>,
     buffers: Res<RenderAssets<GpuShaderStorageBuffer>>,
     params_res: Res<ParamsUniform>,
+    view_res: Res<ViewParams>,
+    comparison: Res<Comparison>,
+    materials: Res<MaterialTable>,
     render_queue: Res<RenderQueue>,
 ) {
-    let uniform_buffer = render_device.create_buffer(&BufferDescriptor {
-        label: Some("uniform"),
-        size: std::mem::size_of::<ParamsUniform>() as u64,
+    let create_uniform_buffer = |label: &'static str| {
+        render_device.create_buffer(&BufferDescriptor {
+            label: Some(label),
+            size: std::mem::size_of::<ParamsUniform>() as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    };
+
+    let uniform_buffer = create_uniform_buffer("uniform");
+    render_queue.write_buffer(&uniform_buffer, 0, bytes_of(&*params_res));
+
+    // the comparison chain runs the snapshot parameters through the same buffers
+    let compare_uniform_buffer = create_uniform_buffer("compare uniform");
+    let compare_params = comparison.snapshot.unwrap_or(*params_res);
+    render_queue.write_buffer(&compare_uniform_buffer, 0, bytes_of(&compare_params));
+
+    let view_uniform_buffer = render_device.create_buffer(&BufferDescriptor {
+        label: Some("view uniform"),
+        size: std::mem::size_of::<ViewParams>() as u64,
         usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
         mapped_at_creation: false,
     });
 
-    render_queue.write_buffer(&uniform_buffer, 0, bytes_of(&*params_res));
+    render_queue.write_buffer(&view_uniform_buffer, 0, bytes_of(&*view_res));
+
+    let material_uniform_buffer = render_device.create_buffer(&BufferDescriptor {
+        label: Some("material uniform"),
+        size: std::mem::size_of::<MaterialColours>() as u64,
+        usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    render_queue.write_buffer(&material_uniform_buffer, 0, bytes_of(&materials.colours()));
     
     let grid_buffer_a = buffers.get(&buffer_container.grid_buffer_a).unwrap();
     let grid_buffer_b = buffers.get(&buffer_container.grid_buffer_b).unwrap();
     
     let strip_buffer_a = buffers.get(&buffer_container.strip_buffer_a).unwrap();
     let strip_buffer_b = buffers.get(&buffer_container.strip_buffer_b).unwrap();
+    let rock_mask = buffers.get(&buffer_container.rock_mask).unwrap();
+    let edit_buffer = buffers.get(&buffer_container.edit_buffer).unwrap();
+    let ore_buffer = buffers.get(&buffer_container.ore_buffer).unwrap();
+    let convergence = buffers.get(&buffer_container.convergence).unwrap();
+    let chain_size = NonZeroU64::new(convergence.buffer.size() / 2);
 
     let image_a1 = images.get(&buffer_container.tex_buffer_a1).unwrap();
     let image_b1 = images.get(&buffer_container.tex_buffer_b1).unwrap();
@@ -46,96 +81,163 @@ pub fn prepare_bind_groups(
     let image_a3 = images.get(&buffer_container.tex_buffer_a3).unwrap();
     let image_b3 = images.get(&buffer_container.tex_buffer_b3).unwrap();
     let result_image = images.get(&buffer_container.result).unwrap();
+    let compare_image = images.get(&buffer_container.compare).unwrap();
     let gradient_image = images.get(&buffer_container.grad_texture).unwrap();
 
-    let bind_groups = vec![
-        // A -> B
-        render_device.create_bind_group(
-            None,
-            &pipeline.compute_layout,
-            &BindGroupEntries::sequential((
-                uniform_buffer.as_entire_buffer_binding(),
-                image_a1.texture_view.into_binding(),
-                image_b1.texture_view.into_binding(),
-                image_a2.texture_view.into_binding(),
-                image_b2.texture_view.into_binding(),
-                image_a3.texture_view.into_binding(),
-                image_b3.texture_view.into_binding(),
-                grid_buffer_a.buffer.as_entire_buffer_binding(),
-                grid_buffer_b.buffer.as_entire_buffer_binding(),
-                strip_buffer_a.buffer.as_entire_buffer_binding(),
-                strip_buffer_b.buffer.as_entire_buffer_binding(),
-                gradient_image.texture_view.into_binding(),
-            )),
-        ),
-        // B -> A
+    let create_stage_bind_groups = |uniform: &Buffer, convergence_offset: u64| {
+        let convergence = BufferBinding {
+            buffer: &convergence.buffer,
+            offset: convergence_offset,
+            size: chain_size,
+        };
+        vec![
+            // A -> B
+            render_device.create_bind_group(
+                None,
+                &pipeline.compute_layout,
+                &BindGroupEntries::sequential((
+                    uniform.as_entire_buffer_binding(),
+                    image_a1.texture_view.into_binding(),
+                    image_b1.texture_view.into_binding(),
+                    image_a2.texture_view.into_binding(),
+                    image_b2.texture_view.into_binding(),
+                    image_a3.texture_view.into_binding(),
+                    image_b3.texture_view.into_binding(),
+                    grid_buffer_a.buffer.as_entire_buffer_binding(),
+                    grid_buffer_b.buffer.as_entire_buffer_binding(),
+                    strip_buffer_a.buffer.as_entire_buffer_binding(),
+                    strip_buffer_b.buffer.as_entire_buffer_binding(),
+                    gradient_image.texture_view.into_binding(),
+                    rock_mask.buffer.as_entire_buffer_binding(),
+                    edit_buffer.buffer.as_entire_buffer_binding(),
+                    ore_buffer.buffer.as_entire_buffer_binding(),
+                    convergence.clone(),
+                )),
+            ),
+            // B -> A
+            render_device.create_bind_group(
+                None,
+                &pipeline.compute_layout,
+                &BindGroupEntries::sequential((
+                    uniform.as_entire_buffer_binding(),
+                    image_b1.texture_view.into_binding(),
+                    image_a1.texture_view.into_binding(),
+                    image_b2.texture_view.into_binding(),
+                    image_a2.texture_view.into_binding(),
+                    image_b3.texture_view.into_binding(),
+                    image_a3.texture_view.into_binding(),
+                    grid_buffer_a.buffer.as_entire_buffer_binding(),
+                    grid_buffer_b.buffer.as_entire_buffer_binding(),
+                    strip_buffer_a.buffer.as_entire_buffer_binding(),
+                    strip_buffer_b.buffer.as_entire_buffer_binding(),
+                    gradient_image.texture_view.into_binding(),
+                    rock_mask.buffer.as_entire_buffer_binding(),
+                    edit_buffer.buffer.as_entire_buffer_binding(),
+                    ore_buffer.buffer.as_entire_buffer_binding(),
+                    convergence,
+                )),
+            ),
+        ]
+    };
+
+    let create_extract_bind_group = |uniform: &Buffer, inputs: [&GpuImage; 3], output: &GpuImage| {
         render_device.create_bind_group(
             None,
-            &pipeline.compute_layout,
+            &pipeline.extract_layout,
             &BindGroupEntries::sequential((
-                uniform_buffer.as_entire_buffer_binding(),
-                image_b1.texture_view.into_binding(),
-                image_a1.texture_view.into_binding(),
-                image_b2.texture_view.into_binding(),
-                image_a2.texture_view.into_binding(),
-                image_b3.texture_view.into_binding(),
-                image_a3.texture_view.into_binding(),
+                uniform.as_entire_buffer_binding(),
+                inputs[0].texture_view.into_binding(),
+                inputs[1].texture_view.into_binding(),
+                inputs[2].texture_view.into_binding(),
+                output.texture_view.into_binding(),
                 grid_buffer_a.buffer.as_entire_buffer_binding(),
                 grid_buffer_b.buffer.as_entire_buffer_binding(),
                 strip_buffer_a.buffer.as_entire_buffer_binding(),
                 strip_buffer_b.buffer.as_entire_buffer_binding(),
                 gradient_image.texture_view.into_binding(),
+                view_uniform_buffer.as_entire_buffer_binding(),
+                material_uniform_buffer.as_entire_buffer_binding(),
             )),
-        ),
-    ];
-
-    let extract_a = render_device.create_bind_group(
-        None,
-        &pipeline.extract_layout,
-        &BindGroupEntries::sequential((
-            uniform_buffer.as_entire_buffer_binding(),
-            image_a1.texture_view.into_binding(),
-            image_a2.texture_view.into_binding(),
-            image_a3.texture_view.into_binding(),
-            result_image.texture_view.into_binding(),
-            grid_buffer_a.buffer.as_entire_buffer_binding(),
-            grid_buffer_b.buffer.as_entire_buffer_binding(),
-            strip_buffer_a.buffer.as_entire_buffer_binding(),
-            strip_buffer_b.buffer.as_entire_buffer_binding(),
-            gradient_image.texture_view.into_binding(),
-        )),
-    );
-    let extract_b = render_device.create_bind_group(
-        None,
-        &pipeline.extract_layout,
-        &BindGroupEntries::sequential((
-            uniform_buffer.as_entire_buffer_binding(),
-            image_b1.texture_view.into_binding(),
-            image_b2.texture_view.into_binding(),
-            image_b3.texture_view.into_binding(),
-            result_image.texture_view.into_binding(),
-            grid_buffer_a.buffer.as_entire_buffer_binding(),
-            grid_buffer_b.buffer.as_entire_buffer_binding(),
-            strip_buffer_a.buffer.as_entire_buffer_binding(),
-            strip_buffer_b.buffer.as_entire_buffer_binding(),
-            gradient_image.texture_view.into_binding(),
-        )),
-    );
+        )
+    };
+
+    let inputs_a = [image_a1, image_a2, image_a3];
+    let inputs_b = [image_b1, image_b2, image_b3];
 
     commands.insert_resource(GpuBufferBindGroups {
-        bind_groups,
-        final_pass_a: extract_a,
-        final_pass_b: extract_b,
+        bind_groups: create_stage_bind_groups(&uniform_buffer, 0),
+        final_pass_a: create_extract_bind_group(&uniform_buffer, inputs_a, result_image),
+        final_pass_b: create_extract_bind_group(&uniform_buffer, inputs_b, result_image),
+        compare_bind_groups: create_stage_bind_groups(
+            &compare_uniform_buffer,
+            chain_size.map_or(0, |s| s.get()),
+        ),
+        compare_final_pass_a: create_extract_bind_group(&compare_uniform_buffer, inputs_a, compare_image),
+        compare_final_pass_b: create_extract_bind_group(&compare_uniform_buffer, inputs_b, compare_image),
         uniform_buffer,
+        compare_uniform_buffer,
+        view_uniform_buffer,
+        material_uniform_buffer,
         // grad_buffer:gradient_image
         // iteration: 0,
     });
 }
 
+// One PassInfo per iteration of every stage, rewritten when the chain is about to run since the
+// iteration counts can change
+pub fn prepare_pass_uniforms(
+    mut uniforms: ResMut<PassUniforms>,
+    pipelines: Res<ComputePipelines>,
+    shader_configurator: Res<ShaderConfigHolder>,
+    view: Res<ViewParams>,
+    changed: Res<ParamsChanged>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    // the stop point decides how many iterations each stage has entries for
+    if pipelines.pass_constants != PassConstants::DynamicUniform
+        || (!changed.0 && !view.is_changed() && uniforms.bind_group.is_some())
+    {
+        return;
+    }
+
+    let uniforms = &mut *uniforms;
+    uniforms.buffer.clear();
+    uniforms.offsets = shader_configurator
+        .shader_configs
+        .iter()
+        .enumerate()
+        .map(|(stage, config)| {
+            let iterations = view.stage_iterations(stage, config.iterations);
+            (0..iterations)
+                .map(|iteration| {
+                    uniforms.buffer.push(&PassInfo {
+                        stage: stage as u32,
+                        iteration,
+                        iterations,
+                        converge_below: config.converge_below.unwrap_or(0),
+                    })
+                })
+                .collect()
+        })
+        .collect();
+    uniforms.buffer.write_buffer(&render_device, &render_queue);
+
+    // the buffer is reallocated when it grows
+    uniforms.bind_group = uniforms.buffer.binding().map(|binding| {
+        render_device.create_bind_group(
+            "pass uniforms",
+            &pipelines.pass_layout,
+            &BindGroupEntries::single(binding),
+        )
+    });
+}
+
 pub fn prepare_bind_group_selection(
     mut commands: Commands,
     pipelines: Res<ComputePipelines>,
     shader_configurator: Res<ShaderConfigHolder>,
+    view: Res<ViewParams>,
 ) {
     let mut selectors = HashMap::new();
     let mut total_iterations = 0;
@@ -146,7 +248,10 @@ pub fn prepare_bind_group_selection(
     for _ in &pipelines.pipeline_configs {
         let mut node_selections = Vec::new();
 
-        let i = shader_configurator.shader_configs[node as usize].iterations;
+        let i = view.stage_iterations(
+            node as usize,
+            shader_configurator.shader_configs[node as usize].iterations,
+        );
         for _ in 0..i {
             node_selections.push(total_iterations % 2);
             total_iterations += 1;
@@ -0,0 +1,326 @@
+use bevy::{prelude::*, render::render_resource::ShaderDefVal};
+
+use crate::{
+    data_structures::ShaderConfig, parameters::ParamsUniform, ParamsChanged, ShaderConfigHolder,
+};
+
+// Which texels count towards a texel in the rules that look at the 3x3 around it
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Neighbourhood {
+    // all eight
+    Moore,
+    // the four sharing an edge
+    VonNeumann,
+}
+
+impl Neighbourhood {
+    pub const ALL: [Neighbourhood; 2] = [Neighbourhood::Moore, Neighbourhood::VonNeumann];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Neighbourhood::Moore => "moore",
+            Neighbourhood::VonNeumann => "von neumann",
+        }
+    }
+
+    // whether the texel at this offset is a neighbour, in_neighbourhood in ca_run.wgsl
+    pub fn contains(&self, dx: i32, dy: i32) -> bool {
+        match self {
+            Neighbourhood::Moore => dx.abs().max(dy.abs()) == 1,
+            Neighbourhood::VonNeumann => dx.abs() + dy.abs() == 1,
+        }
+    }
+
+    pub fn size(&self) -> u32 {
+        match self {
+            Neighbourhood::Moore => 8,
+            Neighbourhood::VonNeumann => 4,
+        }
+    }
+}
+
+// The rule ca_run iterates. Live texels are 1 in texture 2 and the edge of the texture counts as
+// live, as it always has for the weighted rule
+#[derive(Resource, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum CaRule {
+    // the distance weighted share of live texels within ca_search_radius against ca_thresh,
+    // suppressed towards the edge of the planet
+    #[default]
+    WeightedRadius,
+    // life-like, a dead texel comes alive with a birth count of live neighbours and a live one
+    // stays alive with a survive count, bit n of each mask is n neighbours
+    Classic {
+        birth: u32,
+        survive: u32,
+        neighbourhood: Neighbourhood,
+    },
+    // each texel takes whatever most of itself and its neighbours are
+    Smoothing {
+        neighbourhood: Neighbourhood,
+    },
+}
+
+impl CaRule {
+    // B678/S345678, the usual cave rule
+    pub const CAVE: CaRule = CaRule::Classic {
+        birth: 0b1_1100_0000,
+        survive: 0b1_1111_1000,
+        neighbourhood: Neighbourhood::Moore,
+    };
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            CaRule::WeightedRadius => "weighted radius",
+            CaRule::Classic { .. } => "classic",
+            CaRule::Smoothing { .. } => "smoothing",
+        }
+    }
+
+    pub fn neighbourhood(&self) -> Option<Neighbourhood> {
+        match self {
+            CaRule::WeightedRadius => None,
+            CaRule::Classic { neighbourhood, .. } | CaRule::Smoothing { neighbourhood } => {
+                Some(*neighbourhood)
+            }
+        }
+    }
+
+    // the defs ca_run.wgsl picks the rule with
+    pub fn shader_defs(&self) -> Vec<ShaderDefVal> {
+        let mut defs = match self {
+            CaRule::WeightedRadius => Vec::new(),
+            CaRule::Classic { .. } => vec!["CA_RULE_CLASSIC".into()],
+            CaRule::Smoothing { .. } => vec!["CA_RULE_SMOOTH".into()],
+        };
+        if self.neighbourhood() == Some(Neighbourhood::VonNeumann) {
+            defs.push("CA_VON_NEUMANN".into());
+        }
+        defs
+    }
+
+    // Set the stage's rule defs, leaving any others alone, and the masks in the uniform
+    pub fn apply(&self, config: &mut ShaderConfig, params: &mut ParamsUniform) {
+        config
+            .shader_defs
+            .retain(|def| !def_name(def).starts_with("CA_"));
+        config.shader_defs.extend(self.shader_defs());
+
+        (params.ca_birth, params.ca_survive) = match self {
+            CaRule::Classic { birth, survive, .. } => (*birth, *survive),
+            _ => (0, 0),
+        };
+    }
+}
+
+fn def_name(def: &ShaderDefVal) -> &str {
+    match def {
+        ShaderDefVal::Bool(name, _) | ShaderDefVal::Int(name, _) | ShaderDefVal::UInt(name, _) => {
+            name
+        }
+    }
+}
+
+// Birth and survive masks from a rulestring like B678/S345678
+pub fn parse_rulestring(text: &str) -> Result<(u32, u32), String> {
+    let text = text.trim().to_ascii_uppercase();
+    let (birth, survive) = text
+        .split_once('/')
+        .ok_or_else(|| format!("'{}' isn't B.../S...", text))?;
+
+    let mask = |part: &str, prefix: char| {
+        let digits = part
+            .strip_prefix(prefix)
+            .ok_or_else(|| format!("'{}' should start with {}", part, prefix))?;
+        digits
+            .chars()
+            .try_fold(0u32, |mask, c| match c.to_digit(10) {
+                Some(n) if n <= 8 => Ok(mask | 1 << n),
+                _ => Err(format!("'{}' isn't a neighbour count", c)),
+            })
+    };
+    Ok((mask(birth, 'B')?, mask(survive, 'S')?))
+}
+
+pub fn rulestring(birth: u32, survive: u32) -> String {
+    let counts = |mask: u32| {
+        (0..=8)
+            .filter(|n| mask & 1 << n != 0)
+            .map(|n| n.to_string())
+            .collect::<String>()
+    };
+    format!("B{}/S{}", counts(birth), counts(survive))
+}
+
+pub struct CaRulePlugin;
+
+impl Plugin for CaRulePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CaRule>();
+        // after the gui, so a snapshot or preset restored this frame is fixed up before it runs
+        app.add_systems(PostUpdate, apply_ca_rule);
+    }
+}
+
+// The rule goes to the first ca_run stage, one added to the chain again keeps its own defs.
+// CaRule is the only record of the rule, the masks are put back whenever the params are
+// replaced as a whole, by restoring a snapshot or loading a preset
+fn apply_ca_rule(
+    rule: Res<CaRule>,
+    mut configs: ResMut<ShaderConfigHolder>,
+    mut params: ResMut<ParamsUniform>,
+    mut changed: ResMut<ParamsChanged>,
+) {
+    if !rule.is_changed() && !params.is_changed() {
+        return;
+    }
+    let Some(config) = configs.stage("ca_run") else {
+        return;
+    };
+
+    let mut ruled = config.clone();
+    let mut ruled_params = *params;
+    rule.apply(&mut ruled, &mut ruled_params);
+
+    if ruled.shader_defs != config.shader_defs {
+        if let Some(config) = configs.stage_mut("ca_run") {
+            *config = ruled;
+        }
+        changed.0 = true;
+    }
+    if params.set_if_neq(ruled_params) {
+        changed.0 = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        compute_node::ComputeNodeMode,
+        compute_plugin::default_shader_configs,
+        constants::BUFFER_LEN,
+        cpu_reference::{generate_rock, generate_rock_with},
+    };
+
+    #[test]
+    fn rulestrings_parse_and_print() {
+        assert_eq!(parse_rulestring("b678/s345678"), Ok((0b1_1100_0000, 0b1_1111_1000)));
+        assert_eq!(rulestring(0b1_1100_0000, 0b1_1111_1000), "B678/S345678");
+        assert_eq!(parse_rulestring("B3/S"), Ok((0b1000, 0)));
+        assert!(parse_rulestring("B9/S1").is_err());
+        assert!(parse_rulestring("S23/B3").is_err());
+
+        for text in ["B3/S23", "B0/S012345678", "B/S", "B36/S23"] {
+            let (birth, survive) = parse_rulestring(text).unwrap();
+            assert_eq!(rulestring(birth, survive), text);
+        }
+    }
+
+    #[test]
+    fn ca_rules_change_the_caves() {
+        let params = ParamsUniform {
+            dimensions: 64,
+            ca_search_radius: 0.5,
+            ..Default::default()
+        };
+        let weighted = generate_rock(params).unwrap().channel(0);
+
+        let rules = [
+            CaRule::CAVE,
+            CaRule::Classic {
+                birth: 0b1_1000,
+                survive: 0b1_1100,
+                neighbourhood: Neighbourhood::VonNeumann,
+            },
+            CaRule::Smoothing {
+                neighbourhood: Neighbourhood::Moore,
+            },
+            CaRule::Smoothing {
+                neighbourhood: Neighbourhood::VonNeumann,
+            },
+        ];
+        // von neumann rules can fill every cave at this size, so only the weighted rule is
+        // guaranteed to differ from the rest
+        for rule in rules {
+            let mut configs = default_shader_configs();
+            let mut rule_params = params;
+            let config = configs.iter_mut().find(|c| c.name() == "ca_run").unwrap();
+            rule.apply(config, &mut rule_params);
+
+            let first = generate_rock_with(rule_params, &configs).unwrap().channel(0);
+            let again = generate_rock_with(rule_params, &configs).unwrap().channel(0);
+            assert_eq!(first, again, "{:?}", rule);
+            assert_ne!(first, weighted, "{:?}", rule);
+        }
+    }
+
+    #[test]
+    fn restoring_a_snapshot_keeps_the_rule() {
+        let mut app = App::new();
+        app.insert_resource(CaRule::WeightedRadius);
+        app.insert_resource(ParamsUniform::default());
+        app.insert_resource(ShaderConfigHolder {
+            shader_configs: default_shader_configs(),
+        });
+        app.insert_resource(ParamsChanged::default());
+        app.add_systems(PostUpdate, apply_ca_rule);
+        app.update();
+
+        // saved under the weighted rule, so its masks are empty
+        let snapshot = *app.world().resource::<ParamsUniform>();
+        assert_eq!((snapshot.ca_birth, snapshot.ca_survive), (0, 0));
+
+        *app.world_mut().resource_mut::<CaRule>() = CaRule::CAVE;
+        app.update();
+        *app.world_mut().resource_mut::<ParamsUniform>() = snapshot;
+        app.world_mut().resource_mut::<ParamsChanged>().0 = false;
+        app.update();
+
+        let params = app.world().resource::<ParamsUniform>();
+        assert_eq!((params.ca_birth, params.ca_survive), (0b1_1100_0000, 0b1_1111_1000));
+        let configs = app.world().resource::<ShaderConfigHolder>();
+        assert!(configs.stage("ca_run").unwrap().has_def("CA_RULE_CLASSIC"));
+        assert!(app.world().resource::<ParamsChanged>().0);
+
+        // and params that already agree are left alone
+        app.world_mut().resource_mut::<ParamsChanged>().0 = false;
+        app.world_mut().resource_mut::<ParamsUniform>().set_changed();
+        app.update();
+        assert!(!app.world().resource::<ParamsChanged>().0);
+    }
+
+    #[test]
+    fn applying_a_rule_replaces_only_its_own_defs() {
+        let mut config = ShaderConfig {
+            shader_path: "shaders/ca_run.wgsl",
+            shader_mode: ComputeNodeMode::Compute2D(BUFFER_LEN),
+            iterations: 16,
+            shader_defs: vec!["OTHER".into(), "CA_RULE_SMOOTH".into(), "CA_VON_NEUMANN".into()],
+            converge_below: Some(0),
+        };
+        let mut params = ParamsUniform::default();
+
+        CaRule::CAVE.apply(&mut config, &mut params);
+        assert!(config.has_def("OTHER"));
+        assert!(config.has_def("CA_RULE_CLASSIC"));
+        assert!(!config.has_def("CA_RULE_SMOOTH"));
+        assert!(!config.has_def("CA_VON_NEUMANN"));
+        assert_eq!((params.ca_birth, params.ca_survive), (0b1_1100_0000, 0b1_1111_1000));
+
+        let smoothing = CaRule::Smoothing {
+            neighbourhood: Neighbourhood::VonNeumann,
+        };
+        smoothing.apply(&mut config, &mut params);
+        assert!(config.has_def("OTHER"));
+        assert!(!config.has_def("CA_RULE_CLASSIC"));
+        assert!(config.has_def("CA_RULE_SMOOTH"));
+        assert!(config.has_def("CA_VON_NEUMANN"));
+        assert_eq!((params.ca_birth, params.ca_survive), (0, 0));
+
+        CaRule::CAVE.apply(&mut config, &mut params);
+        CaRule::WeightedRadius.apply(&mut config, &mut params);
+        assert!(config.has_def("OTHER"));
+        assert_eq!(config.shader_defs.len(), 1);
+        assert_eq!((params.ca_birth, params.ca_survive), (0, 0));
+    }
+}
@@ -0,0 +1,646 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use bevy::{
+    prelude::*,
+    render::{
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        render_asset::{RenderAssetUsages, RenderAssets},
+        render_resource::{
+            CommandEncoder, Extent3d, PipelineCache, Texture, TextureDimension, TextureFormat,
+            TextureUsages,
+        },
+        texture::GpuImage,
+        Render, RenderApp, RenderSet,
+    },
+    utils::HashMap,
+};
+
+use crate::{
+    cam_controller::CameraController, compare::ResultSprite, constants::*, edits::TerrainChanged,
+    parameters::ParamsUniform, pipeline::ComputePipelines, ImageBufferContainer, ParamsChanged,
+    ShaderConfigHolder,
+};
+
+// Tiled mode splits the planet into chunks_per_side² chunks, each generated by the normal chain
+// at full resolution, one per frame, nearest the camera first. Every chunk's texture also covers
+// a halo around it so the stages that read their neighbours agree either side of a border, only
+// the inside is shown. The halo is sized from how far the warps and the CA reach.
+#[derive(Resource, Clone)]
+pub struct ChunkSettings {
+    pub enabled: bool,
+    pub chunks_per_side: u32,
+    // texels generated on each side of a chunk on top of what the stages reach, not shown
+    pub halo: u32,
+    // chunks this far from the one under the camera are generated
+    pub load_radius: u32,
+    // least recently seen chunks are dropped past this
+    pub max_cached: usize,
+}
+
+impl Default for ChunkSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            chunks_per_side: 4,
+            halo: 4,
+            load_radius: 1,
+            max_cached: 16,
+        }
+    }
+}
+
+// A chunk's halo in texels, and the most a warp iteration may move a texel to stay within it,
+// 0 when the halo covers the warps
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ChunkHalo {
+    pub texels: u32,
+    pub warp_limit: f32,
+}
+
+impl ChunkSettings {
+    // How far past its edges a chunk's texture has to go for nothing shown to be read from
+    // beyond them. Each warp iteration moves a texel up to its amounts, or up to a whole planet
+    // uv for domain_warp_2's first warp which takes its amount from noise, and each CA iteration
+    // reaches its search radius. The halo stops at a quarter of the texture and past that the
+    // warps are held to what's left of it. The jump flood and the liquids reach further still,
+    // their fields are only exact away from the edges
+    pub fn halo(&self, params: &ParamsUniform, configs: &ShaderConfigHolder) -> ChunkHalo {
+        let iterations = |name| configs.stage(name).map_or(0, |c| c.iterations) as f32;
+        let dim = params.dimensions as f32;
+        let max_halo = params.dimensions / 4;
+
+        // in planet uv, fbm and noise2 stay within ±1
+        let warps = [
+            (
+                iterations("domain_warp_1"),
+                params.domain_warp_1_amount_a.abs() + params.domain_warp_1_amount_b.abs(),
+            ),
+            (iterations("domain_warp_2"), 1.0 + params.domain_warp_2_amount_b.abs()),
+        ];
+        let warp: f32 = warps.iter().map(|(iterations, amount)| iterations * amount).sum();
+
+        // ca_run.wgsl scales the search radius with the texture, the other rules look at the 3x3
+        let ca = configs.stage("ca_run").map_or(0.0, |config| {
+            let radius = if config.has_def("CA_RULE_CLASSIC") || config.has_def("CA_RULE_SMOOTH") {
+                1.0
+            } else {
+                (params.ca_search_radius * (8.0 / (dim / 128.0))).ceil()
+            };
+            radius * config.iterations as f32
+        });
+
+        // a planet uv is (dim - 2 halo) * chunks_per_side texels of a chunk, and the halo has to
+        // cover the warp in those texels as well as the CA
+        let n = self.chunks_per_side as f32;
+        let texels = ((warp * dim * n + ca) / (1.0 + 2.0 * warp * n)).ceil() as u32 + self.halo;
+        if texels <= max_halo {
+            return ChunkHalo {
+                texels,
+                warp_limit: 0.0,
+            };
+        }
+
+        let warp_iterations: f32 = warps.iter().map(|(iterations, _)| iterations).sum();
+        let left = max_halo as f32 - self.halo as f32 - ca;
+        ChunkHalo {
+            texels: max_halo,
+            warp_limit: (left / warp_iterations.max(1.0)).floor().max(1.0),
+        }
+    }
+
+    // Planet uv of the first texel and the planet uv spanned by a chunk's texture, halo included
+    pub fn tile(&self, coord: IVec2, halo: u32, dimensions: u32) -> (Vec2, f32) {
+        let dim = dimensions as f32;
+        let halo = halo as f32;
+        let scale = dim / (dim - 2.0 * halo) / self.chunks_per_side as f32;
+        let origin = coord.as_vec2() / self.chunks_per_side as f32 - Vec2::splat(halo / dim * scale);
+        (origin, scale)
+    }
+
+    // The planet is chunks_per_side result sprites across, centred where the result sprite is
+    fn planet_size(&self) -> f32 {
+        self.chunks_per_side as f32 * SPRITE_SIZE
+    }
+
+    fn chunk_centre(&self, coord: IVec2, planet_centre: Vec2) -> Vec2 {
+        let uv = (coord.as_vec2() + 0.5) / self.chunks_per_side as f32 - 0.5;
+        planet_centre + Vec2::new(uv.x, -uv.y) * self.planet_size()
+    }
+
+    fn chunk_at(&self, world: Vec2, planet_centre: Vec2) -> IVec2 {
+        let p = (world - planet_centre) / self.planet_size();
+        let uv = Vec2::new(p.x, -p.y) + 0.5;
+        (uv * self.chunks_per_side as f32).floor().as_ivec2()
+    }
+
+    fn contains(&self, coord: IVec2) -> bool {
+        let n = self.chunks_per_side as i32;
+        coord.x >= 0 && coord.y >= 0 && coord.x < n && coord.y < n
+    }
+}
+
+struct Chunk {
+    image: Handle<Image>,
+    entity: Option<Entity>,
+    // generated with old parameters or edits, shown until it's regenerated
+    stale: bool,
+    last_used: u64,
+}
+
+#[derive(Resource, Default)]
+pub struct ChunkCache {
+    chunks: HashMap<IVec2, Chunk>,
+    frame: u64,
+    // chunks_per_side and halo the chunks were generated with
+    layout: (u32, u32),
+    params_key: u64,
+    next_request: u64,
+}
+
+impl ChunkCache {
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn ready(&self) -> usize {
+        self.chunks.values().filter(|c| c.entity.is_some() && !c.stale).count()
+    }
+
+    fn remove(&mut self, coord: IVec2, commands: &mut Commands, images: &mut Assets<Image>) {
+        if let Some(chunk) = self.chunks.remove(&coord) {
+            if let Some(entity) = chunk.entity {
+                commands.entity(entity).despawn();
+            }
+            images.remove(&chunk.image);
+        }
+    }
+
+    fn clear(&mut self, commands: &mut Commands, images: &mut Assets<Image>) {
+        let coords: Vec<IVec2> = self.chunks.keys().copied().collect();
+        for coord in coords {
+            self.remove(coord, commands, images);
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ChunkTarget {
+    pub id: u64,
+    pub coord: IVec2,
+    pub image: Handle<Image>,
+    // the halo it's generated with, in texels
+    pub halo: u32,
+}
+
+// The chunk the chain is generating, the final node copies the result into its image
+#[derive(Resource, ExtractResource, Clone, Default)]
+pub struct ChunkRequest {
+    pub target: Option<ChunkTarget>,
+}
+
+// Ids of the requests the render world has copied out, shared between the worlds
+#[derive(Resource, Clone, Default)]
+pub struct ChunkCompletions(Arc<Mutex<Vec<u64>>>);
+
+#[derive(Component)]
+pub struct ChunkSprite;
+
+pub struct ChunkPlugin;
+
+impl Plugin for ChunkPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ChunkSettings>();
+        app.init_resource::<ChunkCache>();
+        app.init_resource::<ChunkRequest>();
+        app.init_resource::<ChunkCompletions>();
+        app.add_plugins(ExtractResourcePlugin::<ChunkRequest>::default());
+        app.add_systems(
+            Update,
+            (apply_settings, invalidate_chunks, collect_chunks, request_chunks).chain(),
+        );
+    }
+
+    fn finish(&self, app: &mut App) {
+        let completions = app.world().resource::<ChunkCompletions>().clone();
+
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app.insert_resource(ChunkCopy {
+            target: None,
+            completions,
+            spoiled: AtomicBool::new(false),
+        });
+        render_app.add_systems(Render, prepare_chunk_copy.in_set(RenderSet::PrepareResources));
+    }
+}
+
+// Switch between the single texture and the chunks, dropping everything when the layout changes
+#[allow(clippy::too_many_arguments)]
+fn apply_settings(
+    mut commands: Commands,
+    settings: Res<ChunkSettings>,
+    mut cache: ResMut<ChunkCache>,
+    mut request: ResMut<ChunkRequest>,
+    mut params: ResMut<ParamsUniform>,
+    mut changed: ResMut<ParamsChanged>,
+    mut images: ResMut<Assets<Image>>,
+    mut result_sprites: Query<&mut Visibility, With<ResultSprite>>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+
+    let layout = (settings.chunks_per_side, settings.halo);
+    if !settings.enabled || cache.layout != layout {
+        cache.clear(&mut commands, &mut images);
+        cache.layout = layout;
+        if request.target.is_some() {
+            request.target = None;
+        }
+    }
+
+    for mut visibility in &mut result_sprites {
+        *visibility = if settings.enabled {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        };
+    }
+
+    // back to the whole planet in one texture
+    if !settings.enabled
+        && (params.tile_origin != Vec2::ZERO
+            || params.tile_scale != 1.0
+            || params.tile_warp_limit != 0.0)
+    {
+        params.tile_origin = Vec2::ZERO;
+        params.tile_scale = 1.0;
+        params.tile_warp_limit = 0.0;
+        changed.0 = true;
+    }
+}
+
+// Everything but the tile decides what a chunk looks like
+fn params_key(params: &ParamsUniform) -> u64 {
+    let mut untiled = *params;
+    untiled.tile_origin = Vec2::ZERO;
+    untiled.tile_scale = 1.0;
+    untiled.tile_warp_limit = 0.0;
+
+    let mut hasher = DefaultHasher::new();
+    bytemuck::bytes_of(&untiled).hash(&mut hasher);
+    hasher.finish()
+}
+
+// Mark chunks stale when the parameters change or an edit touches them
+fn invalidate_chunks(
+    settings: Res<ChunkSettings>,
+    params: Res<ParamsUniform>,
+    configs: Res<ShaderConfigHolder>,
+    mut cache: ResMut<ChunkCache>,
+    mut terrain_changed: EventReader<TerrainChanged>,
+) {
+    let key = params_key(&params);
+    if cache.params_key != key {
+        cache.params_key = key;
+        for chunk in cache.chunks.values_mut() {
+            chunk.stale = true;
+        }
+    }
+
+    // None is sent for parameter changes, which the key covers
+    for region in terrain_changed.read().filter_map(|e| e.region) {
+        // edits are in texels of the untiled planet, neighbours see them through their halo
+        let n = settings.chunks_per_side as f32;
+        let halo = settings.halo(&params, &configs).texels as f32 / BUFFER_LEN as f32 / n;
+        let min = ((region.min / BUFFER_LEN as f32 - halo) * n).floor().as_ivec2();
+        let max = ((region.max / BUFFER_LEN as f32 + halo) * n).floor().as_ivec2();
+
+        for (coord, chunk) in cache.chunks.iter_mut() {
+            if coord.cmpge(min).all() && coord.cmple(max).all() {
+                chunk.stale = true;
+            }
+        }
+    }
+}
+
+// Show the chunks the render world has finished
+fn collect_chunks(
+    mut commands: Commands,
+    settings: Res<ChunkSettings>,
+    completions: Res<ChunkCompletions>,
+    mut cache: ResMut<ChunkCache>,
+    mut request: ResMut<ChunkRequest>,
+    result_sprites: Query<&Transform, With<ResultSprite>>,
+    mut chunk_sprites: Query<&mut Sprite, With<ChunkSprite>>,
+) {
+    let completed = std::mem::take(&mut *completions.0.lock().unwrap());
+    let Some(target) = request.target.clone() else {
+        return;
+    };
+    if !completed.contains(&target.id) {
+        return;
+    }
+    request.target = None;
+
+    let Some(chunk) = cache.chunks.get_mut(&target.coord) else {
+        return;
+    };
+    chunk.stale = false;
+
+    // the halo can have changed since the chunk was last generated
+    let halo = target.halo as f32;
+    let dim = BUFFER_LEN as f32;
+    let rect = Rect::new(halo, halo, dim - halo, dim - halo);
+
+    if let Some(mut sprite) = chunk.entity.and_then(|e| chunk_sprites.get_mut(e).ok()) {
+        sprite.rect = Some(rect);
+    } else {
+        let planet_centre = result_sprites
+            .get_single()
+            .map(|t| t.translation.truncate())
+            .unwrap_or_default();
+
+        let entity = commands
+            .spawn((
+                Sprite {
+                    image: chunk.image.clone(),
+                    custom_size: Some(Vec2::splat(SPRITE_SIZE)),
+                    rect: Some(rect),
+                    ..default()
+                },
+                Transform::from_translation(
+                    settings.chunk_centre(target.coord, planet_centre).extend(0.0),
+                ),
+                ChunkSprite,
+            ))
+            .id();
+        chunk.entity = Some(entity);
+    }
+}
+
+// Point the chain at the nearest chunk that's missing or stale
+#[allow(clippy::too_many_arguments)]
+fn request_chunks(
+    mut commands: Commands,
+    settings: Res<ChunkSettings>,
+    mut cache: ResMut<ChunkCache>,
+    mut request: ResMut<ChunkRequest>,
+    mut params: ResMut<ParamsUniform>,
+    mut changed: ResMut<ParamsChanged>,
+    mut images: ResMut<Assets<Image>>,
+    configs: Res<ShaderConfigHolder>,
+    cameras: Query<&Transform, With<CameraController>>,
+    result_sprites: Query<&Transform, With<ResultSprite>>,
+) {
+    if !settings.enabled {
+        return;
+    }
+
+    // keep the chain running until the render world has copied the chunk out
+    if request.target.is_some() {
+        changed.0 = true;
+        return;
+    }
+
+    let Ok(camera) = cameras.get_single() else {
+        return;
+    };
+    let planet_centre = result_sprites
+        .get_single()
+        .map(|t| t.translation.truncate())
+        .unwrap_or_default();
+
+    cache.frame += 1;
+    let frame = cache.frame;
+
+    let centre = settings.chunk_at(camera.translation.truncate(), planet_centre);
+    let r = settings.load_radius as i32;
+    let mut wanted: Vec<IVec2> = (-r..=r)
+        .flat_map(|y| (-r..=r).map(move |x| centre + IVec2::new(x, y)))
+        .filter(|coord| settings.contains(*coord))
+        .collect();
+    wanted.sort_by_key(|coord| (*coord - centre).length_squared());
+
+    for coord in &wanted {
+        if let Some(chunk) = cache.chunks.get_mut(coord) {
+            chunk.last_used = frame;
+        }
+    }
+
+    let Some(coord) = wanted
+        .iter()
+        .copied()
+        .find(|coord| cache.chunks.get(coord).is_none_or(|c| c.stale))
+    else {
+        return;
+    };
+
+    if !cache.chunks.contains_key(&coord) {
+        while cache.chunks.len() >= settings.max_cached.max(1) {
+            let Some(lru) = cache
+                .chunks
+                .iter()
+                .filter(|(c, _)| !wanted.contains(c))
+                .min_by_key(|(_, chunk)| chunk.last_used)
+                .map(|(c, _)| *c)
+            else {
+                break;
+            };
+            cache.remove(lru, &mut commands, &mut images);
+        }
+
+        let image = images.add(create_chunk_image());
+        cache.chunks.insert(
+            coord,
+            Chunk {
+                image,
+                entity: None,
+                stale: true,
+                last_used: frame,
+            },
+        );
+    }
+
+    let halo = settings.halo(&params, &configs);
+    let (origin, scale) = settings.tile(coord, halo.texels, params.dimensions);
+    params.tile_origin = origin;
+    params.tile_scale = scale;
+    params.tile_warp_limit = halo.warp_limit;
+    changed.0 = true;
+
+    cache.next_request += 1;
+    request.target = Some(ChunkTarget {
+        id: cache.next_request,
+        coord,
+        image: cache.chunks[&coord].image.clone(),
+        halo: halo.texels,
+    });
+}
+
+fn create_chunk_image() -> Image {
+    let mut image = Image::new_fill(
+        Extent3d {
+            width: BUFFER_LEN as u32,
+            height: BUFFER_LEN as u32,
+            ..default()
+        },
+        TextureDimension::D2,
+        &[0; 16],
+        TextureFormat::Rgba32Float,
+        RenderAssetUsages::RENDER_WORLD,
+    );
+    image.texture_descriptor.usage |= TextureUsages::COPY_DST;
+    image
+}
+
+// Texture the final live node copies the result into this frame, and the request it's for
+#[derive(Resource)]
+pub struct ChunkCopy {
+    target: Option<(u64, Texture)>,
+    completions: ChunkCompletions,
+    // set when a stage couldn't dispatch, so the result isn't the chunk
+    spoiled: AtomicBool,
+}
+
+impl ChunkCopy {
+    pub fn spoil(&self) {
+        self.spoiled.store(true, Ordering::Relaxed);
+    }
+
+    // The chunk only counts as done once the copy is in the encoder
+    pub fn encode_copy(&self, encoder: &mut CommandEncoder, world: &World) {
+        let Some((id, target)) = &self.target else {
+            return;
+        };
+        if self.spoiled.load(Ordering::Relaxed) {
+            return;
+        }
+        let textures = world.resource::<ImageBufferContainer>();
+        let images = world.resource::<RenderAssets<GpuImage>>();
+        let Some(result) = images.get(&textures.result) else {
+            return;
+        };
+
+        encoder.copy_texture_to_texture(
+            result.texture.as_image_copy(),
+            target.as_image_copy(),
+            Extent3d {
+                width: BUFFER_LEN as u32,
+                height: BUFFER_LEN as u32,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.completions.0.lock().unwrap().push(*id);
+    }
+}
+
+fn prepare_chunk_copy(
+    mut copy: ResMut<ChunkCopy>,
+    request: Option<Res<ChunkRequest>>,
+    changed: Res<ParamsChanged>,
+    pipelines: Option<Res<ComputePipelines>>,
+    pipeline_cache: Res<PipelineCache>,
+    images: Res<RenderAssets<GpuImage>>,
+) {
+    copy.target = None;
+    copy.spoiled = AtomicBool::new(false);
+
+    let (Some(target), Some(pipelines)) = (request.as_ref().and_then(|r| r.target.as_ref()), pipelines) else {
+        return;
+    };
+    if !changed.0 {
+        return;
+    }
+
+    // a chain still waiting on pipelines would leave holes in the planet
+    let ready = pipelines
+        .pipeline_configs
+        .iter()
+        .chain(std::iter::once(&pipelines.final_pass))
+        .all(|id| pipeline_cache.get_compute_pipeline(*id).is_some());
+    let Some(image) = images.get(&target.image).filter(|_| ready) else {
+        return;
+    };
+
+    copy.target = Some((target.id, image.texture.clone()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compute_plugin::default_shader_configs, cpu_reference::generate_rock_with};
+
+    // texels along the border of two chunks side by side that differ, and how many were compared
+    fn edge_differences(params: ParamsUniform, configs: &ShaderConfigHolder, halo: ChunkHalo) -> (usize, usize) {
+        let settings = ChunkSettings {
+            chunks_per_side: 2,
+            ..default()
+        };
+        let rock = |coord| {
+            let (tile_origin, tile_scale) = settings.tile(coord, halo.texels, params.dimensions);
+            let mut tiled = params;
+            tiled.tile_origin = tile_origin;
+            tiled.tile_scale = tile_scale;
+            tiled.tile_warp_limit = halo.warp_limit;
+            generate_rock_with(tiled, &configs.shader_configs).unwrap().channel(0)
+        };
+        let (left, right) = (rock(IVec2::new(0, 0)), rock(IVec2::new(1, 0)));
+
+        // the left chunk's last shown column and first halo column are the right chunk's last
+        // halo column and first shown one
+        let (dim, halo) = (params.dimensions as usize, halo.texels as usize);
+        let mut differing = 0;
+        let mut compared = 0;
+        for y in halo..dim - halo {
+            for k in 0..2 {
+                let a = left[y * dim + dim - halo - 1 + k];
+                let b = right[y * dim + halo - 1 + k];
+                differing += (a != b) as usize;
+                compared += 1;
+            }
+        }
+        (differing, compared)
+    }
+
+    #[test]
+    fn adjacent_chunks_match_along_their_edge() {
+        let params = ParamsUniform {
+            dimensions: 256,
+            domain_warp_1_amount_a: 0.002,
+            domain_warp_1_scale_a: 3.0,
+            ..default()
+        };
+        let mut configs = ShaderConfigHolder {
+            shader_configs: default_shader_configs(),
+        };
+        // sixteen CA iterations reach past a quarter of a texture this small
+        configs.stage_mut("ca_run").unwrap().iterations = 2;
+
+        let halo = ChunkSettings {
+            chunks_per_side: 2,
+            ..default()
+        }
+        .halo(&params, &configs);
+        // domain_warp_2's first warp goes further than a halo this small covers
+        assert!(halo.warp_limit > 0.0, "{:?} doesn't hold the warps", halo);
+
+        // positions either side are the same planet uv summed differently, so a texel can land
+        // the other side of a rounding
+        let (differing, compared) = edge_differences(params, &configs, halo);
+        assert!(differing * 100 < compared, "{} of {} differ", differing, compared);
+
+        let no_halo = ChunkHalo {
+            texels: 1,
+            warp_limit: 0.0,
+        };
+        let (without_halo, _) = edge_differences(params, &configs, no_halo);
+        assert!(without_halo > differing * 4, "{} differ without the halo", without_halo);
+    }
+}
@@ -0,0 +1,565 @@
+use bevy::{
+    prelude::*,
+    tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task},
+    utils::HashMap,
+};
+
+use crate::{
+    compare::ResultSprite,
+    contours::{texel_to_sprite, Contour, ContourKind, Contours},
+    edits::TerrainChanged,
+};
+
+const OVERLAY_COLOUR: Color = Color::srgb(1.0, 0.3, 0.6);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ColliderMode {
+    // one closed polyline per contour, cheapest and fine for static terrain
+    Polyline,
+    // islands minus their caves, split into convex pieces
+    Convex,
+}
+
+#[derive(Resource)]
+pub struct ColliderSettings {
+    pub enabled: bool,
+    pub mode: ColliderMode,
+    // convex pieces aren't merged past this many vertices
+    pub max_convex_vertices: usize,
+    pub show_overlay: bool,
+}
+
+impl Default for ColliderSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            mode: ColliderMode::Polyline,
+            max_convex_vertices: 16,
+            show_overlay: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum ColliderShape {
+    Polyline {
+        vertices: Vec<Vec2>,
+        indices: Vec<[u32; 2]>,
+    },
+    // counter clockwise
+    Convex(Vec<Vec2>),
+}
+
+// Collision shapes for the current terrain, in world units relative to the centre of the
+// result sprite. Rebuilt whenever the contours change, generation is bumped each time.
+#[derive(Resource, Default)]
+pub struct TerrainColliders {
+    pub shapes: Vec<ColliderShape>,
+    pub generation: u32,
+    // area touched by edits since the previous build, None when everything may have changed
+    pub changed_region: Option<Rect>,
+    // convex pieces being built on the task pool, replaced when the contours change again
+    task: Option<Task<Vec<ColliderShape>>>,
+}
+
+impl TerrainColliders {
+    pub fn building(&self) -> bool {
+        self.task.is_some()
+    }
+
+    fn publish(&mut self, shapes: Vec<ColliderShape>, region: Option<Option<Rect>>) {
+        self.changed_region = region.flatten().map(|region| {
+            Rect::from_corners(texel_to_sprite(region.min), texel_to_sprite(region.max))
+        });
+        self.shapes = shapes;
+        self.generation = self.generation.wrapping_add(1);
+    }
+}
+
+// Marks the entities spawned for the physics adapters
+#[cfg(any(feature = "avian2d", feature = "rapier2d"))]
+#[derive(Component)]
+pub struct TerrainColliderEntity;
+
+pub struct ColliderPlugin;
+
+impl Plugin for ColliderPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ColliderSettings>();
+        app.init_resource::<TerrainColliders>();
+        app.add_systems(Update, (build_colliders, draw_colliders).chain());
+
+        #[cfg(any(feature = "avian2d", feature = "rapier2d"))]
+        app.add_systems(Update, spawn_collider_entities.after(build_colliders));
+    }
+}
+
+fn build_colliders(
+    contours: Res<Contours>,
+    settings: Res<ColliderSettings>,
+    mut colliders: ResMut<TerrainColliders>,
+    mut terrain_changed: EventReader<TerrainChanged>,
+    mut pending: Local<Option<Option<Rect>>>,
+) {
+    for event in terrain_changed.read() {
+        *pending = Some(match (pending.take(), event.region) {
+            (None, region) => region,
+            (Some(Some(a)), Some(b)) => Some(a.union(b)),
+            _ => None,
+        });
+    }
+
+    if contours.is_changed() || settings.is_changed() {
+        colliders.bypass_change_detection().task = None;
+        if !settings.enabled {
+            colliders.publish(Vec::new(), pending.take());
+        } else if settings.mode == ColliderMode::Polyline {
+            let shapes = contours.contours.iter().map(polyline_shape).collect();
+            colliders.publish(shapes, pending.take());
+        } else {
+            // ear clipping is quadratic in the points of an island, so it's kept off the main thread
+            let contours = contours.contours.clone();
+            let max_vertices = settings.max_convex_vertices;
+            let task = AsyncComputeTaskPool::get()
+                .spawn(async move { convex_shapes(&contours, max_vertices) });
+            colliders.bypass_change_detection().task = Some(task);
+        }
+    }
+
+    // polling doesn't count as a change, only the shapes arriving do
+    let Some(task) = colliders.bypass_change_detection().task.as_mut() else {
+        return;
+    };
+    let Some(shapes) = block_on(future::poll_once(task)) else {
+        return;
+    };
+    colliders.task = None;
+    colliders.publish(shapes, pending.take());
+}
+
+fn draw_colliders(
+    settings: Res<ColliderSettings>,
+    colliders: Res<TerrainColliders>,
+    sprites: Query<&Transform, With<ResultSprite>>,
+    mut gizmos: Gizmos,
+) {
+    if !settings.show_overlay {
+        return;
+    }
+    let Ok(transform) = sprites.get_single() else {
+        return;
+    };
+
+    let origin = transform.translation.truncate();
+
+    for shape in &colliders.shapes {
+        match shape {
+            ColliderShape::Polyline { vertices, indices } => {
+                for [a, b] in indices {
+                    gizmos.line_2d(
+                        origin + vertices[*a as usize],
+                        origin + vertices[*b as usize],
+                        OVERLAY_COLOUR,
+                    );
+                }
+            }
+            ColliderShape::Convex(points) => {
+                let points = points.iter().chain(points.first()).map(|p| origin + *p);
+                gizmos.linestrip_2d(points, OVERLAY_COLOUR);
+            }
+        }
+    }
+}
+
+fn to_world(points: &[Vec2]) -> Vec<Vec2> {
+    // the texture is y down, flipping it reverses the winding so reverse the order back
+    points.iter().rev().map(|p| texel_to_sprite(*p)).collect()
+}
+
+fn polyline_shape(contour: &Contour) -> ColliderShape {
+    let vertices = to_world(&contour.points);
+    let n = vertices.len() as u32;
+    let indices = (0..n).map(|i| [i, (i + 1) % n]).collect();
+    ColliderShape::Polyline { vertices, indices }
+}
+
+fn convex_shapes(contours: &[Contour], max_vertices: usize) -> Vec<ColliderShape> {
+    let islands: Vec<&Contour> = contours
+        .iter()
+        .filter(|c| c.kind == ContourKind::Island && c.points.len() >= 3)
+        .collect();
+
+    // each cave belongs to the smallest island around it
+    let mut holes: Vec<Vec<&Contour>> = vec![Vec::new(); islands.len()];
+    for cave in contours.iter().filter(|c| c.kind == ContourKind::Cave && c.points.len() >= 3) {
+        let owner = islands
+            .iter()
+            .enumerate()
+            .filter(|(_, island)| point_in_polygon(cave.points[0], &island.points))
+            .min_by(|a, b| a.1.area.total_cmp(&b.1.area));
+        if let Some((index, _)) = owner {
+            holes[index].push(cave);
+        }
+    }
+
+    islands
+        .iter()
+        .zip(holes)
+        .flat_map(|(island, holes)| {
+            let polygon = bridge_holes(&island.points, &holes);
+            let triangles = triangulate(&polygon);
+            merge_convex(&polygon, triangles, max_vertices)
+                .into_iter()
+                .map(|piece| {
+                    let points: Vec<Vec2> = piece.iter().map(|i| polygon[*i]).collect();
+                    ColliderShape::Convex(to_world(&points))
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+pub fn point_in_polygon(p: Vec2, polygon: &[Vec2]) -> bool {
+    if polygon.len() < 3 {
+        return false;
+    }
+
+    let mut inside = false;
+    let mut j = polygon.len() - 1;
+    for i in 0..polygon.len() {
+        let (a, b) = (polygon[i], polygon[j]);
+        if (a.y > p.y) != (b.y > p.y) && p.x < (b.x - a.x) * (p.y - a.y) / (b.y - a.y) + a.x {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+fn segments_cross(a: Vec2, b: Vec2, c: Vec2, d: Vec2) -> bool {
+    let side = |p: Vec2, q: Vec2, r: Vec2| (q - p).perp_dot(r - p);
+    let (d1, d2) = (side(a, b, c), side(a, b, d));
+    let (d3, d4) = (side(c, d, a), side(c, d, b));
+    d1 * d2 < 0.0 && d3 * d4 < 0.0
+}
+
+// Cut each hole into the outline with a pair of coincident edges so the result is a single
+// simple polygon that ear clipping can handle. Holes wind the opposite way to the outline.
+fn bridge_holes(outline: &[Vec2], holes: &[&Contour]) -> Vec<Vec2> {
+    let mut polygon = outline.to_vec();
+
+    // rightmost holes first so later bridges don't have to cross earlier ones
+    let mut holes: Vec<&[Vec2]> = holes.iter().map(|h| h.points.as_slice()).collect();
+    holes.sort_by(|a, b| {
+        let max_x = |h: &[Vec2]| h.iter().map(|p| p.x).fold(f32::MIN, f32::max);
+        max_x(b).total_cmp(&max_x(a))
+    });
+
+    for (h, hole) in holes.iter().enumerate() {
+        let (m, &hole_point) = hole
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.x.total_cmp(&b.1.x))
+            .unwrap();
+
+        // closest outline vertex the bridge can reach without crossing anything
+        let mut candidates: Vec<usize> = (0..polygon.len()).collect();
+        candidates.sort_by(|a, b| {
+            polygon[*a]
+                .distance_squared(hole_point)
+                .total_cmp(&polygon[*b].distance_squared(hole_point))
+        });
+
+        let visible = |v: usize| {
+            let target = polygon[v];
+            let crosses_ring = |ring: &[Vec2]| {
+                (0..ring.len()).any(|i| {
+                    segments_cross(hole_point, target, ring[i], ring[(i + 1) % ring.len()])
+                })
+            };
+            // the hole is entirely to the left of its rightmost point, so a bridge heading
+            // left could pass through the hole without crossing any of its edges
+            !crosses_ring(&polygon)
+                && !holes[h..].iter().any(|other| crosses_ring(other))
+                && !point_in_polygon((hole_point + target) * 0.5, hole)
+        };
+
+        let Some(v) = candidates.into_iter().find(|v| visible(*v)) else {
+            continue;
+        };
+
+        let mut bridged = Vec::with_capacity(polygon.len() + hole.len() + 2);
+        bridged.extend_from_slice(&polygon[..=v]);
+        bridged.extend_from_slice(&hole[m..]);
+        bridged.extend_from_slice(&hole[..=m]);
+        bridged.extend_from_slice(&polygon[v..]);
+        polygon = bridged;
+    }
+
+    polygon
+}
+
+fn point_in_triangle(p: Vec2, a: Vec2, b: Vec2, c: Vec2) -> bool {
+    (b - a).perp_dot(p - a) >= 0.0 && (c - b).perp_dot(p - b) >= 0.0 && (a - c).perp_dot(p - c) >= 0.0
+}
+
+// Ear clipping, the polygon has positive area. Returns triangles as indices into the polygon.
+fn triangulate(polygon: &[Vec2]) -> Vec<[usize; 3]> {
+    let mut remaining: Vec<usize> = (0..polygon.len()).collect();
+    let mut triangles = Vec::with_capacity(polygon.len());
+
+    let mut i = 0;
+    let mut since_last_ear = 0;
+    while remaining.len() > 3 {
+        let n = remaining.len();
+        let (prev, current, next) = (remaining[(i + n - 1) % n], remaining[i % n], remaining[(i + 1) % n]);
+        let (a, b, c) = (polygon[prev], polygon[current], polygon[next]);
+
+        let convex = (b - a).perp_dot(c - b) > 0.0;
+        let is_ear = convex
+            && !remaining.iter().any(|&j| {
+                let p = polygon[j];
+                // bridges duplicate vertices, those can't block an ear
+                p != a && p != b && p != c && point_in_triangle(p, a, b, c)
+            });
+
+        if is_ear || since_last_ear > n {
+            // a degenerate polygon can run out of ears, drop a vertex rather than loop forever
+            if is_ear {
+                triangles.push([prev, current, next]);
+            }
+            remaining.remove(i % n);
+            since_last_ear = 0;
+        } else {
+            i += 1;
+            since_last_ear += 1;
+        }
+        i %= remaining.len();
+    }
+
+    if remaining.len() == 3 {
+        triangles.push([remaining[0], remaining[1], remaining[2]]);
+    }
+
+    triangles
+}
+
+fn is_convex(polygon: &[Vec2], piece: &[usize]) -> bool {
+    let n = piece.len();
+    (0..n).all(|i| {
+        let (a, b, c) = (polygon[piece[i]], polygon[piece[(i + 1) % n]], polygon[piece[(i + 2) % n]]);
+        (b - a).perp_dot(c - b) >= -1e-4
+    })
+}
+
+// Hertel-Mehlhorn, greedily remove diagonals between pieces while the result stays convex
+fn merge_convex(polygon: &[Vec2], triangles: Vec<[usize; 3]>, max_vertices: usize) -> Vec<Vec<usize>> {
+    // directed edge to the piece it belongs to
+    let mut owners: HashMap<(usize, usize), usize> = HashMap::new();
+    for (index, [a, b, c]) in triangles.iter().enumerate() {
+        for edge in [(*a, *b), (*b, *c), (*c, *a)] {
+            owners.insert(edge, index);
+        }
+    }
+
+    let mut pieces: Vec<Option<Vec<usize>>> = triangles.into_iter().map(|t| Some(t.to_vec())).collect();
+
+    for p in 0..pieces.len() {
+        let mut merged_any = true;
+        while merged_any {
+            merged_any = false;
+            let Some(piece) = pieces[p].clone() else {
+                break;
+            };
+
+            for i in 0..piece.len() {
+                let (a, b) = (piece[i], piece[(i + 1) % piece.len()]);
+                let Some(&q) = owners.get(&(b, a)) else {
+                    continue;
+                };
+                if q == p {
+                    continue;
+                }
+                let Some(other) = pieces[q].as_ref() else {
+                    continue;
+                };
+                if piece.len() + other.len() - 2 > max_vertices {
+                    continue;
+                }
+
+                // walk p from b round to a, then q from a round to b
+                let start_p = (i + 1) % piece.len();
+                // merging hands all of q's edges to p, so the owner is never a stale piece, but a
+                // bad entry just means the diagonal stays
+                let Some(start_q) = other.iter().position(|v| *v == a) else {
+                    continue;
+                };
+                let mut candidate: Vec<usize> = (0..piece.len())
+                    .map(|k| piece[(start_p + k) % piece.len()])
+                    .collect();
+                candidate.extend((1..other.len() - 1).map(|k| other[(start_q + k) % other.len()]));
+
+                if !is_convex(polygon, &candidate) {
+                    continue;
+                }
+
+                for k in 0..candidate.len() {
+                    owners.insert((candidate[k], candidate[(k + 1) % candidate.len()]), p);
+                }
+                owners.remove(&(a, b));
+                owners.remove(&(b, a));
+                pieces[p] = Some(candidate);
+                pieces[q] = None;
+                merged_any = true;
+                break;
+            }
+        }
+    }
+
+    pieces.into_iter().flatten().collect()
+}
+
+#[cfg(any(feature = "avian2d", feature = "rapier2d"))]
+fn spawn_collider_entities(
+    mut commands: Commands,
+    colliders: Res<TerrainColliders>,
+    existing: Query<Entity, With<TerrainColliderEntity>>,
+    sprites: Query<Entity, With<ResultSprite>>,
+) {
+    if !colliders.is_changed() {
+        return;
+    }
+    let Ok(sprite) = sprites.get_single() else {
+        return;
+    };
+
+    for entity in &existing {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    // children of the result sprite so they follow it around
+    commands.entity(sprite).with_children(|parent| {
+        for shape in &colliders.shapes {
+            let mut entity = parent.spawn((TerrainColliderEntity, Transform::default()));
+
+            #[cfg(feature = "avian2d")]
+            if let Some(collider) = avian::collider(shape) {
+                entity.insert((avian2d::prelude::RigidBody::Static, collider));
+            }
+
+            #[cfg(feature = "rapier2d")]
+            if let Some(collider) = rapier::collider(shape) {
+                entity.insert((bevy_rapier2d::prelude::RigidBody::Fixed, collider));
+            }
+        }
+    });
+}
+
+#[cfg(feature = "avian2d")]
+pub mod avian {
+    use avian2d::prelude::Collider;
+
+    use super::ColliderShape;
+
+    pub fn collider(shape: &ColliderShape) -> Option<Collider> {
+        match shape {
+            ColliderShape::Polyline { vertices, indices } => {
+                Some(Collider::polyline(vertices.clone(), Some(indices.clone())))
+            }
+            ColliderShape::Convex(points) => Collider::convex_hull(points.clone()),
+        }
+    }
+}
+
+#[cfg(feature = "rapier2d")]
+pub mod rapier {
+    use bevy_rapier2d::prelude::Collider;
+
+    use super::ColliderShape;
+
+    pub fn collider(shape: &ColliderShape) -> Option<Collider> {
+        match shape {
+            ColliderShape::Polyline { vertices, indices } => {
+                Some(Collider::polyline(vertices.clone(), Some(indices.clone())))
+            }
+            ColliderShape::Convex(points) => Collider::convex_polyline(points.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contours::signed_area;
+
+    fn area(polygon: &[Vec2], piece: &[usize]) -> f32 {
+        signed_area(&piece.iter().map(|i| polygon[*i]).collect::<Vec<_>>())
+    }
+
+    // the pieces tile the polygon, are convex and none is over the limit
+    fn check_pieces(polygon: &[Vec2], expected_area: f32, max_vertices: usize) -> Vec<Vec<usize>> {
+        let triangles = triangulate(polygon);
+        assert_eq!(triangles.len(), polygon.len() - 2);
+        let triangle_area: f32 = triangles.iter().map(|t| area(polygon, t)).sum();
+        assert!((triangle_area - expected_area).abs() < 1e-4, "{}", triangle_area);
+
+        let pieces = merge_convex(polygon, triangles, max_vertices);
+        for piece in &pieces {
+            assert!(is_convex(polygon, piece), "{:?} isn't convex", piece);
+            assert!(piece.len() <= max_vertices, "{:?} has too many vertices", piece);
+            assert!(area(polygon, piece) > 0.0);
+        }
+        let piece_area: f32 = pieces.iter().map(|p| area(polygon, p)).sum();
+        assert!((piece_area - expected_area).abs() < 1e-4, "{}", piece_area);
+        pieces
+    }
+
+    #[test]
+    fn a_concave_l_merges_into_convex_pieces() {
+        let l = [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(2.0, 0.0),
+            Vec2::new(2.0, 1.0),
+            Vec2::new(1.0, 1.0),
+            Vec2::new(1.0, 2.0),
+            Vec2::new(0.0, 2.0),
+        ];
+        assert!(!is_convex(&l, &[0, 1, 2, 3, 4, 5]));
+
+        assert_eq!(check_pieces(&l, 3.0, 16).len(), 2);
+        // at three vertices nothing can merge
+        assert_eq!(check_pieces(&l, 3.0, 3).len(), 4);
+    }
+
+    #[test]
+    fn a_hole_is_bridged_and_left_out() {
+        let outline = [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(4.0, 0.0),
+            Vec2::new(4.0, 4.0),
+            Vec2::new(0.0, 4.0),
+        ];
+        // caves wind the other way
+        let hole = Contour {
+            kind: ContourKind::Cave,
+            points: vec![
+                Vec2::new(1.0, 1.0),
+                Vec2::new(1.0, 3.0),
+                Vec2::new(3.0, 3.0),
+                Vec2::new(3.0, 1.0),
+            ],
+            area: -4.0,
+        };
+
+        let polygon = bridge_holes(&outline, &[&hole]);
+        // both rings plus the two ends of the bridge
+        assert_eq!(polygon.len(), outline.len() + hole.points.len() + 2);
+        assert!((signed_area(&polygon) - 12.0).abs() < 1e-4);
+
+        let pieces = check_pieces(&polygon, 12.0, 8);
+        for piece in &pieces {
+            let centre = piece.iter().map(|i| polygon[*i]).sum::<Vec2>() / piece.len() as f32;
+            assert!(!point_in_polygon(centre, &hole.points), "{:?} is in the hole", piece);
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use bevy::{
+    prelude::*,
+    render::{
+        extract_resource::ExtractResourcePlugin,
+        render_asset::RenderAssets,
+        render_resource::{CommandEncoderDescriptor, Extent3d},
+        renderer::{render_system, RenderDevice, RenderQueue},
+        texture::GpuImage,
+        Render, RenderApp, RenderSet,
+    },
+    window::PrimaryWindow,
+};
+use bevy_egui::EguiContexts;
+
+use crate::{
+    constants::*, resources::CompareMode, Comparison, ImageBufferContainer,
+};
+
+// gap between the two images in side by side mode
+const SIDE_BY_SIDE_GAP: f32 = 20.0;
+
+// Marks the sprite showing the live result
+#[derive(Component)]
+pub struct ResultSprite;
+
+// Marks the sprite showing the snapshot
+#[derive(Component)]
+pub struct CompareSprite;
+
+pub struct ComparePlugin;
+
+impl Plugin for ComparePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Comparison>();
+        app.add_plugins(ExtractResourcePlugin::<Comparison>::default());
+        app.add_systems(Update, (drag_wipe, update_compare_sprites).chain());
+    }
+
+    fn finish(&self, app: &mut App) {
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app.add_systems(
+            Render,
+            copy_snapshot
+                .after(render_system)
+                .in_set(RenderSet::Render),
+        );
+    }
+}
+
+// Copies the current result into the compare texture whenever a new snapshot is requested
+fn copy_snapshot(
+    comparison: Res<Comparison>,
+    mut last_generation: Local<u32>,
+    textures: Res<ImageBufferContainer>,
+    images: Res<RenderAssets<GpuImage>>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    if comparison.snapshot_generation == *last_generation {
+        return;
+    }
+
+    let (Some(result), Some(compare)) = (
+        images.get(&textures.result),
+        images.get(&textures.compare),
+    ) else {
+        return;
+    };
+
+    *last_generation = comparison.snapshot_generation;
+
+    let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("snapshot"),
+    });
+    encoder.copy_texture_to_texture(
+        result.texture.as_image_copy(),
+        compare.texture.as_image_copy(),
+        Extent3d {
+            width: BUFFER_LEN as u32,
+            height: BUFFER_LEN as u32,
+            depth_or_array_layers: 1,
+        },
+    );
+    render_queue.submit([encoder.finish()]);
+}
+
+// Drag the wipe with the mouse when the pointer isn't over the gui
+fn drag_wipe(
+    mut comparison: ResMut<Comparison>,
+    mut contexts: EguiContexts,
+    mouse: Res<ButtonInput<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+) {
+    if comparison.mode != CompareMode::Wipe || !mouse.pressed(MouseButton::Left) {
+        return;
+    }
+
+    let ctx = contexts.ctx_mut();
+    if ctx.is_pointer_over_area() || ctx.is_using_pointer() {
+        return;
+    }
+
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    let Ok((camera, camera_transform)) = cameras.get_single() else {
+        return;
+    };
+    let Some(cursor) = window.cursor_position() else {
+        return;
+    };
+    let Ok(world_pos) = camera.viewport_to_world_2d(camera_transform, cursor) else {
+        return;
+    };
+
+    let split = ((world_pos.x + SPRITE_SIZE * 0.5) / SPRITE_SIZE).clamp(0.0, 1.0);
+    if split != comparison.split {
+        comparison.split = split;
+    }
+}
+
+fn update_compare_sprites(
+    comparison: Res<Comparison>,
+    mut result_sprites: Query<&mut Transform, (With<ResultSprite>, Without<CompareSprite>)>,
+    mut compare_sprites: Query<
+        (&mut Sprite, &mut Transform, &mut Visibility),
+        (With<CompareSprite>, Without<ResultSprite>),
+    >,
+) {
+    if !comparison.is_changed() {
+        return;
+    }
+
+    let has_snapshot = comparison.snapshot.is_some();
+
+    for mut transform in &mut result_sprites {
+        transform.translation.x = match comparison.mode {
+            CompareMode::SideBySide if has_snapshot => (SPRITE_SIZE + SIDE_BY_SIDE_GAP) * 0.5,
+            _ => 0.0,
+        };
+    }
+
+    for (mut sprite, mut transform, mut visibility) in &mut compare_sprites {
+        match comparison.mode {
+            CompareMode::SideBySide if has_snapshot => {
+                *visibility = Visibility::Visible;
+                sprite.rect = None;
+                sprite.custom_size = Some(Vec2::splat(SPRITE_SIZE));
+                transform.translation.x = -(SPRITE_SIZE + SIDE_BY_SIDE_GAP) * 0.5;
+            }
+            CompareMode::Wipe if has_snapshot && comparison.split > 0.0 => {
+                // show the left part of the snapshot over the live result
+                let width = SPRITE_SIZE * comparison.split;
+                *visibility = Visibility::Visible;
+                sprite.rect = Some(Rect::new(
+                    0.0,
+                    0.0,
+                    BUFFER_LEN as f32 * comparison.split,
+                    BUFFER_LEN as f32,
+                ));
+                sprite.custom_size = Some(Vec2::new(width, SPRITE_SIZE));
+                transform.translation.x = (width - SPRITE_SIZE) * 0.5;
+            }
+            _ => {
+                *visibility = Visibility::Hidden;
+            }
+        }
+    }
+}
@@ -2,13 +2,13 @@ use bevy::{
     prelude::*,
     render::{
         render_graph::{self},
-        render_resource::{ComputePassDescriptor, PipelineCache},
+        render_resource::{ComputePass, ComputePassDescriptor, PipelineCache},
         renderer::RenderContext,
     },
 };
 
 use crate::{
-    constants::*, parameters::ParamsUniform, pipeline::ComputePipelines, BindGroupSelection, GpuBufferBindGroups, ParamsChanged, ShaderConfigHolder
+    chunks::ChunkCopy, constants::*, parameters::{ParamsUniform, PassInfo, ViewParams}, pipeline::{ComputePipelines, PassConstants}, pipeline_status::PipelineStatus, profiler::{PassLabel, ProfilerSettings, StageProfiler}, stage_cache::StageCache, BindGroupSelection, Comparison, GpuBufferBindGroups, ParamsChanged, PassUniforms, ShaderConfigHolder
 };
 
 #[derive(Clone)]
@@ -23,9 +23,42 @@ pub struct ComputeNode {
     pub pipeline_index: usize,
     // pub is_final: bool,
     pub mode: ComputeNodeMode,
+    // runs the comparison parameter set instead of the live one
+    pub compare: bool,
 }
 
 
+// Give the dispatch its PassInfo, false when the uniforms for it aren't ready yet
+fn set_pass_info(pass: &mut ComputePass, world: &World, stage: usize, iteration: u32) -> bool {
+    match world.resource::<ComputePipelines>().pass_constants {
+        PassConstants::PushConstant => {
+            let config = &world.resource::<ShaderConfigHolder>().shader_configs[stage];
+            let view = world.resource::<ViewParams>();
+            let info = PassInfo {
+                stage: stage as u32,
+                iteration,
+                iterations: view.stage_iterations(stage, config.iterations),
+                converge_below: config.converge_below.unwrap_or(0),
+            };
+            pass.set_push_constants(0, bytemuck::bytes_of(&info));
+            true
+        }
+        PassConstants::DynamicUniform => {
+            let uniforms = world.resource::<PassUniforms>();
+            let offset = uniforms
+                .offsets
+                .get(stage)
+                .and_then(|offsets| offsets.get(iteration as usize));
+            match (&uniforms.bind_group, offset) {
+                (Some(bind_group), Some(offset)) => {
+                    pass.set_bind_group(1, bind_group, &[*offset]);
+                    true
+                }
+                _ => false,
+            }
+        }
+    }
+}
 
 impl render_graph::Node for ComputeNode {
     fn run(
@@ -41,11 +74,71 @@ impl render_graph::Node for ComputeNode {
         let selectors = world.resource::<BindGroupSelection>();
         let shader_configurator = world.resource::<ShaderConfigHolder>();
         let changed = world.resource::<ParamsChanged>();
+        let view = world.resource::<ViewParams>();
+        let stage_cache = world.resource::<StageCache>();
+        let chunk_copy = world.resource::<ChunkCopy>();
+        let profiler = world
+            .get_resource::<StageProfiler>()
+            .filter(|_| world.resource::<ProfilerSettings>().enabled);
+        let timestamp_writes = |stage: Option<usize>, iteration: u32| {
+            profiler.and_then(|p| {
+                p.timestamp_writes(PassLabel {
+                    stage,
+                    iteration,
+                    compare: self.compare,
+                })
+            })
+        };
 
         if !changed.0{
             // println!("not changed");
             return Ok(());
         }
+
+        if self.compare && !world.resource::<Comparison>().runs_snapshot() {
+            return Ok(());
+        }
+
+        let stage = match self.mode {
+            ComputeNodeMode::Extract => shader_configurator.shader_configs.len(),
+            _ => self.pipeline_index,
+        };
+
+        // the live chain starts at the first stage the cache has no entry for, the first node
+        // copies back what the ones before it wrote
+        if !self.compare {
+            let extract = matches!(self.mode, ComputeNodeMode::Extract);
+            if stage == 0 && !extract {
+                stage_cache.encode_restores(encoder, world);
+            }
+            if extract && stage_cache.restores_result() {
+                stage_cache.encode_result(encoder, world);
+                chunk_copy.encode_copy(encoder, world);
+                return Ok(());
+            }
+            if !extract && !stage_cache.runs(stage) {
+                return Ok(());
+            }
+        }
+
+        // with halting on, nothing after a stage that failed to compile runs
+        if world.resource::<PipelineStatus>().halts(stage) {
+            return Ok(());
+        }
+
+        let (stage_bind_groups, final_pass_a, final_pass_b) = if self.compare {
+            (
+                &bind_groups.compare_bind_groups,
+                &bind_groups.compare_final_pass_a,
+                &bind_groups.compare_final_pass_b,
+            )
+        } else {
+            (
+                &bind_groups.bind_groups,
+                &bind_groups.final_pass_a,
+                &bind_groups.final_pass_b,
+            )
+        };
             
         // println!("changed");
         
@@ -56,13 +149,15 @@ impl render_graph::Node for ComputeNode {
 
                     {
                         let group = if selectors.final_pass == 0 {
-                            &bind_groups.final_pass_a
+                            final_pass_a
                         } else {
-                            &bind_groups.final_pass_b
+                            final_pass_b
                         };
 
-                        let mut pass =
-                            encoder.begin_compute_pass(&ComputePassDescriptor::default());
+                        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                            label: Some("Final pass"),
+                            timestamp_writes: timestamp_writes(None, 0),
+                        });
                         pass.set_bind_group(0, group, &[]);
                         pass.set_pipeline(pipeline);
                         pass.dispatch_workgroups(
@@ -72,13 +167,31 @@ impl render_graph::Node for ComputeNode {
                         );
                     }
                     encoder.pop_debug_group();
+
+                    if !self.compare {
+                        stage_cache.encode_result(encoder, world);
+                        chunk_copy.encode_copy(encoder, world);
+                    }
                 }
             }
-            ComputeNodeMode::Compute2D(buffer_len) => {
+            ComputeNodeMode::Compute2D(buffer_len) | ComputeNodeMode::Compute1D(buffer_len) => {
+                let workgroups = match self.mode {
+                    ComputeNodeMode::Compute2D(_) => {
+                        let groups = ((buffer_len + 15) / 16) as u32;
+                        (groups, groups, 1)
+                    }
+                    _ => (((buffer_len + 255) / 256) as u32, 1, 1),
+                };
                 let pipeline_id = pipelines.pipeline_configs[self.pipeline_index];
 
+                // only a stage that dispatched every iteration is worth caching
+                let mut all_ready = false;
                 if let Some(pipeline) = pipeline_cache.get_compute_pipeline(pipeline_id) {
-                    let iters = shader_configurator.shader_configs[self.pipeline_index].iterations;
+                    all_ready = true;
+                    let iters = view.stage_iterations(
+                        self.pipeline_index,
+                        shader_configurator.shader_configs[self.pipeline_index].iterations,
+                    );
                     // println!("iters: {}", iters);
                     for iteration in 0..iters {
                         encoder.push_debug_group(&format!(
@@ -89,58 +202,38 @@ impl render_graph::Node for ComputeNode {
                         {
                             let node = self.pipeline_index as u32;
                             let selection = selectors.selectors[&node][iteration as usize];
-                            let mut pass =
-                                encoder.begin_compute_pass(&ComputePassDescriptor::default());
+                            let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                                label: None,
+                                timestamp_writes: timestamp_writes(
+                                    Some(self.pipeline_index),
+                                    iteration,
+                                ),
+                            });
                             pass.set_bind_group(
                                 0,
-                                &bind_groups.bind_groups[selection as usize],
+                                &stage_bind_groups[selection as usize],
                                 &[],
                             );
                             pass.set_pipeline(pipeline);
+                            let ready =
+                                set_pass_info(&mut pass, world, self.pipeline_index, iteration);
                             // println!("dispatching iteration {}", iteration);
-                            pass.dispatch_workgroups(
-                                ((buffer_len + 15) / 16) as u32,
-                                ((buffer_len + 15) / 16) as u32,
-                                1,
-                            );
+                            if ready {
+                                pass.dispatch_workgroups(workgroups.0, workgroups.1, workgroups.2);
+                            }
+                            all_ready &= ready;
                         }
                         encoder.pop_debug_group();
                     }
                 }
-            } 
-            ComputeNodeMode::Compute1D(buffer_len) => {
-                let pipeline_id = pipelines.pipeline_configs[self.pipeline_index];
-
-                if let Some(pipeline) = pipeline_cache.get_compute_pipeline(pipeline_id) {
-                    let iters = shader_configurator.shader_configs[self.pipeline_index].iterations;
 
-                    for iteration in 0..iters {
-                        encoder.push_debug_group(&format!(
-                            "Compute pass {} iteration {}",
-                            self.pipeline_index, iteration
-                        ));
-
-                        {
-                            let node = self.pipeline_index as u32;
-                            let selection = selectors.selectors[&node][iteration as usize];
-                            let mut pass =
-                                encoder.begin_compute_pass(&ComputePassDescriptor::default());
-                            pass.set_bind_group(
-                                0,
-                                &bind_groups.bind_groups[selection as usize],
-                                &[],
-                            );
-                            pass.set_pipeline(pipeline);
-                            pass.dispatch_workgroups(
-                                ((buffer_len + 255) / 256) as u32,
-                                1,
-                                1,
-                            );
-                        }
-                        encoder.pop_debug_group();
+                if !self.compare {
+                    if !all_ready {
+                        chunk_copy.spoil();
                     }
+                    stage_cache.encode_store(encoder, world, self.pipeline_index, all_ready);
                 }
-            } 
+            }
             
             
             // if self.is_final {
@@ -1,11 +1,13 @@
 use bevy::{
-    asset::load_internal_asset,
     prelude::*,
     render::{
         extract_resource::ExtractResourcePlugin,
         render_asset::RenderAssetUsages,
         render_graph::{RenderGraph, RenderLabel},
-        render_resource::{BufferUsages, Extent3d, TextureDimension, TextureFormat, TextureUsages},
+        render_resource::{
+            BufferUsages, Extent3d, SpecializedComputePipelines, TextureDimension, TextureFormat,
+            TextureUsages,
+        },
         renderer::RenderQueue,
         storage::ShaderStorageBuffer,
         Render, RenderApp, RenderSet,
@@ -13,64 +15,170 @@ use bevy::{
 };
 
 use crate::{
-    bind_groups::{prepare_bind_group_selection, prepare_bind_groups}, compute_node::{ComputeNode, ComputeNodeMode}, constants::*, data_structures::ShaderConfig, gradient_editor::update_gradient_texture, parameters::ParamsUniform, pipeline::ComputePipelines, GpuBufferBindGroups, ImageBufferContainer, ParamsChanged, ShaderConfigHolder
+    bind_groups::{prepare_bind_group_selection, prepare_bind_groups, prepare_pass_uniforms}, compare::{CompareSprite, ResultSprite}, convergence::convergence_buffer_size, compute_node::{ComputeNode, ComputeNodeMode}, constants::*, data_structures::ShaderConfig, edits::edit_buffer_size, ores::ore_buffer_size, gradient_editor::update_gradient_texture, materials::MaterialTable, parameters::{ParamsUniform, ViewParams}, pipeline::{respecialize_stages, ComputePipelines}, CommonShaders, Comparison, GpuBufferBindGroups, ImageBufferContainer, ParamsChanged, PassUniforms, ShaderConfigHolder
 };
 
 #[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
 enum ComputeNodeLabel {
     Compute(usize),
     Final,
+    CompareCompute(usize),
+    CompareFinal,
+}
+
+// The stages of the chain in order, shared with the CPU reference
+pub fn default_shader_configs() -> Vec<ShaderConfig> {
+    vec![
+        ShaderConfig {
+            shader_path: "shaders/init_generate_heights.wgsl",
+            shader_mode: ComputeNodeMode::Compute1D(STRIP_SIZE),
+            iterations: 1,
+            // voronoi ridges under the terrain noise, without it the surface is the bare terrain
+            shader_defs: vec!["USE_VORONOI".into()],
+            converge_below: None,
+        },
+        // one pass per step, the two share a pass counter to ping-pong the profile
+        ShaderConfig {
+            shader_path: "shaders/erode_thermal.wgsl",
+            shader_mode: ComputeNodeMode::Compute1D(STRIP_SIZE),
+            iterations: 32,
+            shader_defs: Vec::new(),
+            converge_below: None,
+        },
+        ShaderConfig {
+            shader_path: "shaders/erode_hydraulic.wgsl",
+            shader_mode: ComputeNodeMode::Compute1D(STRIP_SIZE),
+            iterations: 64,
+            shader_defs: Vec::new(),
+            converge_below: None,
+        },
+        ShaderConfig {
+            shader_path: "shaders/init_generate_circle.wgsl",
+            shader_mode: ComputeNodeMode::Compute2D(BUFFER_LEN),
+            iterations: 1,
+            shader_defs: Vec::new(),
+            converge_below: None,
+        },
+        ShaderConfig {
+            shader_path: "shaders/domain_warp_1.wgsl",
+            shader_mode: ComputeNodeMode::Compute2D(BUFFER_LEN),
+            iterations: 5,
+            shader_defs: Vec::new(),
+            converge_below: None,
+        },
+        ShaderConfig {
+            shader_path: "shaders/ca_prepare.wgsl",
+            shader_mode: ComputeNodeMode::Compute2D(BUFFER_LEN),
+            iterations: 1,
+            shader_defs: Vec::new(),
+            converge_below: None,
+        },
+        // counts the texels it flips, a threshold in the Pipelines window holds it once it settles
+        ShaderConfig {
+            shader_path: "shaders/ca_run.wgsl",
+            shader_mode: ComputeNodeMode::Compute2D(BUFFER_LEN),
+            iterations: 16,
+            shader_defs: Vec::new(),
+            converge_below: Some(0),
+        },
+        ShaderConfig {
+            shader_path: "shaders/domain_warp_2.wgsl",
+            shader_mode: ComputeNodeMode::Compute2D(BUFFER_LEN),
+            iterations: 1,
+            shader_defs: Vec::new(),
+            converge_below: None,
+        },
+        ShaderConfig {
+            shader_path: "shaders/subtract_caves.wgsl",
+            shader_mode: ComputeNodeMode::Compute2D(BUFFER_LEN),
+            iterations: 1,
+            shader_defs: Vec::new(),
+            converge_below: None,
+        },
+        // one pass per batch of edits, see TerrainEdits::batches
+        ShaderConfig {
+            shader_path: "shaders/apply_edits.wgsl",
+            shader_mode: ComputeNodeMode::Compute2D(BUFFER_LEN),
+            iterations: 1,
+            shader_defs: Vec::new(),
+            converge_below: None,
+        },
+        ShaderConfig {
+            shader_path: "shaders/jump_flood_prepare.wgsl",
+            shader_mode: ComputeNodeMode::Compute2D(BUFFER_LEN),
+            iterations: 1,
+            shader_defs: Vec::new(),
+            converge_below: None,
+        },
+        // one pass per step of the schedule in the uniform
+        ShaderConfig {
+            shader_path: "shaders/jump_flood_run.wgsl",
+            shader_mode: ComputeNodeMode::Compute2D(BUFFER_LEN),
+            iterations: ParamsUniform::default().jfa_pass_count,
+            shader_defs: Vec::new(),
+            converge_below: None,
+        },
+        ShaderConfig {
+            shader_path: "shaders/classify_materials.wgsl",
+            shader_mode: ComputeNodeMode::Compute2D(BUFFER_LEN),
+            iterations: 1,
+            shader_defs: Vec::new(),
+            converge_below: None,
+        },
+        ShaderConfig {
+            shader_path: "shaders/place_ores.wgsl",
+            shader_mode: ComputeNodeMode::Compute2D(BUFFER_LEN),
+            iterations: 1,
+            shader_defs: Vec::new(),
+            converge_below: None,
+        },
+        ShaderConfig {
+            shader_path: "shaders/liquid_prepare.wgsl",
+            shader_mode: ComputeNodeMode::Compute2D(BUFFER_LEN),
+            iterations: 1,
+            shader_defs: Vec::new(),
+            converge_below: None,
+        },
+        // one pass per step of falling, enough for the liquid to settle
+        ShaderConfig {
+            shader_path: "shaders/liquid_run.wgsl",
+            shader_mode: ComputeNodeMode::Compute2D(BUFFER_LEN),
+            iterations: 128,
+            shader_defs: Vec::new(),
+            converge_below: Some(0),
+        },
+    ]
 }
 
 pub struct ComputeShaderPlugin;
 
+// The jump flood runs one pass per step of the schedule in the uniform, which the refinements
+// slider, a preset or a restored snapshot can replace
+fn sync_jump_flood_passes(
+    params: Res<ParamsUniform>,
+    mut configs: ResMut<ShaderConfigHolder>,
+    mut changed: ResMut<ParamsChanged>,
+) {
+    if !params.is_changed() {
+        return;
+    }
+    let passes = params.jfa_pass_count;
+    if configs
+        .stage("jump_flood_run")
+        .is_none_or(|config| config.iterations == passes)
+    {
+        return;
+    }
+
+    if let Some(config) = configs.stage_mut("jump_flood_run") {
+        config.iterations = passes;
+    }
+    changed.0 = true;
+}
+
 impl Plugin for ComputeShaderPlugin {
     fn build(&self, app: &mut App) {
-        let shader_configs = vec![
-            ShaderConfig {
-                shader_path: "shaders/init_generate_heights.wgsl",
-                shader_mode: ComputeNodeMode::Compute1D(STRIP_SIZE),
-                iterations: 1,
-            },
-            ShaderConfig {
-                shader_path: "shaders/init_generate_circle.wgsl",
-                shader_mode: ComputeNodeMode::Compute2D(BUFFER_LEN),
-                iterations: 1,
-            },
-            ShaderConfig {
-                shader_path: "shaders/domain_warp_1.wgsl",
-                shader_mode: ComputeNodeMode::Compute2D(BUFFER_LEN),
-                iterations: 5,
-            },
-            ShaderConfig {
-                shader_path: "shaders/ca_prepare.wgsl",
-                shader_mode: ComputeNodeMode::Compute2D(BUFFER_LEN),
-                iterations: 1,
-            },
-            ShaderConfig {
-                shader_path: "shaders/ca_run.wgsl",
-                shader_mode: ComputeNodeMode::Compute2D(BUFFER_LEN),
-                iterations: 16,
-            },
-            ShaderConfig {
-                shader_path: "shaders/domain_warp_2.wgsl",
-                shader_mode: ComputeNodeMode::Compute2D(BUFFER_LEN),
-                iterations: 1,
-            },
-            ShaderConfig {
-                shader_path: "shaders/subtract_caves.wgsl",
-                shader_mode: ComputeNodeMode::Compute2D(BUFFER_LEN),
-                iterations: 1,
-            },
-            // ShaderConfig {
-            //     shader_path: "shaders/jump_flood_prepare.wgsl",
-            //     iterations: 1,
-            // },
-            // ShaderConfig {
-            //     shader_path: "shaders/jump_flood_run.wgsl",
-            //     iterations: 30,
-            // },
-        ];
+        let shader_configs = default_shader_configs();
 
         app.insert_resource(ShaderConfigHolder { shader_configs });
         app.insert_resource(ParamsChanged::default());
@@ -80,21 +188,27 @@ impl Plugin for ComputeShaderPlugin {
         load_common_shaders(app);
 
         app.add_systems(Startup, setup);
+        app.add_systems(PostUpdate, sync_jump_flood_passes);
         // app.add_systems(PostUpdate, reset_changed);
     }
 
     fn finish(&self, app: &mut App) {
         let shader_configs = app.world().resource::<ShaderConfigHolder>().clone();
+        let common_shaders = app.world().resource::<CommonShaders>().clone();
 
         let render_app = app.sub_app_mut(RenderApp);
 
         render_app.insert_resource(shader_configs.clone());
+        render_app.insert_resource(common_shaders);
 
+        render_app.init_resource::<SpecializedComputePipelines<ComputePipelines>>();
+        render_app.init_resource::<PassUniforms>();
         render_app.init_resource::<ComputePipelines>().add_systems(
             Render,
             (
                 update_gradient_texture,
                 update_uniform_buffer,
+                respecialize_stages.in_set(RenderSet::Prepare),
                 // reset_changed.after(),
                 prepare_bind_groups
                     .in_set(RenderSet::PrepareBindGroups)
@@ -102,6 +216,7 @@ impl Plugin for ComputeShaderPlugin {
                 prepare_bind_group_selection
                     .in_set(RenderSet::PrepareBindGroups)
                     .after(prepare_bind_groups),
+                prepare_pass_uniforms.in_set(RenderSet::PrepareBindGroups),
                 reset_changed.in_set(RenderSet::Cleanup),
             ),
         );
@@ -111,8 +226,35 @@ impl Plugin for ComputeShaderPlugin {
         // Generate nodes dynamically
         let mut node_labels: Vec<ComputeNodeLabel> = Vec::new();
 
-        // Create compute nodes
-        for (index, _) in shader_configs.shader_configs.iter().enumerate() {
+        // The comparison chain runs the snapshot parameters first, through the same buffers, so
+        // the live chain's grids, strips, rock mask and ore counts are what gets read back
+        for (index, config) in shader_configs.shader_configs.iter().enumerate() {
+            let label = ComputeNodeLabel::CompareCompute(index);
+            node_labels.push(label.clone());
+
+            render_graph.add_node(
+                label,
+                ComputeNode {
+                    pipeline_index: index,
+                    mode: config.shader_mode.clone(),
+                    compare: true,
+                },
+            );
+        }
+
+        let compare_final_label = ComputeNodeLabel::CompareFinal;
+        node_labels.push(compare_final_label.clone());
+        render_graph.add_node(
+            compare_final_label,
+            ComputeNode {
+                pipeline_index: 0,
+                mode: ComputeNodeMode::Extract,
+                compare: true,
+            },
+        );
+
+        // Create compute nodes, each dispatched in its stage's shape
+        for (index, config) in shader_configs.shader_configs.iter().enumerate() {
             let label = ComputeNodeLabel::Compute(index);
             node_labels.push(label.clone());
 
@@ -120,8 +262,9 @@ impl Plugin for ComputeShaderPlugin {
                 label,
                 ComputeNode {
                     pipeline_index: index,
-                    mode: ComputeNodeMode::Compute2D(BUFFER_LEN),
+                    mode: config.shader_mode.clone(),
                     // is_final: false,
+                    compare: false,
                 },
             );
         }
@@ -134,6 +277,7 @@ impl Plugin for ComputeShaderPlugin {
             ComputeNode {
                 pipeline_index: 0,
                 mode: ComputeNodeMode::Extract,
+                compare: false,
             },
         );
 
@@ -148,6 +292,7 @@ fn setup(
     mut commands: Commands,
     mut images: ResMut<Assets<Image>>,
     mut buffers: ResMut<Assets<ShaderStorageBuffer>>,
+    configs: Res<ShaderConfigHolder>,
 ) {
     // Datastrip Buffers
     let strip_buffer_size = std::mem::size_of::<f32>() * STRIP_SIZE * STRIP_COUNT
@@ -157,13 +302,13 @@ fn setup(
         &vec![0u8; strip_buffer_size],
         RenderAssetUsages::RENDER_WORLD,
     );
-    strip_buffer_1.buffer_description.usage |= BufferUsages::COPY_SRC;
+    strip_buffer_1.buffer_description.usage |= BufferUsages::COPY_SRC | BufferUsages::COPY_DST;
     
     let mut strip_buffer_2 = ShaderStorageBuffer::new(
         &vec![0u8; strip_buffer_size],
         RenderAssetUsages::RENDER_WORLD,
     );
-    strip_buffer_2.buffer_description.usage |= BufferUsages::COPY_SRC;
+    strip_buffer_2.buffer_description.usage |= BufferUsages::COPY_SRC | BufferUsages::COPY_DST;
 
     let strip_buffer_1_handle = buffers.add(strip_buffer_1);
     let strip_buffer_2_handle = buffers.add(strip_buffer_2);
@@ -177,17 +322,53 @@ fn setup(
         &vec![0u8; grid_buffer_size],
         RenderAssetUsages::RENDER_WORLD,
     );
-    grid_buffer_1.buffer_description.usage |= BufferUsages::COPY_SRC;
+    grid_buffer_1.buffer_description.usage |= BufferUsages::COPY_SRC | BufferUsages::COPY_DST;
 
     let mut grid_buffer_2 = ShaderStorageBuffer::new(
         &vec![0u8; grid_buffer_size],
         RenderAssetUsages::RENDER_WORLD,
     );
-    grid_buffer_2.buffer_description.usage |= BufferUsages::COPY_SRC;
+    grid_buffer_2.buffer_description.usage |= BufferUsages::COPY_SRC | BufferUsages::COPY_DST;
 
     let grid_buffer_1_handle = buffers.add(grid_buffer_1);
     let grid_buffer_2_handle = buffers.add(grid_buffer_2);
 
+    // Rock mask, written by apply_edits and read back for the contours
+
+    let mut rock_mask = ShaderStorageBuffer::new(
+        &vec![0u8; std::mem::size_of::<f32>() * BUFFER_LEN * BUFFER_LEN],
+        RenderAssetUsages::RENDER_WORLD,
+    );
+    rock_mask.buffer_description.usage |= BufferUsages::COPY_SRC | BufferUsages::COPY_DST;
+    let rock_mask_handle = buffers.add(rock_mask);
+
+    // Terrain edits, uploaded by the edits plugin whenever they change
+
+    let mut edit_buffer = ShaderStorageBuffer::new(
+        &vec![0u8; edit_buffer_size()],
+        RenderAssetUsages::RENDER_WORLD,
+    );
+    edit_buffer.buffer_description.usage |= BufferUsages::COPY_DST;
+    let edit_buffer_handle = buffers.add(edit_buffer);
+
+    // Ore definitions uploaded by the ores plugin, with the per ore counts read back after them
+
+    let mut ore_buffer = ShaderStorageBuffer::new(
+        &vec![0u8; ore_buffer_size()],
+        RenderAssetUsages::RENDER_WORLD,
+    );
+    ore_buffer.buffer_description.usage |= BufferUsages::COPY_SRC | BufferUsages::COPY_DST;
+    let ore_buffer_handle = buffers.add(ore_buffer);
+
+    // Changes counted by the iterative stages, zeroed before each run and read back after it
+
+    let mut convergence = ShaderStorageBuffer::new(
+        &vec![0u8; convergence_buffer_size(configs.shader_configs.len())],
+        RenderAssetUsages::RENDER_WORLD,
+    );
+    convergence.buffer_description.usage |= BufferUsages::COPY_SRC | BufferUsages::COPY_DST;
+    let convergence_handle = buffers.add(convergence);
+
     // Texture buffers
 
     let texture_size = Extent3d {
@@ -204,7 +385,8 @@ fn setup(
             TextureFormat::Rgba32Float,
             RenderAssetUsages::RENDER_WORLD,
         );
-        let texture_usages = TextureUsages::COPY_SRC | TextureUsages::STORAGE_BINDING;
+        let texture_usages =
+            TextureUsages::COPY_SRC | TextureUsages::COPY_DST | TextureUsages::STORAGE_BINDING;
         image.texture_descriptor.usage |= texture_usages;
         images.add(image)
     };
@@ -216,6 +398,7 @@ fn setup(
     let texture_buffer_a3 = create_texture_image();
     let texture_buffer_b3 = create_texture_image();
     let result = create_texture_image();
+    let compare = create_texture_image();
 
     // Grad Texture
 
@@ -237,10 +420,22 @@ fn setup(
     commands.spawn((
         Sprite {
             image: result.clone(),
-            custom_size: Some(Vec2::splat(1000.0)),
+            custom_size: Some(Vec2::splat(SPRITE_SIZE)),
             ..Default::default()
         },
         Transform::from_xyz(0.0, 0.5, 0.0).with_scale(Vec3::splat(1.0)),
+        ResultSprite,
+    ));
+
+    commands.spawn((
+        Sprite {
+            image: compare.clone(),
+            custom_size: Some(Vec2::splat(SPRITE_SIZE)),
+            ..Default::default()
+        },
+        Transform::from_xyz(0.0, 0.5, 0.1).with_scale(Vec3::splat(1.0)),
+        Visibility::Hidden,
+        CompareSprite,
     ));
 
     commands.insert_resource(ImageBufferContainer {
@@ -251,10 +446,15 @@ fn setup(
         tex_buffer_a3: texture_buffer_a3,
         tex_buffer_b3: texture_buffer_b3,
         result,
+        compare,
         grid_buffer_a: grid_buffer_1_handle,
         grid_buffer_b: grid_buffer_2_handle,
         strip_buffer_a: strip_buffer_1_handle,
         strip_buffer_b: strip_buffer_2_handle,
+        rock_mask: rock_mask_handle,
+        edit_buffer: edit_buffer_handle,
+        ore_buffer: ore_buffer_handle,
+        convergence: convergence_handle,
         grad_texture: grad_texture_handle,
     });
 }
@@ -263,9 +463,22 @@ fn update_uniform_buffer(
     bind_groups: Option<Res<GpuBufferBindGroups>>,
     render_queue: Res<RenderQueue>,
     params: Res<ParamsUniform>,
+    view: Res<ViewParams>,
+    comparison: Res<Comparison>,
+    materials: Res<MaterialTable>,
 ) {
     if let Some(bind_group) = bind_groups {
         render_queue.write_buffer(&bind_group.uniform_buffer, 0, bytemuck::bytes_of(&*params));
+        render_queue.write_buffer(&bind_group.view_uniform_buffer, 0, bytemuck::bytes_of(&*view));
+        render_queue.write_buffer(
+            &bind_group.material_uniform_buffer,
+            0,
+            bytemuck::bytes_of(&materials.colours()),
+        );
+
+        if let Some(snapshot) = &comparison.snapshot {
+            render_queue.write_buffer(&bind_group.compare_uniform_buffer, 0, bytemuck::bytes_of(snapshot));
+        }
     }
 }
 
@@ -279,34 +492,47 @@ fn reset_changed(mut changed: ResMut<ParamsChanged>) {
 }
 
 fn load_common_shaders(app: &mut App) {
-    load_internal_asset!(app, COMMON_HANDLE, "shaders/common.wgsl", Shader::from_wgsl);
-    load_internal_asset!(
-        app,
-        UTIL_NOISE_SHADER_HANDLE,
-        "shaders/utils/noise.wgsl",
-        Shader::from_wgsl
-    );
-    load_internal_asset!(
-        app,
-        UTILS_SHADER_HANDLE,
-        "shaders/utils/utils.wgsl",
-        Shader::from_wgsl
-    );
-    load_internal_asset!(
-        app,
-        UTILS_SHADER_HANDLE,
-        "shaders/utils/anal_noise.wgsl",
-        Shader::from_wgsl
-    );
-
-    // todo: this should be loaded as an asset like the other compute shaders
-
-    load_internal_asset!(
-        app,
-        EXTRACT_HANDLE,
-        "shaders/extract.wgsl",
-        Shader::from_wgsl
-    );
+    let asset_server = app.world().resource::<AssetServer>();
+
+    let shaders = CommonShaders {
+        common: asset_server.load("shaders/common.wgsl"),
+        noise: asset_server.load("shaders/utils/noise.wgsl"),
+        utils: asset_server.load("shaders/utils/utils.wgsl"),
+        anal_noise: asset_server.load("shaders/utils/anal_noise.wgsl"),
+        pass: asset_server.load("shaders/utils/pass.wgsl"),
+        extract: asset_server.load("shaders/extract.wgsl"),
+    };
+    app.insert_resource(shaders);
+}
 
-    app.add_systems(Startup, setup);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jump_flood_passes_follow_replaced_params() {
+        let mut app = App::new();
+        app.insert_resource(ParamsUniform::default());
+        app.insert_resource(ShaderConfigHolder {
+            shader_configs: default_shader_configs(),
+        });
+        app.insert_resource(ParamsChanged::default());
+        app.add_systems(PostUpdate, sync_jump_flood_passes);
+
+        let passes = |app: &App| {
+            let configs = app.world().resource::<ShaderConfigHolder>();
+            configs.stage("jump_flood_run").unwrap().iterations
+        };
+        app.update();
+        assert_eq!(passes(&app), ParamsUniform::default().jfa_pass_count);
+
+        // a snapshot from another refinement level replaces the whole uniform
+        let snapshot = ParamsUniform::default().with_jfa_refinements(2);
+        assert_ne!(snapshot.jfa_pass_count, ParamsUniform::default().jfa_pass_count);
+        *app.world_mut().resource_mut::<ParamsUniform>() = snapshot;
+        app.world_mut().resource_mut::<ParamsChanged>().0 = false;
+        app.update();
+        assert_eq!(passes(&app), snapshot.jfa_pass_count);
+        assert!(app.world().resource::<ParamsChanged>().0);
+    }
 }
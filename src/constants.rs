@@ -1,16 +1,19 @@
-use bevy::prelude::*;
+// these need to match the constance in assets/shaders/common.wgsl
 
+pub const BUFFER_LEN: usize = 1024;
+pub const GRID_SIZE: usize = 8;
 
-pub const COMMON_HANDLE: Handle<Shader> = Handle::weak_from_u128(13278847158748079035);
-pub const EXTRACT_HANDLE: Handle<Shader> = Handle::weak_from_u128(33378847158248049035);
-pub const UTIL_NOISE_SHADER_HANDLE: Handle<Shader> = Handle::weak_from_u128(14378847158248049035);
-pub const UTILS_SHADER_HANDLE: Handle<Shader> = Handle::weak_from_u128(25378847158248049035);
+pub const STRIP_SIZE: usize = 8192;
+pub const STRIP_COUNT: usize = 3;
 
+pub const MAX_EDITS: usize = 1024;
+pub const MAX_EDIT_VERTICES: usize = 8192;
 
-// these need to match the constance in src/shaders/common.wgsl
+pub const MAX_MATERIALS: usize = 16;
+pub const MAX_ORES: usize = 8;
 
-pub const BUFFER_LEN: usize = 1024;
-pub const GRID_SIZE: usize = 8;
+// iterations of a stage that count their changes, the gui's iteration sliders stop here
+pub const MAX_CONVERGENCE_ITERATIONS: usize = 2048;
 
-pub const STRIP_SIZE: usize = 8192;
-pub const STRIP_COUNT: usize = 3;
\ No newline at end of file
+// size of the result sprite in world units
+pub const SPRITE_SIZE: f32 = 1000.0;
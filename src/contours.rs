@@ -0,0 +1,490 @@
+use bevy::{
+    prelude::*,
+    render::gpu_readback::{Readback, ReadbackComplete},
+    utils::HashMap,
+};
+
+use crate::{compare::ResultSprite, constants::*, ImageBufferContainer, ParamsChanged};
+
+const ISLAND_COLOUR: Color = Color::srgb(1.0, 0.8, 0.2);
+const CAVE_COLOUR: Color = Color::srgb(0.2, 0.8, 1.0);
+
+// The rock mask written by apply_edits, read back whenever the chain re-runs
+#[derive(Resource, Default)]
+pub struct RockMask {
+    pub size: usize,
+    pub values: Vec<f32>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ContourKind {
+    // outline of a solid region
+    Island,
+    // outline of a hole inside a solid region
+    Cave,
+}
+
+#[derive(Clone, Debug)]
+pub struct Contour {
+    pub kind: ContourKind,
+    // closed loop in texel coordinates, the last point connects back to the first.
+    // islands wind with positive area, caves with negative, so rock is always on the same side
+    pub points: Vec<Vec2>,
+    pub area: f32,
+}
+
+#[derive(Resource, Default)]
+pub struct Contours {
+    pub contours: Vec<Contour>,
+    // point count before simplification
+    pub raw_points: usize,
+}
+
+#[derive(Resource)]
+pub struct ContourSettings {
+    pub iso_level: f32,
+    // Douglas-Peucker tolerance in texels, 0 keeps every point
+    pub tolerance: f32,
+    // contours enclosing less than this many texels are dropped
+    pub min_area: f32,
+    pub show_overlay: bool,
+}
+
+impl Default for ContourSettings {
+    fn default() -> Self {
+        Self {
+            iso_level: 0.5,
+            tolerance: 1.0,
+            min_area: 4.0,
+            show_overlay: false,
+        }
+    }
+}
+
+pub struct ContourPlugin;
+
+impl Plugin for ContourPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RockMask>();
+        app.init_resource::<Contours>();
+        app.init_resource::<ContourSettings>();
+        app.add_systems(PostUpdate, request_rock_mask_readback);
+        app.add_systems(Update, (extract_contours, draw_contours).chain());
+    }
+}
+
+fn request_rock_mask_readback(
+    mut commands: Commands,
+    changed: Res<ParamsChanged>,
+    buffers: Option<Res<ImageBufferContainer>>,
+) {
+    let Some(buffers) = buffers else {
+        return;
+    };
+
+    if !changed.is_changed() {
+        return;
+    }
+
+    commands.spawn(Readback::buffer(buffers.rock_mask.clone())).observe(
+        |trigger: Trigger<ReadbackComplete>, mut commands: Commands, mut mask: ResMut<RockMask>| {
+            mask.values = bytemuck::pod_collect_to_vec(&trigger.event().0);
+            mask.size = BUFFER_LEN;
+            commands.entity(trigger.entity()).despawn();
+        },
+    );
+}
+
+fn extract_contours(
+    mask: Res<RockMask>,
+    settings: Res<ContourSettings>,
+    mut contours: ResMut<Contours>,
+) {
+    if !mask.is_changed() && !settings.is_changed() {
+        return;
+    }
+    if mask.values.len() != mask.size * mask.size {
+        return;
+    }
+
+    let loops = marching_squares(&mask.values, mask.size, mask.size, settings.iso_level);
+    contours.raw_points = loops.iter().map(|l| l.len()).sum();
+
+    contours.contours = loops
+        .into_iter()
+        .filter_map(|points| {
+            let area = signed_area(&points);
+            if area.abs() < settings.min_area {
+                return None;
+            }
+
+            Some(Contour {
+                kind: if area > 0.0 {
+                    ContourKind::Island
+                } else {
+                    ContourKind::Cave
+                },
+                points: simplify_closed(&points, settings.tolerance),
+                area,
+            })
+        })
+        .collect();
+}
+
+fn draw_contours(
+    settings: Res<ContourSettings>,
+    contours: Res<Contours>,
+    sprites: Query<&Transform, With<ResultSprite>>,
+    mut gizmos: Gizmos,
+) {
+    if !settings.show_overlay {
+        return;
+    }
+    let Ok(transform) = sprites.get_single() else {
+        return;
+    };
+
+    let origin = transform.translation.truncate();
+
+    for contour in &contours.contours {
+        let colour = match contour.kind {
+            ContourKind::Island => ISLAND_COLOUR,
+            ContourKind::Cave => CAVE_COLOUR,
+        };
+
+        let points = contour
+            .points
+            .iter()
+            .chain(contour.points.first())
+            .map(|p| origin + texel_to_sprite(*p));
+        gizmos.linestrip_2d(points, colour);
+    }
+}
+
+/// Position of a texel coordinate relative to the centre of the result sprite
+pub fn texel_to_sprite(p: Vec2) -> Vec2 {
+    let uv = p / BUFFER_LEN as f32 - 0.5;
+    Vec2::new(uv.x, -uv.y) * SPRITE_SIZE
+}
+
+/// Inverse of texel_to_sprite
+pub fn sprite_to_texel(p: Vec2) -> Vec2 {
+    let uv = p / SPRITE_SIZE;
+    (Vec2::new(uv.x, -uv.y) + 0.5) * BUFFER_LEN as f32
+}
+
+// unit cell, corners clockwise from the top left and edges top, right, bottom, left
+const CELL_CORNERS: [Vec2; 4] = [
+    Vec2::new(0.0, 0.0),
+    Vec2::new(1.0, 0.0),
+    Vec2::new(1.0, 1.0),
+    Vec2::new(0.0, 1.0),
+];
+const EDGE_MIDPOINTS: [Vec2; 4] = [
+    Vec2::new(0.5, 0.0),
+    Vec2::new(1.0, 0.5),
+    Vec2::new(0.5, 1.0),
+    Vec2::new(0.0, 0.5),
+];
+
+// Crossings are identified by the grid edge they sit on, horizontal edges run from (x, y) to
+// (x + 1, y) and vertical ones from (x, y) to (x, y + 1) in sample coordinates
+fn edge_key(x: i32, y: i32, vertical: bool) -> u64 {
+    (((x + 1) as u64) << 32) | (((y + 1) as u64) << 1) | vertical as u64
+}
+
+/// Closed loops around everything at or above `iso`, in texel coordinates.
+/// Samples outside the grid count as empty so every loop closes.
+pub fn marching_squares(values: &[f32], width: usize, height: usize, iso: f32) -> Vec<Vec<Vec2>> {
+    let sample = |x: i32, y: i32| -> f32 {
+        if x < 0 || y < 0 || x >= width as i32 || y >= height as i32 {
+            f32::MIN
+        } else {
+            values[y as usize * width + x as usize]
+        }
+    };
+
+    // where the iso line crosses an edge, samples sit at texel centres
+    let crossing = |a: Vec2, va: f32, b: Vec2, vb: f32| -> Vec2 {
+        let t = if va == f32::MIN || vb == f32::MIN {
+            0.5
+        } else {
+            ((iso - va) / (vb - va)).clamp(0.0, 1.0)
+        };
+        a.lerp(b, t) + Vec2::splat(0.5)
+    };
+
+    // each segment maps its start crossing to its end crossing
+    let mut next: HashMap<u64, u64> = HashMap::new();
+    let mut positions: HashMap<u64, Vec2> = HashMap::new();
+
+    for cy in -1..height as i32 {
+        for cx in -1..width as i32 {
+            let corners = [(cx, cy), (cx + 1, cy), (cx + 1, cy + 1), (cx, cy + 1)];
+            let v = corners.map(|(x, y)| sample(x, y));
+            let solid = v.map(|v| v >= iso);
+
+            let case = (solid[0] as u8) << 3
+                | (solid[1] as u8) << 2
+                | (solid[2] as u8) << 1
+                | solid[3] as u8;
+            if case == 0 || case == 15 {
+                continue;
+            }
+
+            let p = corners.map(|(x, y)| Vec2::new(x as f32, y as f32));
+
+            // top, right, bottom, left
+            let edges = [
+                (edge_key(cx, cy, false), crossing(p[0], v[0], p[1], v[1])),
+                (edge_key(cx + 1, cy, true), crossing(p[1], v[1], p[2], v[2])),
+                (edge_key(cx, cy + 1, false), crossing(p[3], v[3], p[2], v[2])),
+                (edge_key(cx, cy, true), crossing(p[0], v[0], p[3], v[3])),
+            ];
+            const TOP: usize = 0;
+            const RIGHT: usize = 1;
+            const BOTTOM: usize = 2;
+            const LEFT: usize = 3;
+
+            let centre_solid = v.iter().sum::<f32>() * 0.25 >= iso;
+
+            // pairs of crossed edges, with the corner used to decide which side is solid
+            let segments: &[(usize, usize, usize)] = match case {
+                1 | 14 => &[(LEFT, BOTTOM, 3)],
+                2 | 13 => &[(BOTTOM, RIGHT, 2)],
+                3 | 12 => &[(LEFT, RIGHT, 3)],
+                4 | 11 => &[(TOP, RIGHT, 1)],
+                6 | 9 => &[(TOP, BOTTOM, 0)],
+                7 | 8 => &[(LEFT, TOP, 0)],
+                // saddles, the centre decides which diagonal is connected
+                5 if centre_solid => &[(LEFT, TOP, 0), (BOTTOM, RIGHT, 2)],
+                5 => &[(TOP, RIGHT, 1), (LEFT, BOTTOM, 3)],
+                10 if centre_solid => &[(TOP, RIGHT, 1), (LEFT, BOTTOM, 3)],
+                _ => &[(LEFT, TOP, 0), (BOTTOM, RIGHT, 2)],
+            };
+
+            for &(a, b, corner) in segments {
+                let (mut start, mut end) = (edges[a], edges[b]);
+
+                // orient every segment so the solid side is on its left (positive cross product),
+                // worked out on the edge midpoints so the corner can never sit on the segment
+                let d = EDGE_MIDPOINTS[b] - EDGE_MIDPOINTS[a];
+                let to_corner = CELL_CORNERS[corner] - EDGE_MIDPOINTS[a];
+                let corner_left = d.perp_dot(to_corner) > 0.0;
+                if corner_left != solid[corner] {
+                    std::mem::swap(&mut start, &mut end);
+                }
+
+                next.insert(start.0, end.0);
+                positions.insert(start.0, start.1);
+                positions.insert(end.0, end.1);
+            }
+        }
+    }
+
+    let mut loops = Vec::new();
+    while let Some(&first) = next.keys().next() {
+        let mut points = Vec::new();
+        let mut key = first;
+        while let Some(following) = next.remove(&key) {
+            points.push(positions[&key]);
+            key = following;
+        }
+        if points.len() > 2 {
+            loops.push(points);
+        }
+    }
+
+    loops
+}
+
+// Shoelace area, positive when the interior is on the left of the direction of travel
+pub fn signed_area(points: &[Vec2]) -> f32 {
+    let n = points.len();
+    (0..n)
+        .map(|i| points[i].perp_dot(points[(i + 1) % n]))
+        .sum::<f32>()
+        * 0.5
+}
+
+/// Douglas-Peucker on a closed loop. The loop is split at the point furthest from the
+/// first one so both halves are open polylines.
+pub fn simplify_closed(points: &[Vec2], tolerance: f32) -> Vec<Vec2> {
+    if points.len() < 4 || tolerance <= 0.0 {
+        return points.to_vec();
+    }
+
+    let (split, _) = points
+        .iter()
+        .enumerate()
+        .map(|(i, p)| (i, p.distance_squared(points[0])))
+        .fold((0, 0.0), |best, c| if c.1 > best.1 { c } else { best });
+
+    let mut second_half: Vec<Vec2> = points[split..].to_vec();
+    second_half.push(points[0]);
+
+    let mut simplified = simplify_open(&points[..=split], tolerance);
+    simplified.pop();
+    simplified.extend(simplify_open(&second_half, tolerance));
+    simplified.pop();
+    simplified
+}
+
+/// Douglas-Peucker on an open polyline, the end points are always kept
+pub fn simplify_open(points: &[Vec2], tolerance: f32) -> Vec<Vec2> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+
+    let mut stack = vec![(0, points.len() - 1)];
+    while let Some((first, last)) = stack.pop() {
+        let (a, b) = (points[first], points[last]);
+
+        let mut furthest = (first, 0.0);
+        for (i, p) in points.iter().enumerate().take(last).skip(first + 1) {
+            let d = distance_to_segment(*p, a, b);
+            if d > furthest.1 {
+                furthest = (i, d);
+            }
+        }
+
+        if furthest.1 > tolerance {
+            keep[furthest.0] = true;
+            stack.push((first, furthest.0));
+            stack.push((furthest.0, last));
+        }
+    }
+
+    points
+        .iter()
+        .zip(keep)
+        .filter_map(|(p, k)| k.then_some(*p))
+        .collect()
+}
+
+fn distance_to_segment(p: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let ab = b - a;
+    let len_sq = ab.length_squared();
+    if len_sq == 0.0 {
+        return p.distance(a);
+    }
+    let t = ((p - a).dot(ab) / len_sq).clamp(0.0, 1.0);
+    p.distance(a + ab * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(rows: &[&[f32]]) -> (Vec<f32>, usize, usize) {
+        (rows.concat(), rows[0].len(), rows.len())
+    }
+
+    #[test]
+    fn a_single_solid_texel_gets_a_closed_ring() {
+        let (values, width, height) = grid(&[&[0., 0., 0.], &[0., 1., 0.], &[0., 0., 0.]]);
+        let loops = marching_squares(&values, width, height, 0.5);
+        assert_eq!(loops.len(), 1);
+
+        // a diamond through the edge midpoints of the texel, islands wind positive
+        let ring = &loops[0];
+        assert_eq!(ring.len(), 4);
+        assert!((signed_area(ring) - 0.5).abs() < 1e-6);
+        let centre = Vec2::splat(1.5);
+        for (i, p) in ring.iter().enumerate() {
+            assert!((p.distance(centre) - 0.5).abs() < 1e-6);
+            let next = ring[(i + 1) % ring.len()];
+            assert!((p.distance(next) - 0.5f32.sqrt()).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn a_ring_of_rock_has_an_island_and_a_cave() {
+        let (values, width, height) = grid(&[
+            &[0., 0., 0., 0., 0.],
+            &[0., 1., 1., 1., 0.],
+            &[0., 1., 0., 1., 0.],
+            &[0., 1., 1., 1., 0.],
+            &[0., 0., 0., 0., 0.],
+        ]);
+        let mut areas = marching_squares(&values, width, height, 0.5)
+            .iter()
+            .map(|l| signed_area(l))
+            .collect::<Vec<_>>();
+        areas.sort_by(f32::total_cmp);
+
+        assert_eq!(areas.len(), 2);
+        assert!(areas[0] < 0.0, "the hole should wind as a cave");
+        assert!(areas[1] > -areas[0], "the outline should wind as an island around it");
+    }
+
+    #[test]
+    fn saddles_join_when_the_centre_is_solid() {
+        for diagonal in [[1., 0., 0., 1.], [0., 1., 1., 0.]] {
+            let (values, width, height) = grid(&[&diagonal[..2], &diagonal[2..]]);
+
+            // the centre averages 0.5, so it's solid at 0.5 and empty above it
+            let joined = marching_squares(&values, width, height, 0.5);
+            let split = marching_squares(&values, width, height, 0.6);
+
+            assert_eq!(joined.len(), 1, "{:?} at 0.5", diagonal);
+            assert_eq!(split.len(), 2, "{:?} at 0.6", diagonal);
+            for ring in joined.iter().chain(&split) {
+                assert!(signed_area(ring) > 0.0);
+            }
+            // joined, the two texels share one outline bigger than both apart
+            let joined_area = signed_area(&joined[0]);
+            let split_area = split.iter().map(|l| signed_area(l)).sum::<f32>();
+            assert!(joined_area > split_area);
+        }
+    }
+
+    #[test]
+    fn simplifying_drops_collinear_points_and_keeps_corners() {
+        // a 4x4 square with a point every texel along its sides, one nudged off the line by
+        // less than the tolerance
+        let mut square = Vec::new();
+        for i in 0..4 {
+            square.push(Vec2::new(i as f32, 0.0));
+        }
+        for i in 0..4 {
+            square.push(Vec2::new(4.0, i as f32));
+        }
+        for i in 0..4 {
+            square.push(Vec2::new(4.0 - i as f32, 4.0));
+        }
+        for i in 0..4 {
+            square.push(Vec2::new(0.0, 4.0 - i as f32));
+        }
+        square[2].y = 0.05;
+
+        let corners = [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(4.0, 0.0),
+            Vec2::new(4.0, 4.0),
+            Vec2::new(0.0, 4.0),
+        ];
+        assert_eq!(simplify_closed(&square, 0.1), corners);
+        // nothing goes with no tolerance, and below its offset the nudged point stays
+        assert_eq!(simplify_closed(&square, 0.0), square);
+        let fine = simplify_closed(&square, 0.01);
+        assert!(corners.iter().chain([&square[2]]).all(|p| fine.contains(p)));
+        assert!(fine.len() < square.len());
+
+        let corner = [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(1.0, 0.0),
+            Vec2::new(2.0, 0.0),
+            Vec2::new(2.0, 1.0),
+            Vec2::new(2.0, 2.0),
+        ];
+        assert_eq!(
+            simplify_open(&corner, 0.1),
+            [corner[0], corner[2], corner[4]]
+        );
+    }
+}
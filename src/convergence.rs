@@ -0,0 +1,192 @@
+use bevy::{
+    prelude::*,
+    render::{
+        gpu_readback::{Readback, ReadbackComplete},
+        render_asset::RenderAssets,
+        renderer::RenderQueue,
+        storage::GpuShaderStorageBuffer,
+        Render, RenderApp, RenderSet,
+    },
+};
+
+use crate::{
+    constants::MAX_CONVERGENCE_ITERATIONS, parameters::ViewParams, ImageBufferContainer,
+    ParamsChanged, ShaderConfigHolder,
+};
+
+// The changed texel counts of every iteration of every stage for the live chain, then the same
+// again for the comparison chain so the two don't stop each other
+pub fn convergence_buffer_size(stages: usize) -> usize {
+    2 * stages * MAX_CONVERGENCE_ITERATIONS * std::mem::size_of::<u32>()
+}
+
+// How far a stage that counts its changes got in the last run
+#[derive(Clone, Debug)]
+pub struct StageConvergence {
+    pub stage: usize,
+    // texels changed by each iteration the view let run
+    pub changes: Vec<u32>,
+    // iterations that ran before the change count fell below the threshold, or all of them
+    pub ran: u32,
+}
+
+impl StageConvergence {
+    pub fn stopped_early(&self) -> bool {
+        (self.ran as usize) < self.changes.len()
+    }
+
+    // the changes of the last iteration that did any work
+    pub fn last_changes(&self) -> Option<u32> {
+        self.changes.get(self.ran.checked_sub(1)? as usize).copied()
+    }
+}
+
+// The number of iterations a stage ran given what each one changed, the same test as
+// converged() in assets/shaders/utils/pass.wgsl
+pub fn iterations_run(changes: &[u32], converge_below: u32) -> u32 {
+    if converge_below == 0 {
+        return changes.len() as u32;
+    }
+    changes
+        .iter()
+        .position(|c| *c < converge_below)
+        .map_or(changes.len(), |i| i + 1) as u32
+}
+
+#[derive(Resource, Default)]
+pub struct ConvergenceReport {
+    pub stages: Vec<StageConvergence>,
+}
+
+impl ConvergenceReport {
+    pub fn stage(&self, stage: usize) -> Option<&StageConvergence> {
+        self.stages.iter().find(|s| s.stage == stage)
+    }
+}
+
+pub struct ConvergencePlugin;
+
+impl Plugin for ConvergencePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ConvergenceReport>();
+        app.add_systems(PostUpdate, request_convergence_readback);
+    }
+
+    fn finish(&self, app: &mut App) {
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app.add_systems(
+            Render,
+            reset_convergence.in_set(RenderSet::PrepareResources),
+        );
+    }
+}
+
+fn request_convergence_readback(
+    mut commands: Commands,
+    changed: Res<ParamsChanged>,
+    buffers: Option<Res<ImageBufferContainer>>,
+) {
+    let Some(buffers) = buffers else {
+        return;
+    };
+
+    if !changed.is_changed() {
+        return;
+    }
+
+    commands
+        .spawn(Readback::buffer(buffers.convergence.clone()))
+        .observe(
+            |trigger: Trigger<ReadbackComplete>,
+             mut commands: Commands,
+             configs: Res<ShaderConfigHolder>,
+             view: Res<ViewParams>,
+             mut report: ResMut<ConvergenceReport>| {
+                let raw: Vec<u32> = bytemuck::pod_collect_to_vec(&trigger.event().0);
+                report.stages = configs
+                    .shader_configs
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(stage, config)| {
+                        let converge_below = config.converge_below?;
+                        let iterations = view
+                            .stage_iterations(stage, config.iterations)
+                            .min(MAX_CONVERGENCE_ITERATIONS as u32);
+                        let start = stage * MAX_CONVERGENCE_ITERATIONS;
+                        let changes = raw.get(start..start + iterations as usize)?.to_vec();
+                        Some(StageConvergence {
+                            stage,
+                            ran: iterations_run(&changes, converge_below),
+                            changes,
+                        })
+                    })
+                    .collect();
+                commands.entity(trigger.entity()).despawn();
+            },
+        );
+}
+
+// Zero the counts before the chain runs, a stage cache hit copies the counts of the run it
+// stored over them
+fn reset_convergence(
+    changed: Res<ParamsChanged>,
+    textures: Option<Res<ImageBufferContainer>>,
+    buffers: Res<RenderAssets<GpuShaderStorageBuffer>>,
+    render_queue: Res<RenderQueue>,
+) {
+    if !changed.0 {
+        return;
+    }
+    let Some(buffer) = textures.and_then(|t| buffers.get(&t.convergence)) else {
+        return;
+    };
+
+    render_queue.write_buffer(&buffer.buffer, 0, &vec![0u8; buffer.buffer.size() as usize]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        compute_plugin::default_shader_configs,
+        cpu_reference::{generate_rock_with, view_through, CpuPipeline, CpuStage},
+        data_structures::ShaderConfig,
+        parameters::ParamsUniform,
+    };
+
+    #[test]
+    fn a_converged_ca_holds_where_the_report_says_it_stopped() {
+        let params = ParamsUniform {
+            dimensions: 64,
+            ca_search_radius: 0.5,
+            ..Default::default()
+        };
+        let mut configs = default_shader_configs();
+        let stage = configs.iter().position(|c| c.name() == "ca_run").unwrap();
+        let run = |configs: &[ShaderConfig]| {
+            let mut cpu = CpuPipeline::new(params);
+            cpu.run(configs, &view_through(configs, CpuStage::SubtractCaves)).unwrap();
+            cpu.changes[stage].clone()
+        };
+
+        // the caves settle, a threshold between the first and last counts stops them partway
+        let all = run(&configs);
+        assert_eq!(all.len(), configs[stage].iterations as usize);
+        assert_eq!(iterations_run(&all, 0), configs[stage].iterations);
+        let threshold = (all[0] + all[all.len() - 1]) / 2;
+        let ran = iterations_run(&all, threshold);
+        assert!(ran > 1 && ran < configs[stage].iterations, "{:?} never crossed {}", all, threshold);
+
+        configs[stage].converge_below = Some(threshold);
+        let held = run(&configs);
+        assert_eq!(iterations_run(&held, threshold), ran);
+        assert_eq!(held[..ran as usize], all[..ran as usize]);
+        assert!(held[ran as usize..].iter().all(|c| *c == 0), "{:?}", held);
+
+        // and the caves are the ones the stage had after the iterations the report counts
+        let rock = generate_rock_with(params, &configs).unwrap().channel(0);
+        configs[stage].converge_below = Some(0);
+        configs[stage].iterations = ran;
+        assert_eq!(rock, generate_rock_with(params, &configs).unwrap().channel(0));
+    }
+}
@@ -0,0 +1,1443 @@
+use std::{
+    f32::consts::{PI, TAU},
+    path::Path,
+    time::Instant,
+};
+
+use bevy::{
+    prelude::*,
+    tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task},
+};
+
+use crate::{
+    ca_rules::Neighbourhood,
+    compute_plugin::default_shader_configs,
+    constants::*,
+    contours::RockMask,
+    data_structures::ShaderConfig,
+    parameters::{ParamsUniform, ViewChannel, ViewParams, ViewSource},
+    Gradients, ShaderConfigHolder,
+};
+
+/*
+Pure Rust versions of the generation stages, for machines without a GPU and as an oracle to
+check the shaders against. Each stage follows its shader line for line on the same
+ParamsUniform, and the three textures ping-pong between two sides exactly as the compute nodes
+swap their bind groups, so a stage that doesn't write a texture leaves the same stale copy on
+the other side. The only difference is that everything starts from zero where the GPU buffers
+keep whatever the last frame left in them.
+
+The GPU rounds some functions differently, sin in hash23 especially, so compare with a
+tolerance and expect the odd texel to differ.
+*/
+
+// these need to match the GRID_ and STRIP_ constants in assets/shaders/common.wgsl
+const GRID_DIST_TO_CENTER: usize = 0;
+const GRID_DIST_TO_EDGE: usize = 1;
+const GRID_NORMALIZED_DIST_TO_EDGE: usize = 2;
+const GRID_DEFORMED_RADIUS: usize = 3;
+const GRID_PLANET_MASK: usize = 4;
+const GRID_EDIT_PASS: usize = 1;
+const STRIP_HEIGHT: usize = 0;
+const STRIP_WATER: usize = 1;
+const STRIP_SEDIMENT: usize = 2;
+const STRIP_EROSION_PASS: usize = 0;
+
+// these need to match the SEED_ constants in assets/shaders/common.wgsl
+const SEED_HEIGHTS: u32 = 1;
+const SEED_WARP_1: u32 = 2;
+const SEED_WARP_2: u32 = 3;
+const SEED_CAVES: u32 = 4;
+
+// The stages with a CPU version, named after their shaders
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CpuStage {
+    GenerateHeights,
+    ErodeThermal,
+    ErodeHydraulic,
+    GenerateCircle,
+    DomainWarp1,
+    CaPrepare,
+    CaRun,
+    DomainWarp2,
+    SubtractCaves,
+}
+
+impl CpuStage {
+    pub const ALL: [CpuStage; 9] = [
+        CpuStage::GenerateHeights,
+        CpuStage::ErodeThermal,
+        CpuStage::ErodeHydraulic,
+        CpuStage::GenerateCircle,
+        CpuStage::DomainWarp1,
+        CpuStage::CaPrepare,
+        CpuStage::CaRun,
+        CpuStage::DomainWarp2,
+        CpuStage::SubtractCaves,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CpuStage::GenerateHeights => "init_generate_heights",
+            CpuStage::ErodeThermal => "erode_thermal",
+            CpuStage::ErodeHydraulic => "erode_hydraulic",
+            CpuStage::GenerateCircle => "init_generate_circle",
+            CpuStage::DomainWarp1 => "domain_warp_1",
+            CpuStage::CaPrepare => "ca_prepare",
+            CpuStage::CaRun => "ca_run",
+            CpuStage::DomainWarp2 => "domain_warp_2",
+            CpuStage::SubtractCaves => "subtract_caves",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<CpuStage> {
+        CpuStage::ALL.into_iter().find(|s| s.name() == name)
+    }
+}
+
+// An rgba32float texture, row major like the rock mask
+#[derive(Clone)]
+pub struct Texture {
+    pub size: usize,
+    pub texels: Vec<Vec4>,
+}
+
+impl Texture {
+    fn new(size: usize) -> Self {
+        Self {
+            size,
+            texels: vec![Vec4::ZERO; size * size],
+        }
+    }
+
+    // out of range loads are clamped to the edge
+    fn load(&self, p: IVec2) -> Vec4 {
+        let max = self.size as i32 - 1;
+        let p = p.clamp(IVec2::ZERO, IVec2::splat(max));
+        self.texels[p.y as usize * self.size + p.x as usize]
+    }
+
+    pub fn channel(&self, c: usize) -> Vec<f32> {
+        self.texels.iter().map(|t| t[c]).collect()
+    }
+}
+
+// DataGrid, row major like the textures rather than [x][y]
+#[derive(Clone)]
+pub struct Grid {
+    pub floats: Vec<[f32; GRID_SIZE]>,
+    pub ints: Vec<[i32; GRID_SIZE]>,
+}
+
+impl Grid {
+    fn new(size: usize) -> Self {
+        Self {
+            floats: vec![[0.0; GRID_SIZE]; size * size],
+            ints: vec![[0; GRID_SIZE]; size * size],
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Strip {
+    pub floats: [Vec<f32>; STRIP_COUNT],
+    pub ints: [Vec<i32>; STRIP_COUNT],
+}
+
+impl Strip {
+    fn new() -> Self {
+        Self {
+            floats: std::array::from_fn(|_| vec![0.0; STRIP_SIZE]),
+            ints: std::array::from_fn(|_| vec![0; STRIP_SIZE]),
+        }
+    }
+}
+
+pub struct CpuPipeline {
+    pub params: ParamsUniform,
+    pub size: usize,
+    // the A and B sides of the three textures
+    sides: [[Texture; 3]; 2],
+    // iterations run so far, picks the side to read like BindGroupSelection
+    iteration: usize,
+    // the stage being run, for its shader defs
+    config: Option<ShaderConfig>,
+    // the texels each iteration of each stage in the last run changed, like the convergence
+    // buffer, empty for the stages that don't count them
+    pub changes: Vec<Vec<u32>>,
+    pub grid_a: Grid,
+    pub grid_b: Grid,
+    pub strip_a: Strip,
+    pub strip_b: Strip,
+}
+
+impl CpuPipeline {
+    // Everything sized by params.dimensions, which can be smaller than BUFFER_LEN here
+    pub fn new(params: ParamsUniform) -> Self {
+        let size = params.dimensions as usize;
+        let textures = || std::array::from_fn(|_| Texture::new(size));
+        Self {
+            params,
+            size,
+            sides: [textures(), textures()],
+            iteration: 0,
+            config: None,
+            changes: Vec::new(),
+            grid_a: Grid::new(size),
+            grid_b: Grid::new(size),
+            strip_a: Strip::new(),
+            strip_b: Strip::new(),
+        }
+    }
+
+    // Run the chain the way the compute nodes do, stopping where the view stops it. Fails at
+    // the first stage with no CPU version that has anything to run.
+    pub fn run(&mut self, configs: &[ShaderConfig], view: &ViewParams) -> Result<(), String> {
+        self.changes = vec![Vec::new(); configs.len()];
+        for (i, config) in configs.iter().enumerate() {
+            let iterations = view.stage_iterations(i, config.iterations);
+            if iterations == 0 {
+                continue;
+            }
+            let Some(stage) = CpuStage::from_name(config.name()) else {
+                return Err(format!("no CPU reference for {}", config.name()));
+            };
+            self.config = Some(config.clone());
+            self.changes[i] = self.run_stage(stage, iterations);
+        }
+        Ok(())
+    }
+
+    // the running stage's shader defs
+    fn has_def(&self, name: &str) -> bool {
+        self.config.as_ref().is_some_and(|c| c.has_def(name))
+    }
+
+    // The texels each iteration changed, if the stage counts them
+    pub fn run_stage(&mut self, stage: CpuStage, iterations: u32) -> Vec<u32> {
+        let converge_below = self.config.as_ref().and_then(|c| c.converge_below).unwrap_or(0);
+        let mut changes: Vec<u32> = Vec::new();
+        for _ in 0..iterations {
+            // converged() in assets/shaders/utils/pass.wgsl
+            let converged = changes.last().is_some_and(|changes| *changes < converge_below);
+            changes.extend(self.run_iteration(stage, converged));
+        }
+        changes
+    }
+
+    // the textures the next stage or the final pass would read
+    pub fn textures(&self) -> &[Texture; 3] {
+        &self.sides[self.iteration % 2]
+    }
+
+    // The texels the iteration changed for stages that count them. A stage that has converged
+    // carries its input through
+    fn run_iteration(&mut self, stage: CpuStage, converged: bool) -> Option<u32> {
+        let read = self.iteration % 2;
+        let write = 1 - read;
+        let mut changes = None;
+
+        match stage {
+            CpuStage::GenerateHeights => self.generate_heights(),
+            CpuStage::ErodeThermal => self.erode_thermal(),
+            CpuStage::ErodeHydraulic => self.erode_hydraulic(),
+            CpuStage::GenerateCircle => {
+                let out = self.generate_circle();
+                self.sides[write][0] = self.texture_from(&out, |t| t.0);
+                for (cell, t) in self.grid_a.floats.iter_mut().zip(&out) {
+                    cell[GRID_DIST_TO_CENTER] = t.1[0];
+                    cell[GRID_DIST_TO_EDGE] = t.1[1];
+                    cell[GRID_NORMALIZED_DIST_TO_EDGE] = t.1[2];
+                    cell[GRID_DEFORMED_RADIUS] = t.1[3];
+                }
+            }
+            CpuStage::DomainWarp1 => {
+                self.sides[write][0] = self.domain_warp(&self.sides[read][0], false);
+            }
+            CpuStage::CaPrepare => {
+                self.sides[write][1] = self.ca_prepare();
+            }
+            CpuStage::CaRun if converged => {
+                self.sides[write][1] = self.sides[read][1].clone();
+                changes = Some(0);
+            }
+            CpuStage::CaRun => {
+                let out = self.ca_run(&self.sides[read][1]);
+                let input = &self.sides[read][1];
+                changes = Some(
+                    out.texels
+                        .iter()
+                        .zip(&input.texels)
+                        .filter(|(a, b)| a.x != b.x)
+                        .count() as u32,
+                );
+                self.sides[write][1] = out;
+            }
+            CpuStage::DomainWarp2 => {
+                self.sides[write][1] = self.domain_warp(&self.sides[read][1], true);
+            }
+            CpuStage::SubtractCaves => {
+                let [rock, caves, _] = &self.sides[read];
+                let mut out = Texture::new(self.size);
+                for (i, texel) in out.texels.iter_mut().enumerate() {
+                    let r = rock.texels[i].x;
+                    self.grid_a.floats[i][GRID_PLANET_MASK] = r;
+                    self.grid_a.ints[i][GRID_EDIT_PASS] = 0;
+                    *texel = Vec4::new((r - caves.texels[i].x).clamp(0.0, 1.0), 0.0, 0.0, 1.0);
+                }
+                // the caves carry through for the jump flood
+                let caves = caves.clone();
+                self.sides[write][0] = out;
+                self.sides[write][1] = caves;
+            }
+        }
+
+        self.iteration += 1;
+        changes
+    }
+
+    fn texture_from<T>(&self, values: &[T], f: impl Fn(&T) -> Vec4) -> Texture {
+        Texture {
+            size: self.size,
+            texels: values.iter().map(f).collect(),
+        }
+    }
+
+    fn planet_pos(&self, x: usize, y: usize) -> Vec2 {
+        let p = &self.params;
+        p.tile_origin + Vec2::new(x as f32, y as f32) / p.dimensions as f32 * p.tile_scale
+    }
+
+    fn planet_texel(&self, x: usize, y: usize) -> IVec2 {
+        let p = &self.params;
+        let origin = p.tile_origin * p.dimensions as f32 / p.tile_scale;
+        IVec2::new(
+            origin.x.round_ties_even() as i32,
+            origin.y.round_ties_even() as i32,
+        ) + IVec2::new(x as i32, y as i32)
+    }
+
+    // init_generate_heights.wgsl
+    fn generate_heights(&mut self) {
+        let p = self.params;
+        let seed = derive_seed(p.seed, SEED_HEIGHTS);
+        for x in 0..STRIP_SIZE {
+            let a = x as f32 / STRIP_SIZE as f32 * 2.0 * PI;
+            let coord = Vec2::new(a.cos(), a.sin());
+
+            let vorro = if self.has_def("USE_VORONOI") {
+                let voro_pos = coord * p.noise_freq * 10.0 + seed_offset(seed, 0);
+                (voro_noise2(voro_pos, 0.5, 0.3) * 2.0 - 1.0).clamp(-1.0, 1.0)
+            } else {
+                0.0
+            };
+
+            let npos = coord * p.noise_freq * 0.1 + seed_offset(seed, 1);
+            let base_settings = Vec4::new(p.noise_lacunarity, 0.5, 10000.0, 0.0);
+            let variation_settings = Vec3::new(p.flatness, p.steepness, p.mix);
+            let terrain = generate_varied_terrain(npos, 8, base_settings, variation_settings) * 5.0;
+
+            self.strip_b.floats[STRIP_HEIGHT][x] = vorro + terrain;
+            self.strip_b.floats[STRIP_WATER][x] = 0.0;
+            self.strip_b.floats[STRIP_SEDIMENT][x] = 0.0;
+            self.strip_a.ints[STRIP_EROSION_PASS][x] = 0;
+        }
+    }
+
+    // copy 0 is strip_b and copy 1 strip_a, as in the erosion shaders
+    fn load_state(&self, copy: i32, i: usize) -> Vec3 {
+        let strip = if copy == 0 { &self.strip_b } else { &self.strip_a };
+        Vec3::new(
+            strip.floats[STRIP_HEIGHT][i],
+            strip.floats[STRIP_WATER][i],
+            strip.floats[STRIP_SEDIMENT][i],
+        )
+    }
+
+    fn store_state(&mut self, copy: i32, i: usize, state: Vec3) {
+        let strip = if copy == 0 { &mut self.strip_b } else { &mut self.strip_a };
+        strip.floats[STRIP_HEIGHT][i] = state.x;
+        strip.floats[STRIP_WATER][i] = state.y;
+        strip.floats[STRIP_SEDIMENT][i] = state.z;
+    }
+
+    // erode_thermal.wgsl
+    fn erode_thermal(&mut self) {
+        const SLIDE_RATE: f32 = 0.25;
+        let p = self.params;
+        let cell = TAU * p.radius / STRIP_SIZE as f32;
+        let talus = p.talus_angle.tan() * cell / (0.02 * p.noise_amplitude).max(0.00001);
+        let slide = |from: f32, to: f32| (from - to - talus).max(0.0) * SLIDE_RATE;
+
+        for x in 0..STRIP_SIZE {
+            let pass_index = self.strip_a.ints[STRIP_EROSION_PASS][x];
+            let copy = pass_index % 2;
+
+            let here = self.load_state(copy, x);
+            let left = self.load_state(copy, wrap(x, -1)).x;
+            let right = self.load_state(copy, wrap(x, 1)).x;
+
+            let out_flow = slide(here.x, left) + slide(here.x, right);
+            let in_flow = slide(left, here.x) + slide(right, here.x);
+
+            self.store_state(1 - copy, x, Vec3::new(here.x - out_flow + in_flow, here.y, here.z));
+            self.strip_a.ints[STRIP_EROSION_PASS][x] = pass_index + 1;
+        }
+    }
+
+    // erode_hydraulic.wgsl
+    fn erode_hydraulic(&mut self) {
+        const ERODE_RATE: f32 = 0.3;
+        const DEPOSIT_RATE: f32 = 0.3;
+        let p = self.params;
+
+        let water_flow = |a: Vec3, b: Vec3| ((a.x + a.y) - (b.x + b.y)) * 0.5;
+        let water_flow = |a: Vec3, b: Vec3| water_flow(a, b).clamp(0.0, a.y * 0.5);
+        let sediment_flow = |a: Vec3, flow: f32| if a.y > 0.0 { a.z * flow / a.y } else { 0.0 };
+
+        for x in 0..STRIP_SIZE {
+            let pass_index = self.strip_a.ints[STRIP_EROSION_PASS][x];
+            let copy = pass_index % 2;
+
+            let rain = Vec3::new(0.0, p.rain, 0.0);
+            let here = self.load_state(copy, x) + rain;
+            let left = self.load_state(copy, wrap(x, -1)) + rain;
+            let right = self.load_state(copy, wrap(x, 1)) + rain;
+
+            let to_left = water_flow(here, left);
+            let to_right = water_flow(here, right);
+            let from_left = water_flow(left, here);
+            let from_right = water_flow(right, here);
+
+            let out_water = to_left + to_right;
+            let mut water = here.y - out_water + from_left + from_right;
+            let mut sediment = here.z - sediment_flow(here, to_left) - sediment_flow(here, to_right)
+                + sediment_flow(left, from_left)
+                + sediment_flow(right, from_right);
+            let mut height = here.x;
+
+            let capacity = p.sediment_capacity * out_water;
+            if sediment > capacity {
+                let deposit = (sediment - capacity) * DEPOSIT_RATE;
+                sediment -= deposit;
+                height += deposit;
+            } else {
+                let erode = (capacity - sediment) * ERODE_RATE;
+                sediment += erode;
+                height -= erode;
+            }
+
+            let evaporated = water * p.evaporation.clamp(0.0, 1.0);
+            let dropped = if water > 0.0 { sediment * evaporated / water } else { 0.0 };
+            water -= evaporated;
+            sediment -= dropped;
+            height += dropped;
+
+            self.store_state(1 - copy, x, Vec3::new(height, water, sediment));
+            self.strip_a.ints[STRIP_EROSION_PASS][x] = pass_index + 1;
+        }
+    }
+
+    // init_generate_circle.wgsl, the texel and the grid channels it writes
+    fn generate_circle(&self) -> Vec<(Vec4, [f32; 4])> {
+        let p = &self.params;
+        par_texels(self.size, |x, y| {
+            let centered = self.planet_pos(x, y) - 0.5;
+            let angle_positive = centered.y.atan2(centered.x) + PI;
+            let index = ((angle_positive / TAU * STRIP_SIZE as f32) as usize).min(STRIP_SIZE - 1);
+
+            let eroded_copy = self.strip_a.ints[STRIP_EROSION_PASS][index] % 2;
+            let nze = if eroded_copy == 1 {
+                self.strip_a.floats[STRIP_HEIGHT][index]
+            } else {
+                self.strip_b.floats[STRIP_HEIGHT][index]
+            };
+
+            let dist_to_center = centered.length();
+            let deformed_radius = p.radius + nze * 0.02 * p.noise_amplitude;
+            let solid = if dist_to_center < deformed_radius { 1.0 } else { 0.0 };
+            let dist_to_edge = deformed_radius - dist_to_center;
+
+            (
+                Vec4::new(solid, 0.0, 0.0, 1.0),
+                [
+                    dist_to_center,
+                    dist_to_edge,
+                    dist_to_edge / deformed_radius,
+                    deformed_radius,
+                ],
+            )
+        })
+    }
+
+    // domain_warp_1.wgsl and domain_warp_2.wgsl, which differ in the first warp and the
+    // texture they warp
+    fn domain_warp(&self, input: &Texture, caves: bool) -> Texture {
+        let p = &self.params;
+        let dim = p.dimensions as f32;
+        let seed = derive_seed(p.seed, if caves { SEED_WARP_2 } else { SEED_WARP_1 });
+        let offset = seed_offset(seed, 0);
+
+        let texels = par_texels(self.size, |x, y| {
+            let pos = self.planet_pos(x, y);
+
+            let (offset1, second_scale, second_amount) = if caves {
+                let scale = noise2(pos * p.domain_warp_2_scale_a + offset);
+                let amount = noise2(Vec2::new(pos.x, p.domain_warp_2_amount_a) + offset);
+                (
+                    apply_domain_warp(pos, scale, amount, offset),
+                    p.domain_warp_2_scale_b,
+                    p.domain_warp_2_amount_b,
+                )
+            } else {
+                (
+                    apply_domain_warp(
+                        pos,
+                        p.domain_warp_1_scale_a,
+                        p.domain_warp_1_amount_a,
+                        Vec2::new(p.misc_f + offset.x, offset.y),
+                    ),
+                    p.domain_warp_1_scale_b,
+                    p.domain_warp_1_amount_b,
+                )
+            };
+            let offset2 = apply_domain_warp(
+                pos + offset1,
+                second_scale,
+                second_amount,
+                Vec2::new(1.234, 5.678) + offset,
+            );
+
+            let mut texels = (offset1 + offset2) * dim / p.tile_scale;
+            if p.tile_warp_limit > 0.0 {
+                texels = texels.clamp(Vec2::splat(-p.tile_warp_limit), Vec2::splat(p.tile_warp_limit));
+            }
+            let sample = IVec2::new(
+                (x as f32 + texels.x).clamp(0.0, dim - 1.0) as i32,
+                (y as f32 + texels.y).clamp(0.0, dim - 1.0) as i32,
+            );
+            input.load(sample)
+        });
+        Texture {
+            size: self.size,
+            texels,
+        }
+    }
+
+    // ca_prepare.wgsl
+    fn ca_prepare(&self) -> Texture {
+        let seed = derive_seed(self.params.seed, SEED_CAVES);
+        let texels = par_texels(self.size, |x, y| {
+            let t = self.planet_texel(x, y);
+            let v = hash_texel(t.x as u32, t.y as u32, seed);
+            let s = if v <= self.params.noise_weight { 1.0 } else { 0.0 };
+            Vec4::new(s, 0.0, 0.0, 1.0)
+        });
+        Texture {
+            size: self.size,
+            texels,
+        }
+    }
+
+    // ca_run.wgsl, with the rule picked by the stage's defs as in src/ca_rules.rs
+    fn ca_run(&self, input: &Texture) -> Texture {
+        if self.has_def("CA_RULE_CLASSIC") || self.has_def("CA_RULE_SMOOTH") {
+            self.ca_run_neighbours(input)
+        } else {
+            self.ca_run_weighted(input)
+        }
+    }
+
+    // the classic and smoothing rules, out of bounds is live
+    fn ca_run_neighbours(&self, input: &Texture) -> Texture {
+        let dim = self.params.dimensions as i32;
+        let neighbourhood = if self.has_def("CA_VON_NEUMANN") {
+            Neighbourhood::VonNeumann
+        } else {
+            Neighbourhood::Moore
+        };
+        let classic = self.has_def("CA_RULE_CLASSIC");
+        let is_live = |x: i32, y: i32| {
+            x < 0 || x >= dim || y < 0 || y >= dim || input.load(IVec2::new(x, y)).x >= 0.5
+        };
+
+        let texels = par_texels(self.size, |x, y| {
+            let (x, y) = (x as i32, y as i32);
+            let mut live = 0;
+            for i in -1..=1 {
+                for j in -1..=1 {
+                    if neighbourhood.contains(i, j) && is_live(x + i, y + j) {
+                        live += 1;
+                    }
+                }
+            }
+
+            let here = is_live(x, y);
+            let caves = if classic {
+                let mask = if here {
+                    self.params.ca_survive
+                } else {
+                    self.params.ca_birth
+                };
+                (mask >> live) & 1 == 1
+            } else {
+                (live + here as u32) * 2 > neighbourhood.size() + 1
+            };
+            Vec4::new(if caves { 1.0 } else { 0.0 }, 0.0, 0.0, 0.0)
+        });
+        Texture {
+            size: self.size,
+            texels,
+        }
+    }
+
+    fn ca_run_weighted(&self, input: &Texture) -> Texture {
+        let p = &self.params;
+        let dim = p.dimensions as i32;
+        let radius = p.ca_search_radius * (8.0 / (p.dimensions as f32 / 128.0));
+        let r = radius.ceil() as i32;
+
+        let texels = par_texels(self.size, |x, y| {
+            let mut found = 0.0;
+            let mut count = 0.0;
+            for i in -r..=r {
+                for j in -r..=r {
+                    let dist_sq = (i * i + j * j) as f32;
+                    if dist_sq > radius * radius || (i == 0 && j == 0) {
+                        continue;
+                    }
+                    let (nx, ny) = (x as i32 + i, y as i32 + j);
+                    if nx < 0 || nx >= dim || ny < 0 || ny >= dim {
+                        found += 1.0;
+                        continue;
+                    }
+                    let v = input.load(IVec2::new(nx, ny)).x;
+                    found += v * (1.0 - dist_sq.sqrt() / radius);
+                    count += 1.0;
+                }
+            }
+            let nbs = found / count;
+
+            let edge_dist = self.grid_a.floats[y * self.size + x][GRID_DIST_TO_EDGE];
+            let mut thresh = p.ca_thresh;
+            let weighted = thresh * (1.0 - edge_dist).powf(p.ca_edge_pow);
+            thresh = mix(thresh, weighted, p.edge_suppress_mix);
+            thresh = thresh * (0.31 - 0.14) + 0.14;
+
+            Vec4::new(if nbs > thresh { 1.0 } else { 0.0 }, 0.0, 0.0, 0.0)
+        });
+        Texture {
+            size: self.size,
+            texels,
+        }
+    }
+
+    // extract.wgsl for everything but the materials and lit views
+    pub fn extract(&self, view: &ViewParams, gradients: &Gradients) -> Result<Texture, String> {
+        let source = ViewSource::ALL
+            .into_iter()
+            .find(|s| *s as u32 == view.source)
+            .unwrap_or(ViewSource::Texture1);
+        if matches!(source, ViewSource::Materials | ViewSource::Lit) {
+            return Err(format!("no CPU reference for the {} view", source.label()));
+        }
+
+        let gradient: Vec<Vec3> = gradients
+            .gradient
+            .linear_eval(256, true)
+            .into_iter()
+            .map(|c| Vec3::new(c.r() as f32, c.g() as f32, c.b() as f32) / 255.0)
+            .collect();
+
+        let textures = self.textures();
+        let channel = view.channel;
+        let grid_channel = (view.grid_channel as usize).min(GRID_SIZE - 1);
+
+        let texels = par_texels(self.size, |x, y| {
+            let i = y * self.size + x;
+            let mut single = true;
+            let value = match source {
+                ViewSource::GridAFloats => Vec3::splat(self.grid_a.floats[i][grid_channel]),
+                ViewSource::GridAInts => Vec3::splat(self.grid_a.ints[i][grid_channel] as f32),
+                ViewSource::GridBFloats => Vec3::splat(self.grid_b.floats[i][grid_channel]),
+                ViewSource::GridBInts => Vec3::splat(self.grid_b.ints[i][grid_channel] as f32),
+                _ => {
+                    let texture = match source {
+                        ViewSource::Texture2 => &textures[1],
+                        ViewSource::Texture3 => &textures[2],
+                        _ => &textures[0],
+                    };
+                    let current = texture.texels[i];
+                    match channel {
+                        c if c == ViewChannel::R as u32 => Vec3::splat(current.x),
+                        c if c == ViewChannel::G as u32 => Vec3::splat(current.y),
+                        c if c == ViewChannel::B as u32 => Vec3::splat(current.z),
+                        c if c == ViewChannel::Rgb as u32 => {
+                            single = false;
+                            current.truncate()
+                        }
+                        _ => Vec3::splat(current.w),
+                    }
+                }
+            };
+
+            let range = (view.range_max - view.range_min).max(0.00001);
+            let mut value = ((value - view.range_min) / range).clamp(Vec3::ZERO, Vec3::ONE);
+
+            if view.false_colour != 0 && single {
+                value = gradient[(value.x.clamp(0.0, 1.0) * 255.0) as usize];
+            }
+            value.extend(1.0)
+        });
+
+        Ok(Texture {
+            size: self.size,
+            texels,
+        })
+    }
+}
+
+// A view that runs the chain up to and including the stage
+pub fn view_through(configs: &[ShaderConfig], stage: CpuStage) -> ViewParams {
+    let stop_stage = configs
+        .iter()
+        .position(|c| c.name() == stage.name())
+        .map_or(-1, |i| i as i32);
+    ViewParams {
+        stop_stage,
+        stop_iteration: u32::MAX - 1,
+        ..default()
+    }
+}
+
+// How far apart two outputs are, a texel differs when it's further than the tolerance
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Difference {
+    pub max: f32,
+    pub mean: f32,
+    pub over_tolerance: usize,
+    pub count: usize,
+}
+
+pub fn difference(a: &[f32], b: &[f32], tolerance: f32) -> Difference {
+    let mut result = Difference {
+        count: a.len().min(b.len()),
+        ..default()
+    };
+    let mut sum = 0.0;
+    for (x, y) in a.iter().zip(b) {
+        let d = (x - y).abs();
+        // NaN on either side counts as different
+        if d.is_nan() || d > tolerance {
+            result.over_tolerance += 1;
+        }
+        if d.is_finite() {
+            result.max = result.max.max(d);
+            sum += d as f64;
+        }
+    }
+    result.mean = (sum / result.count.max(1) as f64) as f32;
+    result
+}
+
+// 8 bit greyscale, 0 to 1 mapped to 0 to 255
+pub fn write_pgm(path: &Path, size: usize, values: &[f32]) -> std::io::Result<()> {
+    let mut bytes = format!("P5\n{} {}\n255\n", size, size).into_bytes();
+    bytes.extend(values.iter().map(|v| (v.clamp(0.0, 1.0) * 255.0).round() as u8));
+    std::fs::write(path, bytes)
+}
+
+// Texture 1 once the caves are subtracted, the last stage with a CPU version
+pub fn generate_rock(params: ParamsUniform) -> Result<Texture, String> {
+    generate_rock_with(params, &default_shader_configs())
+}
+
+pub fn generate_rock_with(
+    params: ParamsUniform,
+    configs: &[ShaderConfig],
+) -> Result<Texture, String> {
+    let mut cpu = CpuPipeline::new(params);
+    cpu.run(configs, &view_through(configs, CpuStage::SubtractCaves))?;
+    cpu.extract(&ViewParams::default(), &Gradients::default())
+}
+
+// Generate the planet on the CPU with the default parameters, or a preset over them, and
+// write the rock out
+pub fn write_headless(path: &Path, preset: Option<&Path>) -> Result<(), String> {
+    let mut params = ParamsUniform::default();
+    if let Some(preset) = preset {
+        let text = std::fs::read_to_string(preset).map_err(|e| e.to_string())?;
+        params
+            .apply_preset(&text)
+            .map_err(|e| format!("{}: {}", preset.display(), e))?;
+    }
+
+    let start = Instant::now();
+    let out = generate_rock(params)?;
+
+    write_pgm(path, out.size, &out.channel(0)).map_err(|e| e.to_string())?;
+    // there's no app and so no logger yet
+    println!("wrote {} in {:.1}s", path.display(), start.elapsed().as_secs_f32());
+    Ok(())
+}
+
+// the CPU rock and how long it took
+type CpuRock = Result<(Vec<f32>, f32), String>;
+
+// Checks the GPU rock mask against the CPU reference from the gui
+#[derive(Resource, Default)]
+pub struct CpuCheck {
+    pub requested: bool,
+    task: Option<Task<CpuRock>>,
+    pub result: Option<Result<CpuCheckResult, String>>,
+}
+
+impl CpuCheck {
+    pub fn running(&self) -> bool {
+        self.task.is_some()
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct CpuCheckResult {
+    pub difference: Difference,
+    pub seconds: f32,
+}
+
+pub struct CpuReferencePlugin;
+
+impl Plugin for CpuReferencePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CpuCheck>();
+        app.add_systems(Update, run_cpu_check);
+    }
+}
+
+// the rock mask is written by apply_edits, which with no edits is subtract_caves' rock
+fn run_cpu_check(
+    mut check: ResMut<CpuCheck>,
+    params: Res<ParamsUniform>,
+    configs: Res<ShaderConfigHolder>,
+    mask: Res<RockMask>,
+) {
+    if check.requested && check.task.is_none() {
+        check.requested = false;
+        let params = *params;
+        let configs = configs.shader_configs.clone();
+        check.task = Some(AsyncComputeTaskPool::get().spawn(async move {
+            let start = Instant::now();
+            let mut cpu = CpuPipeline::new(params);
+            cpu.run(&configs, &view_through(&configs, CpuStage::SubtractCaves))?;
+            Ok((cpu.textures()[0].channel(0), start.elapsed().as_secs_f32()))
+        }));
+    }
+
+    let Some(task) = check.task.as_mut() else {
+        return;
+    };
+    let Some(rock) = block_on(future::poll_once(task)) else {
+        return;
+    };
+    check.task = None;
+
+    check.result = Some(rock.and_then(|(rock, seconds)| {
+        if mask.values.len() != rock.len() {
+            return Err("the GPU rock mask hasn't been read back yet".to_string());
+        }
+        Ok(CpuCheckResult {
+            difference: difference(&mask.values, &rock, 0.5),
+            seconds,
+        })
+    }));
+}
+
+// Evaluate f(x, y) for every texel on all the cores, in row major order
+fn par_texels<T: Send + Clone + Default>(size: usize, f: impl Fn(usize, usize) -> T + Sync) -> Vec<T> {
+    let mut out = vec![T::default(); size * size];
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let rows = size.div_ceil(threads).max(1);
+
+    std::thread::scope(|scope| {
+        for (chunk_index, chunk) in out.chunks_mut(rows * size).enumerate() {
+            let f = &f;
+            scope.spawn(move || {
+                for (i, value) in chunk.iter_mut().enumerate() {
+                    let index = chunk_index * rows * size + i;
+                    *value = f(index % size, index / size);
+                }
+            });
+        }
+    });
+    out
+}
+
+fn wrap(i: usize, offset: i32) -> usize {
+    (i as i32 + STRIP_SIZE as i32 + offset) as usize % STRIP_SIZE
+}
+
+// WGSL's builtins where Rust's differ
+
+fn fract(x: f32) -> f32 {
+    x - x.floor()
+}
+
+fn mix(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+// sign(0) is 0 in WGSL
+fn sign(x: f32) -> f32 {
+    if x > 0.0 {
+        1.0
+    } else if x < 0.0 {
+        -1.0
+    } else {
+        0.0
+    }
+}
+
+// assets/shaders/utils/noise.wgsl, the constants are written as the shaders write them
+
+fn mod289(x: f32) -> f32 {
+    x - (x * (1.0 / 289.0)).floor() * 289.0
+}
+
+fn permute3(x: Vec3) -> Vec3 {
+    ((x * 34.0 + 1.0) * x).to_array().map(mod289).into()
+}
+
+#[allow(clippy::excessive_precision)]
+fn noise2(v: Vec2) -> f32 {
+    const C: Vec4 = Vec4::new(
+        0.211324865405187,
+        0.366025403784439,
+        -0.577350269189626,
+        0.024390243902439,
+    );
+
+    let i = (v + (v.x + v.y) * C.y).floor();
+    let x0 = v - i + (i.x + i.y) * C.x;
+
+    let i1 = if x0.x > x0.y { Vec2::new(1.0, 0.0) } else { Vec2::new(0.0, 1.0) };
+    let mut x12 = Vec4::new(x0.x + C.x, x0.y + C.x, x0.x + C.z, x0.y + C.z);
+    x12.x -= i1.x;
+    x12.y -= i1.y;
+
+    let i = Vec2::new(mod289(i.x), mod289(i.y));
+    let p = permute3(
+        permute3(i.y + Vec3::new(0.0, i1.y, 1.0)) + i.x + Vec3::new(0.0, i1.x, 1.0),
+    );
+    let mut m = (0.5
+        - Vec3::new(
+            x0.dot(x0),
+            x12.x * x12.x + x12.y * x12.y,
+            x12.z * x12.z + x12.w * x12.w,
+        ))
+    .max(Vec3::ZERO);
+    m *= m;
+    m *= m;
+
+    let x = (p * C.w).to_array().map(fract);
+    let x = Vec3::from(x) * 2.0 - 1.0;
+    let h = x.abs() - 0.5;
+    let ox = (x + 0.5).floor();
+    let a0 = x - ox;
+
+    m *= 1.79284291400159 - 0.85373472095314 * (a0 * a0 + h * h);
+
+    let g = Vec3::new(
+        a0.x * x0.x + h.x * x0.y,
+        a0.y * x12.x + h.y * x12.y,
+        a0.z * x12.z + h.z * x12.w,
+    );
+    130.0 * m.dot(g)
+}
+
+fn fbm(p1: Vec2) -> f32 {
+    // mat2x2f(vec2f(0.8, 0.6), vec2f(-0.6, 0.8)) times p
+    let m2 = |p: Vec2| Vec2::new(0.8 * p.x - 0.6 * p.y, 0.6 * p.x + 0.8 * p.y);
+    let mut p = p1;
+    let mut f = 0.0;
+    f += 0.5000 * noise2(p);
+    p = m2(p) * 2.02;
+    f += 0.2500 * noise2(p);
+    p = m2(p) * 2.03;
+    f += 0.1250 * noise2(p);
+    p = m2(p) * 2.01;
+    f += 0.0625 * noise2(p);
+    p = m2(p) * 2.02;
+    f += 0.0313 * noise2(p);
+    p = m2(p) * 2.01;
+    f += 0.0156 * noise2(p);
+    f / 0.9844
+}
+
+fn pcg(n: u32) -> u32 {
+    let mut h = n.wrapping_mul(747796405).wrapping_add(2891336453);
+    h = ((h >> ((h >> 28) + 4)) ^ h).wrapping_mul(277803737);
+    (h >> 22) ^ h
+}
+
+fn pcg2d(x: u32, y: u32) -> (u32, u32) {
+    let mut v = (
+        x.wrapping_mul(1664525).wrapping_add(1013904223),
+        y.wrapping_mul(1664525).wrapping_add(1013904223),
+    );
+    for _ in 0..2 {
+        v.0 = v.0.wrapping_add(v.1.wrapping_mul(1664525));
+        v.1 = v.1.wrapping_add(v.0.wrapping_mul(1664525));
+        v = (v.0 ^ (v.0 >> 16), v.1 ^ (v.1 >> 16));
+    }
+    v
+}
+
+fn derive_seed(seed: u32, stage: u32) -> u32 {
+    pcg(seed ^ pcg(stage))
+}
+
+fn seed_offset(seed: u32, salt: u32) -> Vec2 {
+    let (x, y) = pcg2d(seed, salt);
+    Vec2::new((x >> 18) as f32, (y >> 18) as f32) / 256.0
+}
+
+fn hash_texel(x: u32, y: u32, seed: u32) -> f32 {
+    pcg(pcg(x ^ seed) ^ y) as f32 / 0xffffffffu32 as f32
+}
+
+#[allow(clippy::excessive_precision)]
+fn hash23(p: Vec2) -> Vec3 {
+    let q = Vec3::new(
+        p.dot(Vec2::new(127.1, 311.7)),
+        p.dot(Vec2::new(269.5, 183.3)),
+        p.dot(Vec2::new(419.2, 371.9)),
+    );
+    Vec3::from(q.to_array().map(|v| fract(v.sin() * 43758.5453)))
+}
+
+fn voro_noise2(x: Vec2, u: f32, v: f32) -> f32 {
+    let p = x.floor();
+    let f = Vec2::new(fract(x.x), fract(x.y));
+    let k = 1.0 + 63.0 * (1.0 - v).powf(4.0);
+    let mut va = 0.0;
+    let mut wt = 0.0;
+    for j in -2..=2 {
+        for i in -2..=2 {
+            let g = Vec2::new(i as f32, j as f32);
+            let o = hash23(p + g) * Vec3::new(u, u, 1.0);
+            let r = g - f + o.truncate();
+            let d = r.dot(r);
+            let ww = (1.0 - smoothstep(0.0, 1.414, d.sqrt())).powf(k);
+            va += o.z * ww;
+            wt += ww;
+        }
+    }
+    va / wt
+}
+
+// the domain warp shaders' apply_domain_warp
+fn apply_domain_warp(pos: Vec2, scale: f32, amount: f32, offset: Vec2) -> Vec2 {
+    let noise_pos = pos * scale;
+    let noise_x = fbm(noise_pos + Vec2::new(offset.x, 0.0));
+    let noise_y = fbm(noise_pos + Vec2::new(offset.x + 3.33, offset.y + 2.77));
+    Vec2::new(noise_x, noise_y) * amount
+}
+
+// assets/shaders/utils/anal_noise.wgsl
+
+#[allow(clippy::excessive_precision)]
+fn rgrad2(p: Vec2, rot: f32) -> Vec2 {
+    let permute = |x: f32| mod289((x * 34.0 + 10.0) * x);
+    let px = permute(p.x);
+    let u = permute(px + p.y) * 0.0243902439 + rot;
+    let u1 = fract(u) * TAU;
+    Vec2::new(u1.cos(), u1.sin())
+}
+
+fn mod_v3(x: Vec3, y: f32) -> Vec3 {
+    x - y * (x / y).floor()
+}
+
+fn psrdnoise2(pos: Vec2, per: Vec2, rot: f32) -> Vec3 {
+    let pos1 = Vec2::new(pos.x, pos.y + 0.001);
+    let uv = Vec2::new(pos1.x + pos1.y * 0.5, pos1.y);
+
+    let i0 = uv.floor();
+    let f0 = uv - i0;
+    let i1 = if f0.x > f0.y { Vec2::new(1.0, 0.0) } else { Vec2::new(0.0, 1.0) };
+
+    let p0 = Vec2::new(i0.x - i0.y * 0.5, i0.y);
+    let p1 = Vec2::new(p0.x + i1.x - i1.y * 0.5, p0.y + i1.y);
+    let p2 = Vec2::new(p0.x + 0.5, p0.y + 1.0);
+
+    let d0 = pos1 - p0;
+    let d1 = pos1 - p1;
+    let d2 = pos1 - p2;
+
+    let xw = mod_v3(Vec3::new(p0.x, p1.x, p2.x), per.x);
+    let yw = mod_v3(Vec3::new(p0.y, p1.y, p2.y), per.y);
+    let iuw = xw + 0.5 * yw;
+    let ivw = yw;
+
+    let g0 = rgrad2(Vec2::new(iuw.x, ivw.x), rot);
+    let g1 = rgrad2(Vec2::new(iuw.y, ivw.y), rot);
+    let g2 = rgrad2(Vec2::new(iuw.z, ivw.z), rot);
+
+    let w = Vec3::new(g0.dot(d0), g1.dot(d1), g2.dot(d2));
+    let t = Vec3::new(0.8 - d0.dot(d0), 0.8 - d1.dot(d1), 0.8 - d2.dot(d2));
+
+    let dtdx = -2.0 * Vec3::new(d0.x, d1.x, d2.x);
+    let dtdy = -2.0 * Vec3::new(d0.y, d1.y, d2.y);
+
+    let t1 = t.max(Vec3::ZERO);
+    let t2 = t1 * t1;
+    let t4 = t2 * t2;
+    let t3 = t2 * t1;
+
+    let n = t4.dot(w);
+
+    let dn0 = t4.x * g0 + Vec2::new(dtdx.x, dtdy.x) * 4.0 * t3.x * w.x;
+    let dn1 = t4.y * g1 + Vec2::new(dtdx.y, dtdy.y) * 4.0 * t3.y * w.y;
+    let dn2 = t4.z * g2 + Vec2::new(dtdx.z, dtdy.z) * 4.0 * t3.z * w.z;
+    let dn = dn0 + dn1 + dn2;
+
+    11.0 * Vec3::new(n, dn.x, dn.y)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn terrain_advanced(
+    initial_pos: Vec2,
+    octaves: u32,
+    gain: f32,
+    base_period: f32,
+    rot: f32,
+    ridge_offset: f32,
+    warp_strength: f32,
+    erosion_factor: f32,
+) -> f32 {
+    let mut p = initial_pos;
+    let warp = psrdnoise2(p * 0.5, Vec2::splat(base_period), rot);
+    p += Vec2::new(warp.y, warp.z) * warp_strength;
+
+    let mut a = 0.0;
+    let mut b = 1.0;
+    let mut d = Vec2::ZERO;
+
+    let mut freq_weight = 0.0;
+    let mut total_weight = 0.0;
+
+    for i in 0..octaves {
+        let n = psrdnoise2(p, Vec2::splat(base_period), rot);
+
+        let ridge = ridge_offset - n.x.abs();
+        let mixed_noise = mix(n.x, ridge * ridge, 0.5);
+
+        let gradient_factor = 1.0 + d.dot(d);
+        let erosion = 1.0 / (1.0 + erosion_factor * gradient_factor);
+
+        let freq_contribution = 2f32.powf(i as f32);
+        freq_weight += mixed_noise * freq_contribution * erosion;
+        total_weight += freq_contribution * erosion;
+
+        a += b * mixed_noise * erosion;
+        d += Vec2::new(n.y, n.z) * sign(n.x);
+
+        b *= gain;
+        // mat2x2(0.8, -0.6, 0.6, 0.8) times p
+        p = Vec2::new(0.8 * p.x + 0.6 * p.y, -0.6 * p.x + 0.8 * p.y) * 2.0;
+
+        let (sin, cos) = (0.1 * i as f32).sin_cos();
+        p = Vec2::new(cos * p.x + sin * p.y, -sin * p.x + cos * p.y);
+    }
+
+    mix(a, freq_weight / total_weight, 0.3)
+}
+
+// the lacunarity in base_settings.x only scales the octaves that terrain_advanced ignores
+fn generate_varied_terrain(pos: Vec2, octaves: u32, base_settings: Vec4, variation_settings: Vec3) -> f32 {
+    let (b, v) = (base_settings, variation_settings);
+    let base = terrain_advanced(pos, octaves, b.y, b.z, b.w, v.x, v.y, v.z);
+    let large_features = terrain_advanced(
+        pos * 0.5,
+        octaves - 1,
+        b.y,
+        b.z * 2.0,
+        b.w + 1.0,
+        v.x * 1.2,
+        v.y * 0.7,
+        v.z * 0.5,
+    );
+    let details = terrain_advanced(
+        pos * 2.0,
+        octaves - 2,
+        b.y * 0.7,
+        b.z * 0.5,
+        b.w + 2.0,
+        v.x * 0.8,
+        v.y * 1.3,
+        v.z * 1.5,
+    );
+    base * 0.6 + large_features * 0.3 + details * 0.1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // worked out from pcg in utils/noise.wgsl, so the stages and this copy derive the same seeds
+    #[test]
+    fn derive_seed_matches_the_shaders() {
+        assert_eq!(derive_seed(0, 0), 0x30be035e);
+        assert_eq!(derive_seed(0, 1), 0xe92a518a);
+        assert_eq!(derive_seed(1, 0), 0x5e6301b0);
+        assert_eq!(derive_seed(12345, 3), 0xe1339b9d);
+
+        // and the shaders still hash the way these were worked out
+        let noise = std::fs::read_to_string(
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/shaders/utils/noise.wgsl"),
+        )
+        .unwrap();
+        for line in [
+            "var h = n * 747796405u + 2891336453u;",
+            "h = ((h >> ((h >> 28u) + 4u)) ^ h) * 277803737u;",
+            "return (h >> 22u) ^ h;",
+            "fn derive_seed(seed: u32, stage: u32) -> u32 { return pcg(seed ^ pcg(stage)); }",
+        ] {
+            assert!(noise.contains(line), "noise.wgsl no longer has {}", line);
+        }
+    }
+
+    #[test]
+    fn seeds_are_reproducible() {
+        let mut params = ParamsUniform {
+            dimensions: 64,
+            ca_search_radius: 0.5,
+            ..Default::default()
+        };
+        let first = generate_rock(params).unwrap().channel(0);
+        let again = generate_rock(params).unwrap().channel(0);
+        assert!(first.iter().zip(&again).all(|(a, b)| a.to_bits() == b.to_bits()));
+
+        params.seed = 1;
+        let other = generate_rock(params).unwrap().channel(0);
+        assert_ne!(first, other);
+    }
+
+    fn pipeline(params: ParamsUniform, shader_defs: &[&'static str]) -> CpuPipeline {
+        let mut cpu = CpuPipeline::new(params);
+        let mut config = default_shader_configs()[0].clone();
+        config.shader_defs = shader_defs.iter().map(|def| (*def).into()).collect();
+        cpu.config = Some(config);
+        cpu
+    }
+
+    // a texture of live texels, 1 at the listed positions
+    fn live(size: usize, texels: &[(i32, i32)]) -> Texture {
+        let mut texture = Texture::new(size);
+        for (x, y) in texels {
+            texture.texels[*y as usize * size + *x as usize] = Vec4::new(1.0, 0.0, 0.0, 1.0);
+        }
+        texture
+    }
+
+    fn live_texels(texture: &Texture) -> Vec<(i32, i32)> {
+        let size = texture.size as i32;
+        (0..size * size)
+            .filter(|i| texture.texels[*i as usize].x >= 0.5)
+            .map(|i| (i % size, i / size))
+            .collect()
+    }
+
+    #[test]
+    fn heights_without_frequency_are_flat() {
+        let mut cpu = pipeline(ParamsUniform { noise_freq: 0.0, ..default() }, &[]);
+        cpu.strip_b.floats[STRIP_WATER].fill(1.0);
+        cpu.strip_a.ints[STRIP_EROSION_PASS].fill(3);
+        cpu.generate_heights();
+
+        let heights = &cpu.strip_b.floats[STRIP_HEIGHT];
+        assert!(heights.iter().all(|h| *h == heights[0] && h.is_finite()));
+        assert!(cpu.strip_b.floats[STRIP_WATER].iter().all(|w| *w == 0.0));
+        assert!(cpu.strip_b.floats[STRIP_SEDIMENT].iter().all(|s| *s == 0.0));
+        assert!(cpu.strip_a.ints[STRIP_EROSION_PASS].iter().all(|p| *p == 0));
+
+        let mut cpu = pipeline(ParamsUniform::default(), &[]);
+        cpu.generate_heights();
+        let heights = &cpu.strip_b.floats[STRIP_HEIGHT];
+        assert!(heights.iter().any(|h| *h != heights[0]));
+    }
+
+    #[test]
+    fn thermal_erosion_slides_a_spike_onto_its_neighbours() {
+        // a talus angle of 0 lets a quarter of every difference slide each pass
+        let mut cpu = pipeline(ParamsUniform { talus_angle: 0.0, ..default() }, &[]);
+        cpu.strip_b.floats[STRIP_HEIGHT][5] = 1.0;
+        cpu.erode_thermal();
+
+        // pass 0 reads strip_b and writes strip_a
+        let heights = &cpu.strip_a.floats[STRIP_HEIGHT];
+        assert_eq!(heights[3..8], [0.0, 0.25, 0.5, 0.25, 0.0]);
+        assert_eq!(heights.iter().sum::<f32>(), 1.0);
+        assert!(cpu.strip_a.ints[STRIP_EROSION_PASS].iter().all(|p| *p == 1));
+    }
+
+    #[test]
+    fn hydraulic_erosion_spreads_water_and_carries_what_it_erodes() {
+        let params = ParamsUniform {
+            rain: 0.0,
+            evaporation: 0.0,
+            sediment_capacity: 1.0,
+            ..default()
+        };
+        let mut cpu = pipeline(params, &[]);
+        cpu.strip_b.floats[STRIP_WATER][5] = 1.0;
+        cpu.erode_hydraulic();
+
+        // half the water flows each way, and the water leaving picks up 0.3 of its capacity
+        let state = |i| cpu.load_state(1, i);
+        assert_eq!(state(5), Vec3::new(-0.3, 0.0, 0.3));
+        assert_eq!(state(4), Vec3::new(0.0, 0.5, 0.0));
+        assert_eq!(state(6), Vec3::new(0.0, 0.5, 0.0));
+        assert_eq!(state(3), Vec3::ZERO);
+    }
+
+    #[test]
+    fn warps_without_amounts_leave_the_texture_alone() {
+        let size = 32;
+        let params = ParamsUniform {
+            dimensions: size as u32,
+            domain_warp_1_amount_a: 0.0,
+            domain_warp_1_amount_b: 0.0,
+            ..default()
+        };
+        let cpu = pipeline(params, &[]);
+        let mut input = Texture::new(size);
+        for (i, texel) in input.texels.iter_mut().enumerate() {
+            *texel = Vec4::splat(i as f32);
+        }
+        assert_eq!(cpu.domain_warp(&input, false).texels, input.texels);
+    }
+
+    #[test]
+    fn warps_stay_within_the_tile_limit() {
+        let size = 32;
+        let params = ParamsUniform {
+            dimensions: size as u32,
+            domain_warp_1_amount_a: 1.0,
+            domain_warp_1_amount_b: 1.0,
+            domain_warp_2_amount_b: 1.0,
+            tile_warp_limit: 1.0,
+            ..default()
+        };
+        let cpu = pipeline(params, &[]);
+        let mut input = Texture::new(size);
+        for (i, texel) in input.texels.iter_mut().enumerate() {
+            *texel = Vec4::splat(i as f32);
+        }
+
+        for caves in [false, true] {
+            let out = cpu.domain_warp(&input, caves);
+            let mut moved = 0;
+            for (i, texel) in out.texels.iter().enumerate() {
+                let from = texel.x as usize;
+                let dx = (from % size) as i32 - (i % size) as i32;
+                let dy = (from / size) as i32 - (i / size) as i32;
+                assert!(dx.abs() <= 1 && dy.abs() <= 1, "texel {} came from {}", i, from);
+                moved += (from != i) as usize;
+            }
+            assert!(moved > 0, "warp {} didn't move anything", caves as u32 + 1);
+        }
+    }
+
+    #[test]
+    fn weighted_ca_weighs_neighbours_by_distance() {
+        // a radius of 2 texels sees 12 texels, the four at 1 weigh a half and the four at √2
+        // 0.29. With a threshold of 0 the cut off is 0.14, so four edge neighbours make a cave
+        // and three don't
+        let params = ParamsUniform {
+            dimensions: 128,
+            ca_search_radius: 0.25,
+            ca_thresh: 0.0,
+            ..default()
+        };
+        let cpu = pipeline(params, &[]);
+        let four = live(128, &[(63, 64), (65, 64), (64, 63), (64, 65)]);
+        assert!(cpu.ca_run_weighted(&four).load(IVec2::new(64, 64)).x == 1.0);
+        let three = live(128, &[(63, 64), (65, 64), (64, 63)]);
+        assert!(cpu.ca_run_weighted(&three).load(IVec2::new(64, 64)).x == 0.0);
+
+        // past the edge counts as live and isn't averaged
+        let empty = cpu.ca_run_weighted(&Texture::new(128));
+        assert_eq!(empty.load(IVec2::new(0, 0)).x, 1.0);
+        assert_eq!(empty.load(IVec2::new(64, 64)).x, 0.0);
+    }
+
+    #[test]
+    fn classic_ca_runs_life() {
+        let (birth, survive) = crate::ca_rules::parse_rulestring("B3/S23").unwrap();
+        let params = ParamsUniform {
+            dimensions: 16,
+            ca_birth: birth,
+            ca_survive: survive,
+            ..default()
+        };
+        let cpu = pipeline(params, &["CA_RULE_CLASSIC"]);
+
+        // a blinker turns on its side and back, the edge is live so the border fills in too
+        let blinker = live(16, &[(7, 8), (8, 8), (9, 8)]);
+        let inside = |texture: &Texture| {
+            live_texels(texture)
+                .into_iter()
+                .filter(|(x, y)| (2..14).contains(x) && (2..14).contains(y))
+                .collect::<Vec<_>>()
+        };
+        let turned = cpu.ca_run_neighbours(&blinker);
+        assert_eq!(inside(&turned), vec![(8, 7), (8, 8), (8, 9)]);
+        assert_eq!(inside(&cpu.ca_run_neighbours(&turned)), inside(&blinker));
+    }
+
+    #[test]
+    fn smoothing_ca_takes_the_majority() {
+        let cpu = pipeline(ParamsUniform { dimensions: 16, ..default() }, &["CA_RULE_SMOOTH"]);
+        let block: Vec<_> = (7..10).flat_map(|x| (7..10).map(move |y| (x, y))).collect();
+        let out = cpu.ca_run_neighbours(&live(16, &block));
+        let at = |x, y| out.load(IVec2::new(x, y)).x;
+
+        // the centre and edges of the block have a majority of 9, the corners 4 of 9
+        assert_eq!(at(8, 8), 1.0);
+        assert_eq!(at(8, 7), 1.0);
+        assert_eq!(at(7, 7), 0.0);
+        assert_eq!(at(4, 4), 0.0);
+
+        // four of five with von neumann
+        let cpu = pipeline(
+            ParamsUniform { dimensions: 16, ..default() },
+            &["CA_RULE_SMOOTH", "CA_VON_NEUMANN"],
+        );
+        let out = cpu.ca_run_neighbours(&live(16, &block));
+        assert_eq!(out.load(IVec2::new(7, 7)).x, 1.0);
+        assert_eq!(out.load(IVec2::new(6, 8)).x, 0.0);
+    }
+
+    #[test]
+    fn subtracting_caves_clamps_and_carries_them_through() {
+        let mut cpu = pipeline(ParamsUniform { dimensions: 2, ..default() }, &[]);
+        let rock = [1.0, 1.0, 0.2, 0.0];
+        let caves = [0.0, 0.25, 1.0, 0.5];
+        for i in 0..4 {
+            cpu.sides[0][0].texels[i].x = rock[i];
+            cpu.sides[0][1].texels[i].x = caves[i];
+        }
+        cpu.run_iteration(CpuStage::SubtractCaves, false);
+
+        let [out, carried, _] = cpu.textures();
+        assert_eq!(out.channel(0), vec![1.0, 0.75, 0.0, 0.0]);
+        assert_eq!(carried.channel(0), caves.to_vec());
+        let mask: Vec<f32> = cpu.grid_a.floats.iter().map(|g| g[GRID_PLANET_MASK]).collect();
+        assert_eq!(mask, rock.to_vec());
+    }
+}
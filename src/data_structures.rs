@@ -17,6 +17,69 @@ pub struct ShaderConfig {
     pub shader_path: &'static str,
     pub shader_mode: ComputeNodeMode,
     pub iterations: u32,
+    // the variant of the shader this stage runs, the same file can be in the chain more than once
+    // with different defs
+    pub shader_defs: Vec<ShaderDefVal>,
+    // for stages that count their changes with compute::pass::count_change, carry the texels
+    // through once an iteration changes fewer than this, the remaining iterations are still
+    // dispatched. 0 only reports when the stage settled
+    pub converge_below: Option<u32>,
+}
+
+impl ShaderConfig {
+    // file stem of the shader, used to label the stage in the gui
+    pub fn name(&self) -> &'static str {
+        let file = self.shader_path.rsplit('/').next().unwrap_or(self.shader_path);
+        file.strip_suffix(".wgsl").unwrap_or(file)
+    }
+
+    // whether #ifdef sees the def
+    pub fn has_def(&self, name: &str) -> bool {
+        self.shader_defs.iter().any(|def| match def {
+            ShaderDefVal::Bool(n, value) => n == name && *value,
+            ShaderDefVal::Int(n, _) | ShaderDefVal::UInt(n, _) => n == name,
+        })
+    }
+}
+
+// Defs as they're typed in the gui, comma separated NAME, NAME=false, NAME=-3 or NAME=3u
+pub fn parse_shader_defs(text: &str) -> Result<Vec<ShaderDefVal>, String> {
+    text.split(',')
+        .map(str::trim)
+        .filter(|def| !def.is_empty())
+        .map(|def| {
+            let (name, value) = match def.split_once('=') {
+                Some((name, value)) => (name.trim(), Some(value.trim())),
+                None => (def, None),
+            };
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(format!("'{}' isn't a def name", name));
+            }
+
+            let name = name.to_string();
+            match value {
+                None | Some("true") => Ok(ShaderDefVal::Bool(name, true)),
+                Some("false") => Ok(ShaderDefVal::Bool(name, false)),
+                Some(value) => match value.strip_suffix('u') {
+                    Some(value) => value.parse().map(|v| ShaderDefVal::UInt(name, v)),
+                    None => value.parse().map(|v| ShaderDefVal::Int(name, v)),
+                }
+                .map_err(|_| format!("'{}' isn't a bool or an integer", value)),
+            }
+        })
+        .collect()
+}
+
+pub fn format_shader_defs(defs: &[ShaderDefVal]) -> String {
+    defs.iter()
+        .map(|def| match def {
+            ShaderDefVal::Bool(name, true) => name.clone(),
+            ShaderDefVal::Bool(name, false) => format!("{}=false", name),
+            ShaderDefVal::Int(name, value) => format!("{}={}", name, value),
+            ShaderDefVal::UInt(name, value) => format!("{}={}u", name, value),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
 }
 
 #[derive(Copy, Clone, Pod, Zeroable, ShaderType)]
@@ -0,0 +1,597 @@
+use std::hash::{Hash, Hasher};
+
+use bevy::{
+    ecs::system::SystemParam,
+    prelude::*,
+    render::{
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        render_asset::RenderAssets,
+        renderer::RenderQueue,
+        storage::GpuShaderStorageBuffer,
+        Render, RenderApp, RenderSet,
+    },
+    window::PrimaryWindow,
+};
+use bevy_egui::EguiContexts;
+use bytemuck::{Pod, Zeroable};
+
+use crate::{
+    chunks::ChunkSettings, compare::ResultSprite, constants::*, contours::sprite_to_texel, parameters::ParamsUniform,
+    ImageBufferContainer, ParamsChanged, ShaderConfigHolder,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum EditOp {
+    Add,
+    Subtract,
+    // blend towards the average of the neighbouring texels
+    Smooth,
+}
+
+// positions are in texels of the result texture
+#[derive(Clone, PartialEq, Debug)]
+pub enum Brush {
+    Circle { centre: Vec2, radius: f32 },
+    Polygon(Vec<Vec2>),
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct TerrainEdit {
+    pub brush: Brush,
+    pub op: EditOp,
+    // 0 leaves the rock alone, 1 applies the edit fully
+    pub strength: f32,
+}
+
+impl TerrainEdit {
+    pub fn circle(centre: Vec2, radius: f32, op: EditOp) -> Self {
+        Self {
+            brush: Brush::Circle { centre, radius },
+            op,
+            strength: 1.0,
+        }
+    }
+
+    pub fn polygon(points: Vec<Vec2>, op: EditOp) -> Self {
+        Self {
+            brush: Brush::Polygon(points),
+            op,
+            strength: 1.0,
+        }
+    }
+
+    pub fn with_strength(mut self, strength: f32) -> Self {
+        self.strength = strength;
+        self
+    }
+
+    pub fn bounds(&self) -> Rect {
+        match &self.brush {
+            Brush::Circle { centre, radius } => {
+                Rect::from_center_half_size(*centre, Vec2::splat(*radius))
+            }
+            Brush::Polygon(points) => points.iter().fold(
+                Rect {
+                    min: Vec2::MAX,
+                    max: Vec2::MIN,
+                },
+                |rect, p| rect.union_point(*p),
+            ),
+        }
+    }
+
+    fn vertex_count(&self) -> usize {
+        match &self.brush {
+            Brush::Circle { .. } => 0,
+            Brush::Polygon(points) => points.len(),
+        }
+    }
+}
+
+// Sent whenever the terrain changes, region is in texels and None means everything
+#[derive(Event, Clone, Copy, Debug)]
+pub struct TerrainChanged {
+    pub region: Option<Rect>,
+}
+
+// Every edit made so far, replayed over the generated rock by apply_edits each time the
+// chain runs so the edits survive changes to the upstream stages
+#[derive(Resource, ExtractResource, Clone, Default)]
+pub struct TerrainEdits {
+    edits: Vec<TerrainEdit>,
+    vertex_count: usize,
+    // bumped on every change so the render world knows when to upload
+    revision: u32,
+    pending_regions: Vec<Option<Rect>>,
+}
+
+impl TerrainEdits {
+    // Returns false when the edit buffer is full
+    pub fn push(&mut self, edit: TerrainEdit) -> bool {
+        if self.edits.len() >= MAX_EDITS
+            || self.vertex_count + edit.vertex_count() > MAX_EDIT_VERTICES
+        {
+            return false;
+        }
+
+        self.vertex_count += edit.vertex_count();
+        self.pending_regions.push(Some(edit.bounds()));
+        self.edits.push(edit);
+        self.revision = self.revision.wrapping_add(1);
+        true
+    }
+
+    pub fn undo(&mut self) -> Option<TerrainEdit> {
+        let edit = self.edits.pop()?;
+        self.vertex_count -= edit.vertex_count();
+        self.pending_regions.push(Some(edit.bounds()));
+        self.revision = self.revision.wrapping_add(1);
+        Some(edit)
+    }
+
+    pub fn clear(&mut self) {
+        self.pending_regions.extend(self.edits.iter().map(|e| Some(e.bounds())));
+        self.edits.clear();
+        self.vertex_count = 0;
+        self.revision = self.revision.wrapping_add(1);
+    }
+
+    pub fn edits(&self) -> &[TerrainEdit] {
+        &self.edits
+    }
+
+    // Ranges of edits applied by each pass of apply_edits. Smooth edits read their
+    // neighbours, so they can't share a pass with anything that comes before them.
+    pub fn batches(&self) -> Vec<[u32; 2]> {
+        let mut batches: Vec<[u32; 2]> = Vec::new();
+        for (i, edit) in self.edits.iter().enumerate() {
+            let i = i as u32;
+            match batches.last_mut() {
+                Some(batch) if edit.op != EditOp::Smooth => batch[1] = i + 1,
+                _ => batches.push([i, i + 1]),
+            }
+        }
+        batches
+    }
+
+    pub fn hash_into<H: Hasher>(&self, hasher: &mut H) {
+        for edit in &self.edits {
+            edit.op.hash(hasher);
+            edit.strength.to_bits().hash(hasher);
+            match &edit.brush {
+                Brush::Circle { centre, radius } => {
+                    [centre.x, centre.y, *radius].map(f32::to_bits).hash(hasher);
+                }
+                Brush::Polygon(points) => {
+                    for p in points {
+                        [p.x, p.y].map(f32::to_bits).hash(hasher);
+                    }
+                }
+            }
+        }
+    }
+
+    // Lay the edits out as TerrainEdits in assets/shaders/common.wgsl
+    fn pack(&self) -> Vec<u8> {
+        let batches = self.batches();
+
+        let mut gpu_edits = Vec::with_capacity(self.edits.len());
+        let mut vertices: Vec<[f32; 2]> = Vec::with_capacity(self.vertex_count);
+
+        for edit in &self.edits {
+            let bounds = edit.bounds();
+            let mut gpu_edit = GpuEdit {
+                op: edit.op as u32,
+                strength: edit.strength,
+                bounds_min: bounds.min.to_array(),
+                bounds_max: bounds.max.to_array(),
+                ..default()
+            };
+
+            match &edit.brush {
+                Brush::Circle { centre, radius } => {
+                    gpu_edit.brush = BRUSH_CIRCLE;
+                    gpu_edit.centre = centre.to_array();
+                    gpu_edit.radius = *radius;
+                }
+                Brush::Polygon(points) => {
+                    gpu_edit.brush = BRUSH_POLYGON;
+                    gpu_edit.vertex_start = vertices.len() as u32;
+                    gpu_edit.vertex_count = points.len() as u32;
+                    vertices.extend(points.iter().map(|p| p.to_array()));
+                }
+            }
+            gpu_edits.push(gpu_edit);
+        }
+
+        let mut bytes = vec![0u8; edit_buffer_size()];
+        let mut write = |offset: usize, data: &[u8]| {
+            bytes[offset..offset + data.len()].copy_from_slice(data);
+        };
+
+        write(0, bytemuck::bytes_of(&[self.edits.len() as u32, batches.len() as u32]));
+        write(BATCHES_OFFSET, bytemuck::cast_slice(&batches));
+        write(EDITS_OFFSET, bytemuck::cast_slice(&gpu_edits));
+        write(VERTICES_OFFSET, bytemuck::cast_slice(&vertices));
+
+        bytes
+    }
+}
+
+const BRUSH_CIRCLE: u32 = 0;
+const BRUSH_POLYGON: u32 = 1;
+
+// matches Edit in assets/shaders/common.wgsl
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable, Default)]
+struct GpuEdit {
+    op: u32,
+    brush: u32,
+    strength: f32,
+    radius: f32,
+    centre: [f32; 2],
+    vertex_start: u32,
+    vertex_count: u32,
+    bounds_min: [f32; 2],
+    bounds_max: [f32; 2],
+}
+
+// edit_count and batch_count come first
+const BATCHES_OFFSET: usize = 8;
+const EDITS_OFFSET: usize = BATCHES_OFFSET + MAX_EDITS * 8;
+const VERTICES_OFFSET: usize = EDITS_OFFSET + MAX_EDITS * std::mem::size_of::<GpuEdit>();
+
+pub fn edit_buffer_size() -> usize {
+    VERTICES_OFFSET + MAX_EDIT_VERTICES * 8
+}
+
+// Mouse brush for trying edits out from the gui
+#[derive(Resource)]
+pub struct EditTool {
+    pub enabled: bool,
+    pub op: EditOp,
+    pub radius: f32,
+    pub strength: f32,
+    // drag out a polygon and apply it on release instead of painting circles
+    pub lasso: bool,
+    last_dab: Option<Vec2>,
+    lasso_points: Vec<Vec2>,
+}
+
+impl Default for EditTool {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            op: EditOp::Subtract,
+            radius: 20.0,
+            strength: 1.0,
+            lasso: false,
+            last_dab: None,
+            lasso_points: Vec::new(),
+        }
+    }
+}
+
+pub struct EditPlugin;
+
+impl Plugin for EditPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TerrainEdits>();
+        app.init_resource::<EditTool>();
+        app.add_event::<TerrainChanged>();
+        app.add_plugins(ExtractResourcePlugin::<TerrainEdits>::default());
+        app.add_systems(Update, (paint_edits, sync_edits).chain());
+    }
+
+    fn finish(&self, app: &mut App) {
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app.add_systems(Render, upload_edits.in_set(RenderSet::PrepareResources));
+    }
+}
+
+// Re-run the chain and report the changed regions whenever the edits change
+fn sync_edits(
+    mut edits: ResMut<TerrainEdits>,
+    params: Res<ParamsUniform>,
+    mut configs: ResMut<ShaderConfigHolder>,
+    mut changed: ResMut<ParamsChanged>,
+    mut terrain_changed: EventWriter<TerrainChanged>,
+) {
+    if params.is_changed() {
+        terrain_changed.send(TerrainChanged { region: None });
+    }
+
+    if !edits.is_changed() {
+        return;
+    }
+
+    // apply_edits always runs at least once, it writes the rock mask
+    let passes = edits.batches().len().max(1) as u32;
+    if let Some(config) = configs.stage_mut("apply_edits") {
+        if config.iterations != passes {
+            config.iterations = passes;
+        }
+    }
+    changed.0 = true;
+
+    let regions = std::mem::take(&mut edits.bypass_change_detection().pending_regions);
+    terrain_changed.send_batch(regions.into_iter().map(|region| TerrainChanged { region }));
+}
+
+// The texel of the result under the mouse
+#[derive(SystemParam)]
+struct CursorTexel<'w, 's> {
+    windows: Query<'w, 's, &'static Window, With<PrimaryWindow>>,
+    cameras: Query<'w, 's, (&'static Camera, &'static GlobalTransform)>,
+    sprites: Query<'w, 's, &'static Transform, With<ResultSprite>>,
+    chunks: Res<'w, ChunkSettings>,
+}
+
+impl CursorTexel<'_, '_> {
+    fn get(&self) -> Option<Vec2> {
+        let window = self.windows.get_single().ok()?;
+        let (camera, camera_transform) = self.cameras.get_single().ok()?;
+        let sprite = self.sprites.get_single().ok()?;
+        let cursor = window.cursor_position()?;
+        let world_pos = camera.viewport_to_world_2d(camera_transform, cursor).ok()?;
+
+        // a chunked planet is chunks_per_side result sprites across
+        let planet_scale = if self.chunks.enabled {
+            self.chunks.chunks_per_side as f32
+        } else {
+            1.0
+        };
+        Some(sprite_to_texel((world_pos - sprite.translation.truncate()) / planet_scale))
+    }
+}
+
+fn paint_edits(
+    mut tool: ResMut<EditTool>,
+    mut edits: ResMut<TerrainEdits>,
+    mut contexts: EguiContexts,
+    mouse: Res<ButtonInput<MouseButton>>,
+    cursor: CursorTexel,
+) {
+    if !tool.enabled || !mouse.pressed(MouseButton::Left) {
+        if tool.last_dab.is_some() {
+            tool.last_dab = None;
+        }
+        if !tool.lasso_points.is_empty() {
+            let points = std::mem::take(&mut tool.lasso_points);
+            if points.len() >= 3 {
+                let edit = TerrainEdit::polygon(points, tool.op).with_strength(tool.strength);
+                edits.push(edit);
+            }
+        }
+        return;
+    }
+
+    let ctx = contexts.ctx_mut();
+    if ctx.is_pointer_over_area() || ctx.is_using_pointer() {
+        return;
+    }
+
+    let Some(texel) = cursor.get() else {
+        return;
+    };
+
+    if tool.lasso {
+        if tool.lasso_points.last().is_none_or(|p| p.distance(texel) >= 2.0) {
+            tool.lasso_points.push(texel);
+        }
+        return;
+    }
+
+    // space the dabs out while dragging rather than adding one every frame
+    if let Some(last) = tool.last_dab {
+        if last.distance(texel) < tool.radius * 0.5 {
+            return;
+        }
+    }
+
+    let edit = TerrainEdit::circle(texel, tool.radius, tool.op).with_strength(tool.strength);
+    if edits.push(edit) {
+        tool.last_dab = Some(texel);
+    }
+}
+
+fn upload_edits(
+    edits: Res<TerrainEdits>,
+    textures: Option<Res<ImageBufferContainer>>,
+    buffers: Res<RenderAssets<GpuShaderStorageBuffer>>,
+    render_queue: Res<RenderQueue>,
+    mut uploaded: Local<Option<u32>>,
+) {
+    if *uploaded == Some(edits.revision) {
+        return;
+    }
+    let Some(buffer) = textures.and_then(|t| buffers.get(&t.edit_buffer)) else {
+        return;
+    };
+
+    render_queue.write_buffer(&buffer.buffer, 0, &edits.pack());
+    *uploaded = Some(edits.revision);
+}
+
+#[cfg(test)]
+mod tests {
+    use naga::{ResourceBinding, StructMember, TypeInner};
+
+    use super::*;
+    use crate::shader_validation::compile_stage;
+
+    fn square(min: Vec2, size: f32) -> Vec<Vec2> {
+        [Vec2::ZERO, Vec2::X, Vec2::ONE, Vec2::Y]
+            .map(|corner| min + corner * size)
+            .to_vec()
+    }
+
+    #[test]
+    fn smooth_edits_start_a_new_pass() {
+        let mut edits = TerrainEdits::default();
+        assert!(edits.batches().is_empty());
+
+        let ops = [
+            EditOp::Add,
+            EditOp::Subtract,
+            EditOp::Smooth,
+            EditOp::Add,
+            EditOp::Smooth,
+            EditOp::Smooth,
+        ];
+        for (i, op) in ops.into_iter().enumerate() {
+            assert!(edits.push(TerrainEdit::circle(Vec2::splat(i as f32), 4.0, op)));
+        }
+        assert_eq!(edits.batches(), vec![[0, 2], [2, 4], [4, 5], [5, 6]]);
+    }
+
+    #[test]
+    fn pushes_past_the_buffer_are_refused() {
+        let mut edits = TerrainEdits::default();
+        let points = vec![Vec2::ZERO; MAX_EDIT_VERTICES - 2];
+        assert!(edits.push(TerrainEdit::polygon(points, EditOp::Add)));
+        assert!(!edits.push(TerrainEdit::polygon(square(Vec2::ZERO, 1.0), EditOp::Add)));
+        assert_eq!(edits.edits().len(), 1);
+
+        // circles take no vertices
+        assert!(edits.push(TerrainEdit::circle(Vec2::ZERO, 1.0, EditOp::Add)));
+        while edits.edits().len() < MAX_EDITS {
+            assert!(edits.push(TerrainEdit::circle(Vec2::ZERO, 1.0, EditOp::Add)));
+        }
+        assert!(!edits.push(TerrainEdit::circle(Vec2::ZERO, 1.0, EditOp::Add)));
+    }
+
+    #[test]
+    fn undo_and_clear_report_what_they_removed() {
+        let mut edits = TerrainEdits::default();
+        let circle = TerrainEdit::circle(Vec2::new(10.0, 20.0), 5.0, EditOp::Subtract);
+        let polygon = TerrainEdit::polygon(square(Vec2::new(100.0, 50.0), 8.0), EditOp::Add);
+        edits.push(circle.clone());
+        edits.push(polygon.clone());
+        assert_eq!(
+            std::mem::take(&mut edits.pending_regions),
+            vec![
+                Some(Rect::new(5.0, 15.0, 15.0, 25.0)),
+                Some(Rect::new(100.0, 50.0, 108.0, 58.0)),
+            ]
+        );
+
+        let revision = edits.revision;
+        assert_eq!(edits.undo(), Some(polygon));
+        assert_eq!(edits.pending_regions, vec![Some(Rect::new(100.0, 50.0, 108.0, 58.0))]);
+        assert_eq!(edits.vertex_count, 0);
+        assert_ne!(edits.revision, revision);
+
+        edits.pending_regions.clear();
+        edits.push(circle.clone());
+        edits.pending_regions.clear();
+        edits.clear();
+        assert_eq!(edits.pending_regions, vec![Some(circle.bounds()); 2]);
+        assert!(edits.edits().is_empty());
+
+        edits.pending_regions.clear();
+        assert_eq!(edits.undo(), None);
+        assert!(edits.pending_regions.is_empty());
+    }
+
+    // The members of TerrainEdits and Edit as apply_edits.wgsl sees them
+    fn shader_layout() -> (Vec<StructMember>, Vec<StructMember>, u32) {
+        let module = compile_stage("shaders/apply_edits.wgsl");
+        let edits = module
+            .global_variables
+            .iter()
+            .find(|(_, var)| var.binding == Some(ResourceBinding { group: 0, binding: 13 }))
+            .map(|(_, var)| var.ty)
+            .expect("the edit buffer at binding 13");
+        let TypeInner::Struct { members, .. } = &module.types[edits].inner else {
+            panic!("TerrainEdits isn't a struct");
+        };
+        let array = members.iter().find(|m| m.name.as_deref() == Some("edits")).unwrap();
+        let TypeInner::Array { base, stride, .. } = module.types[array.ty].inner else {
+            panic!("TerrainEdits.edits isn't an array");
+        };
+        let TypeInner::Struct { members: edit, .. } = &module.types[base].inner else {
+            panic!("Edit isn't a struct");
+        };
+        (members.clone(), edit.clone(), stride)
+    }
+
+    #[test]
+    fn packed_edits_match_the_shader_layout() {
+        let (buffer, edit, stride) = shader_layout();
+        let offset = |members: &[StructMember], name: &str| {
+            members
+                .iter()
+                .find(|m| m.name.as_deref() == Some(name))
+                .unwrap_or_else(|| panic!("no member {}", name))
+                .offset as usize
+        };
+
+        assert_eq!(offset(&buffer, "edit_count"), 0);
+        assert_eq!(offset(&buffer, "batch_count"), 4);
+        assert_eq!(offset(&buffer, "batches"), BATCHES_OFFSET);
+        assert_eq!(offset(&buffer, "edits"), EDITS_OFFSET);
+        assert_eq!(offset(&buffer, "vertices"), VERTICES_OFFSET);
+        assert_eq!(stride as usize, std::mem::size_of::<GpuEdit>());
+        for (name, rust) in [
+            ("op", std::mem::offset_of!(GpuEdit, op)),
+            ("brush", std::mem::offset_of!(GpuEdit, brush)),
+            ("strength", std::mem::offset_of!(GpuEdit, strength)),
+            ("radius", std::mem::offset_of!(GpuEdit, radius)),
+            ("centre", std::mem::offset_of!(GpuEdit, centre)),
+            ("vertex_start", std::mem::offset_of!(GpuEdit, vertex_start)),
+            ("vertex_count", std::mem::offset_of!(GpuEdit, vertex_count)),
+            ("bounds_min", std::mem::offset_of!(GpuEdit, bounds_min)),
+            ("bounds_max", std::mem::offset_of!(GpuEdit, bounds_max)),
+        ] {
+            assert_eq!(offset(&edit, name), rust, "Edit.{}", name);
+        }
+
+        let mut edits = TerrainEdits::default();
+        edits.push(TerrainEdit::circle(Vec2::new(3.0, 4.0), 2.0, EditOp::Add).with_strength(0.5));
+        edits.push(TerrainEdit::polygon(square(Vec2::new(1.0, 2.0), 3.0), EditOp::Smooth));
+        let bytes = edits.pack();
+        assert_eq!(bytes.len(), edit_buffer_size());
+
+        let u32_at = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        let f32_at = |at: usize| f32::from_bits(u32_at(at));
+        assert_eq!((u32_at(0), u32_at(4)), (2, 2));
+        assert_eq!(
+            (0..4).map(|i| u32_at(BATCHES_OFFSET + i * 4)).collect::<Vec<_>>(),
+            vec![0, 1, 1, 2]
+        );
+
+        let circle = EDITS_OFFSET;
+        assert_eq!(u32_at(circle + offset(&edit, "op")), EditOp::Add as u32);
+        assert_eq!(u32_at(circle + offset(&edit, "brush")), BRUSH_CIRCLE);
+        assert_eq!(f32_at(circle + offset(&edit, "strength")), 0.5);
+        assert_eq!(f32_at(circle + offset(&edit, "radius")), 2.0);
+        assert_eq!(f32_at(circle + offset(&edit, "centre") + 4), 4.0);
+        assert_eq!(f32_at(circle + offset(&edit, "bounds_min")), 1.0);
+        assert_eq!(f32_at(circle + offset(&edit, "bounds_max") + 4), 6.0);
+
+        let polygon = EDITS_OFFSET + stride as usize;
+        assert_eq!(u32_at(polygon + offset(&edit, "op")), EditOp::Smooth as u32);
+        assert_eq!(u32_at(polygon + offset(&edit, "brush")), BRUSH_POLYGON);
+        assert_eq!(u32_at(polygon + offset(&edit, "vertex_start")), 0);
+        assert_eq!(u32_at(polygon + offset(&edit, "vertex_count")), 4);
+        assert_eq!(f32_at(VERTICES_OFFSET + 2 * 8), 4.0);
+        assert_eq!(f32_at(VERTICES_OFFSET + 2 * 8 + 4), 5.0);
+
+        // and the shader names the ops and brushes the same numbers
+        let common = std::fs::read_to_string(
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/shaders/common.wgsl"),
+        )
+        .unwrap();
+        for (name, value) in [
+            ("EDIT_ADD", EditOp::Add as u32),
+            ("EDIT_SUBTRACT", EditOp::Subtract as u32),
+            ("EDIT_SMOOTH", EditOp::Smooth as u32),
+            ("BRUSH_CIRCLE", BRUSH_CIRCLE),
+            ("BRUSH_POLYGON", BRUSH_POLYGON),
+        ] {
+            let line = format!("const {} = {}u;", name, value);
+            assert!(common.contains(&line), "common.wgsl doesn't have {}", line);
+        }
+    }
+}
@@ -0,0 +1,236 @@
+/*
+Golden image tests. Every preset in presets/ runs through the CPU reference with each of SEEDS
+and the rock is compared with tests/golden/<preset>_<seed>.pgm, texel by texel within
+TEXEL_TOLERANCE and by the mean structural similarity of 8x8 windows. A failing case writes
+the image it made and a diff of the two to target/golden. After a change that's meant to move
+the output, re-bless the goldens with
+
+    BLESS=1 cargo test golden
+
+There's no GPU path, the chain only runs inside the render graph of a windowed app, so this is
+the CPU reference checking itself. The reference's own comparison against the GPU is the
+button in the Compare window.
+*/
+
+use std::path::{Path, PathBuf};
+
+use crate::{
+    cpu_reference::{difference, generate_rock, write_pgm},
+    parameters::ParamsUniform,
+};
+
+const SEEDS: [u32; 2] = [0, 1];
+
+// two steps of the 8 bit images
+const TEXEL_TOLERANCE: f32 = 2.0 / 255.0;
+// fraction of the texels allowed past the tolerance, the rock's edge can shift by a texel
+// where the sin in the noise rounds differently on another platform
+const MAX_DIFFERING: f32 = 0.002;
+const MIN_SSIM: f32 = 0.98;
+
+const SSIM_WINDOW: usize = 8;
+
+struct Case {
+    name: String,
+    params: ParamsUniform,
+}
+
+fn manifest_path(path: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join(path)
+}
+
+fn load_presets() -> Vec<(String, ParamsUniform)> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(manifest_path("presets"))
+        .expect("presets directory")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|e| e == "preset"))
+        .collect();
+    paths.sort();
+
+    paths
+        .into_iter()
+        .map(|path| {
+            let text = std::fs::read_to_string(&path).unwrap();
+            let mut params = ParamsUniform::default();
+            if let Err(e) = params.apply_preset(&text) {
+                panic!("{}: {}", path.display(), e);
+            }
+            let name = path.file_stem().unwrap().to_string_lossy().to_string();
+            (name, params)
+        })
+        .collect()
+}
+
+fn cases() -> Vec<Case> {
+    load_presets()
+        .into_iter()
+        .flat_map(|(preset, params)| {
+            SEEDS.into_iter().map(move |seed| {
+                let mut params = params;
+                params.seed = seed;
+                Case {
+                    name: format!("{}_{}", preset, seed),
+                    params,
+                }
+            })
+        })
+        .collect()
+}
+
+// The size and values of a square image written by write_pgm
+fn read_pgm(path: &Path) -> Result<(usize, Vec<f32>), String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    // the header is the magic number, width, height and max value, then one whitespace byte
+    let mut fields = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    while fields.len() < 4 && i < bytes.len() {
+        if bytes[i].is_ascii_whitespace() {
+            if i > start {
+                fields.push(String::from_utf8_lossy(&bytes[start..i]).to_string());
+            }
+            start = i + 1;
+        }
+        i += 1;
+    }
+
+    let bad = || format!("{}: not an 8 bit square pgm", path.display());
+    if fields.len() < 4 || fields[0] != "P5" || fields[3] != "255" || fields[1] != fields[2] {
+        return Err(bad());
+    }
+    let size: usize = fields[1].parse().map_err(|_| bad())?;
+    let data = &bytes[start..];
+    if data.len() != size * size {
+        return Err(bad());
+    }
+    Ok((size, data.iter().map(|b| *b as f32 / 255.0).collect()))
+}
+
+// Mean SSIM over non-overlapping windows, for values in 0..1
+fn ssim(a: &[f32], b: &[f32], size: usize) -> f32 {
+    const C1: f32 = 0.01 * 0.01;
+    const C2: f32 = 0.03 * 0.03;
+
+    let windows = size / SSIM_WINDOW;
+    let mut total = 0.0;
+    for wy in 0..windows {
+        for wx in 0..windows {
+            let texels = (0..SSIM_WINDOW * SSIM_WINDOW).map(|i| {
+                let x = wx * SSIM_WINDOW + i % SSIM_WINDOW;
+                let y = wy * SSIM_WINDOW + i / SSIM_WINDOW;
+                (a[y * size + x], b[y * size + x])
+            });
+
+            let n = (SSIM_WINDOW * SSIM_WINDOW) as f32;
+            let (mean_a, mean_b) = texels.clone().fold((0.0, 0.0), |m, t| (m.0 + t.0, m.1 + t.1));
+            let (mean_a, mean_b) = (mean_a / n, mean_b / n);
+
+            let (mut var_a, mut var_b, mut covariance) = (0.0, 0.0, 0.0);
+            for (va, vb) in texels {
+                var_a += (va - mean_a) * (va - mean_a);
+                var_b += (vb - mean_b) * (vb - mean_b);
+                covariance += (va - mean_a) * (vb - mean_b);
+            }
+            let (var_a, var_b, covariance) = (var_a / n, var_b / n, covariance / n);
+
+            total += ((2.0 * mean_a * mean_b + C1) * (2.0 * covariance + C2))
+                / ((mean_a * mean_a + mean_b * mean_b + C1) * (var_a + var_b + C2));
+        }
+    }
+    total / (windows * windows).max(1) as f32
+}
+
+// Check one case against its golden, or write the golden when blessing
+fn check_case(case: &Case, bless: bool) -> Result<(), String> {
+    let rock = generate_rock(case.params)?.channel(0);
+    let size = case.params.dimensions as usize;
+    let golden_path = manifest_path(&format!("tests/golden/{}.pgm", case.name));
+
+    if bless {
+        return write_pgm(&golden_path, size, &rock).map_err(|e| e.to_string());
+    }
+
+    let (golden_size, golden) = read_pgm(&golden_path)
+        .map_err(|e| format!("{}, bless the goldens with BLESS=1 cargo test golden", e))?;
+    if golden_size != size {
+        return Err(format!("golden is {} texels across, expected {}", golden_size, size));
+    }
+
+    // compare what the golden stores, not the unrounded floats
+    let rock: Vec<f32> = rock
+        .iter()
+        .map(|v| (v.clamp(0.0, 1.0) * 255.0).round() / 255.0)
+        .collect();
+    let d = difference(&rock, &golden, TEXEL_TOLERANCE);
+    let differing = d.over_tolerance as f32 / d.count.max(1) as f32;
+    let similarity = ssim(&rock, &golden, size);
+
+    if differing <= MAX_DIFFERING && similarity >= MIN_SSIM {
+        return Ok(());
+    }
+
+    let out_dir = std::env::var("CARGO_TARGET_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| manifest_path("target"))
+        .join("golden");
+    std::fs::create_dir_all(&out_dir).map_err(|e| e.to_string())?;
+    let actual_path = out_dir.join(format!("{}.actual.pgm", case.name));
+    let diff_path = out_dir.join(format!("{}.diff.pgm", case.name));
+
+    // any difference past the tolerance in full white
+    let diff: Vec<f32> = rock
+        .iter()
+        .zip(&golden)
+        .map(|(a, b)| if (a - b).abs() > TEXEL_TOLERANCE { 1.0 } else { 0.0 })
+        .collect();
+    write_pgm(&actual_path, size, &rock).map_err(|e| e.to_string())?;
+    write_pgm(&diff_path, size, &diff).map_err(|e| e.to_string())?;
+
+    Err(format!(
+        "{:.3}% of texels differ (max {:.3}), ssim {:.4}, see {}",
+        differing * 100.0,
+        d.max,
+        similarity,
+        diff_path.display()
+    ))
+}
+
+#[test]
+fn golden_images() {
+    let bless = std::env::var("BLESS").is_ok_and(|v| v != "0");
+
+    let failures: Vec<String> = cases()
+        .iter()
+        .filter_map(|case| {
+            check_case(case, bless)
+                .err()
+                .map(|e| format!("{}: {}", case.name, e))
+        })
+        .collect();
+
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[test]
+fn presets_report_bad_lines() {
+    let mut params = ParamsUniform::default();
+    assert!(params.apply_preset("dimensions = 128 # comment\n\nradius=0.25").is_ok());
+    assert_eq!(params.dimensions, 128);
+    assert_eq!(params.radius, 0.25);
+
+    let error = params.apply_preset("radius = 0.2\nnot_a_field = 1").unwrap_err();
+    assert!(error.starts_with("line 2"), "{}", error);
+    assert!(params.apply_preset("radius").is_err());
+    assert!(params.apply_preset("dimensions = -1").is_err());
+}
+
+#[test]
+fn ssim_of_an_image_with_itself_is_one() {
+    let size = 32;
+    let image: Vec<f32> = (0..size * size).map(|i| ((i * 7) % 13) as f32 / 13.0).collect();
+    assert!((ssim(&image, &image, size) - 1.0).abs() < 1e-5);
+
+    let inverted: Vec<f32> = image.iter().map(|v| 1.0 - v).collect();
+    assert!(ssim(&image, &inverted, size) < 0.5);
+}
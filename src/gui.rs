@@ -1,16 +1,51 @@
-use bevy::prelude::*;
+use std::f32::consts::{FRAC_PI_2, PI};
+
+use bevy::{asset::LoadState, prelude::*};
 use bevy_egui::{egui, EguiContexts};
 
+use crate::ca_rules::{parse_rulestring, rulestring, CaRule, Neighbourhood};
+use crate::chunks::{ChunkCache, ChunkRequest, ChunkSettings};
+use crate::colliders::{ColliderMode, ColliderSettings, TerrainColliders};
+use crate::constants::{BUFFER_LEN, MAX_ORES};
+use crate::contours::{ContourKind, ContourSettings, Contours, RockMask};
+use crate::convergence::ConvergenceReport;
+use crate::cpu_reference::CpuCheck;
+use crate::data_structures::{format_shader_defs, parse_shader_defs};
+use crate::edits::{EditOp, EditTool, TerrainEdits};
 use crate::gradient_editor::{gradient_editor, Gradient};
+use crate::materials::{Material, MaterialTable};
+use crate::ores::{OreCounts, OreDefinition, OreNoise, OreTable};
 
-use crate::{Gradients, ParamsChanged, ParamsUniform, ShaderConfigHolder};
+use crate::parameters::{ViewChannel, ViewParams, ViewSource};
+use crate::pipeline_status::{PipelineStatus, PipelineStatusSettings, StageState};
+use crate::profiler::{chrome_trace, summarize, ProfilerSettings, StageTimings};
+use crate::resources::CompareMode;
+use crate::stage_cache::{StageCacheSettings, StageCacheStats};
+use crate::strip_plot::{strip_plot, StripData, StripPlotSettings};
+use crate::{CommonShaders, Comparison, Gradients, ParamsChanged, ParamsUniform, ShaderConfigHolder};
 
 pub struct GuiPlugin;
 
 impl Plugin for GuiPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(bevy_egui::EguiPlugin);
-        app.add_systems(Update, ui_system);
+        app.add_systems(
+            Update,
+            (
+                ui_system,
+                view_ui_system,
+                compare_ui_system,
+                strip_plot_ui_system,
+                contour_ui_system,
+                material_ui_system,
+                ore_ui_system,
+                edit_ui_system,
+                profiler_ui_system,
+                cache_ui_system,
+                chunk_ui_system,
+                pipeline_ui_system,
+            ),
+        );
     }
 }
 
@@ -20,8 +55,12 @@ fn ui_system(
     mut configs: ResMut<ShaderConfigHolder>,
     mut gradients: ResMut<Gradients>,
     mut changed: ResMut<ParamsChanged>,
+    mut ca_rule: ResMut<CaRule>,
+    // the rulestring being typed, and why the last one didn't parse
+    mut rule_edit: Local<(String, Option<String>)>,
 ) {
     let mut old_params: ParamsUniform = params.clone();
+    let mut rule = *ca_rule;
 
     let mut g = Gradient::default();
 
@@ -30,6 +69,13 @@ fn ui_system(
         .default_width(600.0)
         .show(contexts.ctx_mut(), |ui| {
             ui.heading("noiseeee");
+            // every stage's randomness comes from this one seed
+            ui.horizontal(|ui| {
+                ui.add(egui::DragValue::new(&mut old_params.seed).prefix("seed "));
+                if ui.button("randomise seed").clicked() {
+                    old_params.seed = random_seed();
+                }
+            });
             ui.group(|ui| {
                 ui.label("radius");
                 egui::CollapsingHeader::new("Noise Parameters")
@@ -99,28 +145,17 @@ fn ui_system(
                     egui::Slider::new(&mut old_params.domain_warp_1_scale_b, 10.0..=70.)
                         .text("warp 1 scale 2"),
                 );
-                if configs.shader_configs.len() > 1 {
-                    ui.horizontal(|ui| {
-                        ui.label("warp iterations");
-                        ui.add(
-                            egui::DragValue::new(&mut configs.shader_configs[1].iterations)
-                                .range(0..=50),
-                        );
-                    });
+                if stage_iterations_slider(ui, &mut configs, "domain_warp_1", 50, "warp iterations") {
+                    changed.0 = true;
                 }
 
                 ui.add(
                     egui::Slider::new(&mut old_params.noise_weight, 0.0..=1.).text("noise weight"),
                 );
-                if configs.shader_configs.len() > 3 {
-                    ui.horizontal(|ui| {
-                        ui.label("ca iterations");
-                        ui.add(
-                            egui::DragValue::new(&mut configs.shader_configs[4].iterations)
-                                .range(0..=100),
-                        );
-                    });
+                if stage_iterations_slider(ui, &mut configs, "ca_run", 100, "ca iterations") {
+                    changed.0 = true;
                 }
+                ca_rule_ui(ui, &mut rule, &mut rule_edit);
                 ui.add(egui::Slider::new(&mut old_params.ca_thresh, 0.0..=1.).text("thresh"));
                 ui.add(
                     egui::Slider::new(&mut old_params.ca_search_radius, 0.1..=6.)
@@ -147,6 +182,51 @@ fn ui_system(
                         .text("warp 2 scale 1"),
                 );
 
+                let mut refinements = old_params.jfa_refinements;
+                ui.add(egui::Slider::new(&mut refinements, 0..=2).text("sdf refinement passes"));
+                if refinements != old_params.jfa_refinements {
+                    old_params.set_jfa_refinements(refinements);
+                }
+
+                ui.add(
+                    egui::Slider::new(&mut old_params.crust_depth, 0.0..=100.).text("crust depth"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut old_params.lava_depth, 0.0..=1000.).text("lava depth"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut old_params.liquid_fill, 0.0..=1.).text("liquid fill"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut old_params.water_viscosity, 0.0..=1.)
+                        .text("water viscosity"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut old_params.lava_viscosity, 0.0..=1.)
+                        .text("lava viscosity"),
+                );
+                if stage_iterations_slider(ui, &mut configs, "liquid_run", 2048, "liquid settle passes") {
+                    changed.0 = true;
+                }
+
+                ui.add(
+                    egui::Slider::new(&mut old_params.talus_angle, 0.0..=1.5).text("talus angle"),
+                );
+                ui.add(egui::Slider::new(&mut old_params.rain, 0.0..=0.1).text("rain"));
+                ui.add(
+                    egui::Slider::new(&mut old_params.evaporation, 0.0..=1.).text("evaporation"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut old_params.sediment_capacity, 0.0..=4.)
+                        .text("sediment capacity"),
+                );
+                if stage_iterations_slider(ui, &mut configs, "erode_thermal", 512, "thermal erosion passes") {
+                    changed.0 = true;
+                }
+                if stage_iterations_slider(ui, &mut configs, "erode_hydraulic", 512, "hydraulic erosion passes") {
+                    changed.0 = true;
+                }
+
                 ui.add(egui::Slider::new(&mut old_params.misc_f, 0.0..=1.).text("misc f"));
                 ui.add(egui::Slider::new(&mut old_params.misc_i, 1..=2000).text("misc i"));
 
@@ -163,4 +243,833 @@ fn ui_system(
                 }
             });
         });
+
+    ca_rule.set_if_neq(rule);
+}
+
+// The rule ca_run iterates, see src/ca_rules.rs
+fn ca_rule_ui(ui: &mut egui::Ui, rule: &mut CaRule, edit: &mut (String, Option<String>)) {
+    let neighbourhood = rule.neighbourhood().unwrap_or(Neighbourhood::Moore);
+    let rules = [
+        CaRule::WeightedRadius,
+        CaRule::CAVE,
+        CaRule::Smoothing { neighbourhood },
+    ];
+    egui::ComboBox::from_label("ca rule")
+        .selected_text(rule.label())
+        .show_ui(ui, |ui| {
+            for option in rules {
+                let selected = rule.label() == option.label();
+                if ui.selectable_label(selected, option.label()).clicked() && !selected {
+                    *rule = option;
+                }
+            }
+        });
+
+    if let CaRule::Classic { birth, survive, .. } = rule {
+        let (text, error) = edit;
+        let response = ui.add(
+            egui::TextEdit::singleline(text)
+                .hint_text("B678/S345678")
+                .desired_width(160.0),
+        );
+        if response.lost_focus() {
+            match parse_rulestring(text) {
+                Ok(masks) => {
+                    (*birth, *survive) = masks;
+                    *error = None;
+                }
+                Err(e) => *error = Some(e),
+            }
+        }
+        if !response.has_focus() && error.is_none() {
+            *text = rulestring(*birth, *survive);
+        }
+        if let Some(e) = error {
+            ui.colored_label(egui::Color32::LIGHT_RED, e.as_str());
+        }
+    }
+
+    if let CaRule::Classic { neighbourhood, .. } | CaRule::Smoothing { neighbourhood } = rule {
+        ui.horizontal(|ui| {
+            for option in Neighbourhood::ALL {
+                ui.radio_value(neighbourhood, option, option.label());
+            }
+        });
+    }
+}
+
+// Slider for a stage's iteration count, only touches the configs when it moves
+fn stage_iterations_slider(
+    ui: &mut egui::Ui,
+    configs: &mut ResMut<ShaderConfigHolder>,
+    stage: &str,
+    max: u32,
+    label: &str,
+) -> bool {
+    let Some(current) = configs.stage(stage).map(|c| c.iterations) else {
+        return false;
+    };
+
+    let mut iterations = current;
+    ui.add(egui::Slider::new(&mut iterations, 0..=max).text(label));
+    if iterations == current {
+        return false;
+    }
+
+    if let Some(config) = configs.stage_mut(stage) {
+        config.iterations = iterations;
+    }
+    true
+}
+
+fn view_ui_system(
+    mut contexts: EguiContexts,
+    mut view: ResMut<ViewParams>,
+    configs: Res<ShaderConfigHolder>,
+    mut gradients: ResMut<Gradients>,
+    mut changed: ResMut<ParamsChanged>,
+) {
+    let mut new_view = *view;
+    let old_stops = gradients.gradient.stops.clone();
+    let old_interpolation = gradients.gradient.interpolation_method;
+
+    egui::Window::new("View")
+        .default_width(300.0)
+        .show(contexts.ctx_mut(), |ui| {
+            // stop point in the chain
+            let stage_label = |stage: i32| {
+                if stage < 0 {
+                    "final".to_string()
+                } else {
+                    format!("{}: {}", stage, configs.shader_configs[stage as usize].name())
+                }
+            };
+
+            egui::ComboBox::from_label("stage")
+                .selected_text(stage_label(new_view.stop_stage))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut new_view.stop_stage, -1, stage_label(-1));
+                    for i in 0..configs.shader_configs.len() as i32 {
+                        ui.selectable_value(&mut new_view.stop_stage, i, stage_label(i));
+                    }
+                });
+
+            if new_view.stop_stage >= 0 {
+                let iterations = configs.shader_configs[new_view.stop_stage as usize].iterations;
+                if iterations > 1 {
+                    new_view.stop_iteration = new_view.stop_iteration.min(iterations - 1);
+                    ui.add(
+                        egui::Slider::new(&mut new_view.stop_iteration, 0..=iterations - 1)
+                            .text("iteration"),
+                    );
+                } else {
+                    new_view.stop_iteration = 0;
+                }
+            }
+
+            // what to display
+            let source = ViewSource::ALL
+                .into_iter()
+                .find(|s| *s as u32 == new_view.source)
+                .unwrap_or(ViewSource::Texture1);
+
+            egui::ComboBox::from_label("source")
+                .selected_text(source.label())
+                .show_ui(ui, |ui| {
+                    for s in ViewSource::ALL {
+                        ui.selectable_value(&mut new_view.source, s as u32, s.label());
+                    }
+                });
+
+            if source == ViewSource::Materials {
+                ui.label("colours are set in the materials window");
+            } else if source == ViewSource::Lit {
+                lighting_ui(ui, &mut new_view);
+            } else if source.is_grid() {
+                ui.add(egui::Slider::new(&mut new_view.grid_channel, 0..=7).text("grid channel"));
+            } else {
+                let channel = ViewChannel::ALL
+                    .into_iter()
+                    .find(|c| *c as u32 == new_view.channel)
+                    .unwrap_or(ViewChannel::Rgb);
+
+                egui::ComboBox::from_label("channel")
+                    .selected_text(channel.label())
+                    .show_ui(ui, |ui| {
+                        for c in ViewChannel::ALL {
+                            ui.selectable_value(&mut new_view.channel, c as u32, c.label());
+                        }
+                    });
+            }
+
+            // remapping
+            ui.horizontal(|ui| {
+                ui.label("range");
+                ui.add(egui::DragValue::new(&mut new_view.range_min).speed(0.01));
+                ui.add(egui::DragValue::new(&mut new_view.range_max).speed(0.01));
+            });
+
+            let mut false_colour = new_view.false_colour != 0;
+            ui.checkbox(&mut false_colour, "false colour");
+            new_view.false_colour = false_colour as u32;
+
+            if false_colour {
+                gradient_editor(ui, &mut gradients.gradient);
+            }
+
+            if ui.button("reset view").clicked() {
+                new_view = ViewParams::default();
+            }
+        });
+
+    let gradient_changed = gradients.gradient.stops != old_stops
+        || gradients.gradient.interpolation_method != old_interpolation;
+
+    if new_view != *view || gradient_changed {
+        *view = new_view;
+        changed.0 = true;
+    }
+}
+
+fn lighting_ui(ui: &mut egui::Ui, view: &mut ViewParams) {
+    ui.label("material colours, shaded");
+    ui.group(|ui| {
+        ui.label("light");
+        ui.add(egui::Slider::new(&mut view.light_angle, -PI..=PI).text("angle"));
+        ui.add(egui::Slider::new(&mut view.light_elevation, 0.05..=FRAC_PI_2).text("elevation"));
+        ui.add(egui::Slider::new(&mut view.light_intensity, 0.0..=3.0).text("intensity"));
+        ui.add(egui::Slider::new(&mut view.ambient, 0.0..=1.0).text("ambient"));
+    });
+    ui.group(|ui| {
+        ui.label("surface");
+        ui.add(egui::Slider::new(&mut view.relief, 0.0..=32.0).text("relief"));
+        ui.add(egui::Slider::new(&mut view.bevel_width, 1.0..=32.0).text("bevel width"));
+    });
+    ui.group(|ui| {
+        ui.label("shadows and occlusion");
+        ui.add(egui::Slider::new(&mut view.shadow_softness, 1.0..=32.0).text("shadow softness"));
+        ui.add(egui::Slider::new(&mut view.shadow_length, 0.0..=256.0).text("shadow length"));
+        ui.add(egui::Slider::new(&mut view.ao_radius, 1.0..=64.0).text("ao radius"));
+        ui.add(egui::Slider::new(&mut view.ao_strength, 0.0..=1.0).text("ao strength"));
+    });
+    ui.group(|ui| {
+        ui.label("atmosphere");
+        ui.add(egui::Slider::new(&mut view.atmosphere_width, 0.0..=128.0).text("width"));
+        ui.add(egui::Slider::new(&mut view.atmosphere_strength, 0.0..=2.0).text("strength"));
+        let mut rgb = [
+            view.atmosphere_colour.x,
+            view.atmosphere_colour.y,
+            view.atmosphere_colour.z,
+        ];
+        ui.horizontal(|ui| {
+            ui.color_edit_button_rgb(&mut rgb);
+            ui.label("colour");
+        });
+        view.atmosphere_colour = Vec4::new(rgb[0], rgb[1], rgb[2], 1.0);
+    });
+}
+
+fn compare_ui_system(
+    mut contexts: EguiContexts,
+    mut comparison: ResMut<Comparison>,
+    mut params: ResMut<ParamsUniform>,
+    mut changed: ResMut<ParamsChanged>,
+    mut cpu_check: ResMut<CpuCheck>,
+    edits: Res<TerrainEdits>,
+    mask: Res<RockMask>,
+) {
+    egui::Window::new("Compare")
+        .default_width(300.0)
+        .show(contexts.ctx_mut(), |ui| {
+            cpu_check_ui(ui, &mut cpu_check, &params, &edits, &mask);
+            ui.separator();
+
+            ui.horizontal(|ui| {
+                if ui.button("snapshot").clicked() {
+                    comparison.snapshot = Some(*params);
+                    comparison.snapshot_generation += 1;
+                    if comparison.mode == CompareMode::Off {
+                        comparison.mode = CompareMode::SideBySide;
+                    }
+                    changed.0 = true;
+                }
+
+                if let Some(snapshot) = comparison.snapshot {
+                    if ui.button("restore snapshot").clicked() {
+                        *params = snapshot;
+                        changed.0 = true;
+                    }
+                    if ui.button("clear").clicked() {
+                        comparison.snapshot = None;
+                        comparison.mode = CompareMode::Off;
+                    }
+                }
+            });
+
+            if comparison.snapshot.is_none() {
+                ui.label("no snapshot");
+                return;
+            }
+
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut comparison.mode, CompareMode::Off, "off");
+                ui.radio_value(&mut comparison.mode, CompareMode::SideBySide, "side by side");
+                ui.radio_value(&mut comparison.mode, CompareMode::Wipe, "wipe");
+            });
+
+            if comparison.mode == CompareMode::Wipe {
+                ui.add(egui::Slider::new(&mut comparison.split, 0.0..=1.0).text("split"));
+            }
+
+            if ui
+                .checkbox(&mut comparison.rerun_snapshot, "run snapshot params through the pipeline")
+                .changed()
+            {
+                changed.0 = true;
+            }
+        });
+}
+
+// Check the GPU rock mask against the CPU reference of the same parameters
+fn cpu_check_ui(
+    ui: &mut egui::Ui,
+    check: &mut CpuCheck,
+    params: &ParamsUniform,
+    edits: &TerrainEdits,
+    mask: &RockMask,
+) {
+    ui.horizontal(|ui| {
+        let running = check.running();
+        if ui
+            .add_enabled(!running, egui::Button::new("check against CPU"))
+            .clicked()
+        {
+            check.requested = true;
+        }
+        if running {
+            ui.spinner();
+        }
+    });
+
+    if !edits.edits().is_empty() {
+        ui.label("the rock mask includes edits, which the CPU reference doesn't");
+    }
+    if mask.size != params.dimensions as usize {
+        ui.label("the rock mask is from a different resolution");
+    }
+
+    match &check.result {
+        Some(Ok(result)) => {
+            let d = result.difference;
+            ui.label(format!(
+                "{} of {} texels differ, max {:.3}, mean {:.5}, {:.1}s on the CPU",
+                d.over_tolerance, d.count, d.max, d.mean, result.seconds
+            ));
+        }
+        Some(Err(e)) => {
+            ui.label(e);
+        }
+        None => {}
+    }
+}
+
+// The clock, hashed so clicks close together get unrelated seeds. std's hasher keys are only
+// random on native, on wasm they're fixed and the clock is all the entropy there is
+fn random_seed() -> u32 {
+    use std::hash::BuildHasher;
+    let now = bevy::utils::SystemTime::now()
+        .duration_since(bevy::utils::SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    std::collections::hash_map::RandomState::new().hash_one(now.as_nanos()) as u32
+}
+
+fn strip_plot_ui_system(
+    mut contexts: EguiContexts,
+    data: Res<StripData>,
+    mut settings: ResMut<StripPlotSettings>,
+) {
+    egui::Window::new("Strips")
+        .default_width(400.0)
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            strip_plot(ui, &data, &mut settings);
+        });
+}
+
+fn profiler_ui_system(
+    mut contexts: EguiContexts,
+    mut settings: ResMut<ProfilerSettings>,
+    timings: Res<StageTimings>,
+    configs: Res<ShaderConfigHolder>,
+    mut export_status: Local<Option<String>>,
+) {
+    egui::Window::new("Profiler")
+        .default_width(300.0)
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            if !timings.supported() {
+                ui.label("timestamp queries are not supported by this adapter");
+                return;
+            }
+
+            ui.checkbox(&mut settings.enabled, "record timestamps");
+            ui.checkbox(&mut settings.continuous, "re-run every frame");
+
+            let passes = timings.passes();
+            let summaries = summarize(&passes, &configs.shader_configs);
+
+            egui::Grid::new("stage_timings").striped(true).show(ui, |ui| {
+                ui.label("stage");
+                ui.label("passes");
+                ui.label("total ms");
+                ui.label("ms / pass");
+                ui.end_row();
+
+                for summary in &summaries {
+                    ui.label(&summary.name);
+                    ui.label(summary.passes.to_string());
+                    ui.label(format!("{:.3}", summary.total_ms));
+                    ui.label(format!("{:.3}", summary.total_ms / summary.passes as f64));
+                    ui.end_row();
+                }
+            });
+
+            let total: f64 = summaries.iter().map(|s| s.total_ms).sum();
+            ui.label(format!("total {:.3} ms", total));
+            // only when the chain has more passes than a query set can hold
+            let untimed = timings.untimed();
+            if untimed > 0 {
+                ui.colored_label(
+                    egui::Color32::YELLOW,
+                    format!("{} passes not timed", untimed),
+                );
+            }
+
+            if ui.button("export chrome trace").clicked() {
+                let trace = chrome_trace(&passes, &configs.shader_configs);
+                *export_status = Some(write_trace(&trace));
+            }
+            if let Some(status) = export_status.as_ref() {
+                ui.label(status);
+            }
+        });
+}
+
+fn contour_ui_system(
+    mut contexts: EguiContexts,
+    mut settings: ResMut<ContourSettings>,
+    contours: Res<Contours>,
+    mut collider_settings: ResMut<ColliderSettings>,
+    colliders: Res<TerrainColliders>,
+) {
+    egui::Window::new("Contours")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.checkbox(&mut settings.show_overlay, "show overlay");
+            ui.add(egui::Slider::new(&mut settings.iso_level, 0.0..=1.0).text("iso level"));
+            ui.add(
+                egui::Slider::new(&mut settings.tolerance, 0.0..=10.0).text("simplify tolerance"),
+            );
+            ui.add(egui::Slider::new(&mut settings.min_area, 0.0..=1000.0).text("min area"));
+
+            let count = |kind| contours.contours.iter().filter(|c| c.kind == kind).count();
+            let points: usize = contours.contours.iter().map(|c| c.points.len()).sum();
+            ui.label(format!(
+                "{} islands, {} caves",
+                count(ContourKind::Island),
+                count(ContourKind::Cave)
+            ));
+            ui.label(format!("{} points, {} before simplifying", points, contours.raw_points));
+            // caves have negative area so this is the rock left over
+            let area: f32 = contours.contours.iter().map(|c| c.area).sum();
+            ui.label(format!("{:.0} texels of rock", area));
+
+            ui.separator();
+            ui.checkbox(&mut collider_settings.enabled, "build colliders");
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut collider_settings.mode, ColliderMode::Polyline, "polylines");
+                ui.radio_value(&mut collider_settings.mode, ColliderMode::Convex, "convex pieces");
+            });
+            if collider_settings.mode == ColliderMode::Convex {
+                ui.add(
+                    egui::Slider::new(&mut collider_settings.max_convex_vertices, 3..=64)
+                        .text("max vertices"),
+                );
+            }
+            ui.checkbox(&mut collider_settings.show_overlay, "show colliders");
+            ui.label(format!("{} shapes", colliders.shapes.len()));
+            if colliders.building() {
+                ui.label("building convex pieces...");
+            }
+            if let Some(region) = colliders.changed_region {
+                ui.label(format!(
+                    "last rebuild covered {:.0} x {:.0} around ({:.0}, {:.0})",
+                    region.width(),
+                    region.height(),
+                    region.center().x,
+                    region.center().y
+                ));
+            }
+        });
+}
+
+fn material_ui_system(
+    mut contexts: EguiContexts,
+    mut table: ResMut<MaterialTable>,
+    mut changed: ResMut<ParamsChanged>,
+) {
+    let mut new_table = table.clone();
+
+    egui::Window::new("Materials")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            for material in Material::ALL {
+                let Some(info) = new_table.materials.get_mut(material.id() as usize) else {
+                    continue;
+                };
+                ui.horizontal(|ui| {
+                    let mut rgb = info.colour.to_srgba().to_f32_array_no_alpha();
+                    ui.color_edit_button_rgb(&mut rgb);
+                    info.colour = Color::srgb(rgb[0], rgb[1], rgb[2]);
+                    ui.label(format!("{}: {}", material.id(), info.name));
+                });
+            }
+        });
+
+    let colours_changed = new_table
+        .materials
+        .iter()
+        .zip(&table.materials)
+        .any(|(a, b)| a.colour != b.colour);
+
+    if colours_changed {
+        *table = new_table;
+        // the extract pass only reruns with the chain
+        changed.0 = true;
+    }
+}
+
+fn ore_ui_system(
+    mut contexts: EguiContexts,
+    mut table: ResMut<OreTable>,
+    counts: Res<OreCounts>,
+) {
+    let mut new_table = table.clone();
+
+    egui::Window::new("Ores")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            let mut remove = None;
+            for (i, ore) in new_table.ores.iter_mut().enumerate() {
+                ui.separator();
+                ui.horizontal(|ui| {
+                    let mut rgb = ore.colour.to_srgba().to_f32_array_no_alpha();
+                    ui.color_edit_button_rgb(&mut rgb);
+                    ore.colour = Color::srgb(rgb[0], rgb[1], rgb[2]);
+                    ui.text_edit_singleline(&mut ore.name);
+                    if ui.button("remove").clicked() {
+                        remove = Some(i);
+                    }
+                });
+                ui.add(egui::Slider::new(&mut ore.min_depth, 0.0..=1000.0).text("min depth"));
+                ui.add(egui::Slider::new(&mut ore.max_depth, 0.0..=1000.0).text("max depth"));
+                ui.add(egui::Slider::new(&mut ore.rarity, 0.0..=0.5).text("rarity"));
+                ui.add(egui::Slider::new(&mut ore.vein_size, 1.0..=100.0).text("vein size"));
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_id_salt(("ore noise", i))
+                        .selected_text(ore.noise.label())
+                        .show_ui(ui, |ui| {
+                            for noise in OreNoise::ALL {
+                                ui.selectable_value(&mut ore.noise, noise, noise.label());
+                            }
+                        });
+                    ui.add(egui::DragValue::new(&mut ore.seed).prefix("seed "));
+                });
+
+                let texels = counts.count(&ore.name).unwrap_or(0);
+                let share = 100.0 * texels as f32 / (BUFFER_LEN * BUFFER_LEN) as f32;
+                ui.label(format!("{} texels, {:.3}%", texels, share));
+            }
+
+            if let Some(i) = remove {
+                new_table.ores.remove(i);
+            }
+
+            ui.separator();
+            ui.add_enabled_ui(new_table.ores.len() < MAX_ORES, |ui| {
+                if ui.button("add ore").clicked() {
+                    let n = new_table.ores.len() as u32;
+                    new_table.ores.push(OreDefinition {
+                        name: format!("ore {}", n),
+                        colour: Color::WHITE,
+                        min_depth: 0.0,
+                        max_depth: 1000.0,
+                        rarity: 0.05,
+                        vein_size: 10.0,
+                        noise: OreNoise::Simplex,
+                        seed: n + 1,
+                    });
+                }
+            });
+            ui.label(format!("{} ore texels in total", counts.total()));
+        });
+
+    if new_table != *table {
+        *table = new_table;
+    }
+}
+
+fn edit_ui_system(
+    mut contexts: EguiContexts,
+    mut tool: ResMut<EditTool>,
+    mut edits: ResMut<TerrainEdits>,
+) {
+    egui::Window::new("Edit")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.checkbox(&mut tool.enabled, "paint with the left mouse button");
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut tool.op, EditOp::Add, "add");
+                ui.radio_value(&mut tool.op, EditOp::Subtract, "subtract");
+                ui.radio_value(&mut tool.op, EditOp::Smooth, "smooth");
+            });
+            ui.checkbox(&mut tool.lasso, "lasso");
+            if !tool.lasso {
+                ui.add(egui::Slider::new(&mut tool.radius, 1.0..=200.0).text("radius (texels)"));
+            }
+            ui.add(egui::Slider::new(&mut tool.strength, 0.0..=1.0).text("strength"));
+
+            ui.label(format!(
+                "{} edits in {} passes",
+                edits.edits().len(),
+                edits.batches().len()
+            ));
+            ui.horizontal(|ui| {
+                if ui.button("undo").clicked() {
+                    edits.undo();
+                }
+                if ui.button("clear").clicked() {
+                    edits.clear();
+                }
+            });
+        });
+}
+
+fn cache_ui_system(
+    mut contexts: EguiContexts,
+    mut settings: ResMut<StageCacheSettings>,
+    stats: Res<StageCacheStats>,
+    configs: Res<ShaderConfigHolder>,
+) {
+    egui::Window::new("Stage cache")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.checkbox(&mut settings.enabled, "reuse stage outputs for previously seen parameters");
+            ui.add(egui::Slider::new(&mut settings.budget_mb, 0..=8192).text("budget (MB)"));
+
+            let stats = stats.get();
+            ui.label(format!(
+                "{} entries, {:.1} MB",
+                stats.entries,
+                stats.bytes as f64 / (1024.0 * 1024.0)
+            ));
+            ui.label(format!("{} stage hits, {} misses", stats.hits, stats.misses));
+            if let Some(restart) = stats.restart {
+                let stage = configs
+                    .shader_configs
+                    .get(restart)
+                    .map_or("extract", |config| config.name());
+                ui.label(format!("last run restarted at {}", stage));
+            }
+        });
+}
+
+#[allow(clippy::too_many_arguments)]
+fn pipeline_ui_system(
+    mut contexts: EguiContexts,
+    mut settings: ResMut<PipelineStatusSettings>,
+    status: Res<PipelineStatus>,
+    shaders: Res<CommonShaders>,
+    asset_server: Res<AssetServer>,
+    mut configs: ResMut<ShaderConfigHolder>,
+    convergence: Res<ConvergenceReport>,
+    mut changed: ResMut<ParamsChanged>,
+    // the defs being typed for each stage, and why the last ones didn't parse
+    mut def_edits: Local<Vec<(String, Option<String>)>>,
+) {
+    let status = status.get();
+    if def_edits.len() != configs.shader_configs.len() {
+        *def_edits = configs
+            .shader_configs
+            .iter()
+            .map(|config| (format_shader_defs(&config.shader_defs), None))
+            .collect();
+    }
+    let failed = status.stages.iter().filter(|s| s.state == StageState::Error).count();
+
+    egui::Window::new("Pipelines")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            if ui
+                .checkbox(&mut settings.halt_on_error, "halt the chain at the first failed stage")
+                .changed()
+            {
+                changed.0 = true;
+            }
+
+            ui.label(format!(
+                "{} of {} ready, {} failed",
+                status.ready,
+                status.stages.len(),
+                failed
+            ));
+            if let Some(stage) = status.halted_at.and_then(|i| status.stages.get(i)) {
+                ui.colored_label(egui::Color32::LIGHT_RED, format!("halted at {}", stage.name));
+            }
+
+            // a module that didn't load leaves everything importing it queued
+            egui::Grid::new("shader_imports").striped(true).show(ui, |ui| {
+                for (name, handle) in shaders.imports() {
+                    let (colour, state) = match asset_server.load_state(handle) {
+                        LoadState::Loaded => (egui::Color32::LIGHT_GREEN, "loaded".to_string()),
+                        LoadState::Failed(e) => (egui::Color32::LIGHT_RED, e.to_string()),
+                        _ => (egui::Color32::YELLOW, "loading".to_string()),
+                    };
+                    ui.label(name);
+                    ui.colored_label(colour, state);
+                    ui.end_row();
+                }
+            });
+            ui.separator();
+
+            // a stage's defs are applied when the edit loses focus, which re-specializes its
+            // pipeline and reruns the chain
+            egui::Grid::new("pipeline_status").striped(true).show(ui, |ui| {
+                for (i, stage) in status.stages.iter().enumerate() {
+                    let colour = match stage.state {
+                        StageState::Ok => egui::Color32::LIGHT_GREEN,
+                        StageState::Error => egui::Color32::LIGHT_RED,
+                        StageState::Queued | StageState::Compiling => egui::Color32::YELLOW,
+                    };
+                    ui.label(&stage.name);
+                    ui.colored_label(colour, stage.state.label());
+
+                    if let Some((text, error)) = def_edits.get_mut(i) {
+                        let edit = ui.add(
+                            egui::TextEdit::singleline(text)
+                                .hint_text("shader defs")
+                                .desired_width(160.0),
+                        );
+                        if edit.lost_focus() {
+                            match parse_shader_defs(text) {
+                                Ok(defs) => {
+                                    *error = None;
+                                    let config = &mut configs.shader_configs[i];
+                                    if config.shader_defs != defs {
+                                        config.shader_defs = defs;
+                                        changed.0 = true;
+                                    }
+                                    *text = format_shader_defs(&config.shader_defs);
+                                }
+                                Err(e) => *error = Some(e),
+                            }
+                        } else if !edit.has_focus() && error.is_none() {
+                            // defs set from elsewhere, like the ca rule
+                            *text = format_shader_defs(&configs.shader_configs[i].shader_defs);
+                        }
+                        if let Some(e) = error {
+                            ui.colored_label(egui::Color32::LIGHT_RED, e.as_str());
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+
+            // the stages that count their changes, a threshold of 0 runs every iteration
+            ui.separator();
+            ui.label("converged iterations still dispatch, they only carry their input through");
+            egui::Grid::new("convergence").striped(true).show(ui, |ui| {
+                for (i, config) in configs.shader_configs.iter_mut().enumerate() {
+                    let name = config.name();
+                    let Some(converge_below) = &mut config.converge_below else {
+                        continue;
+                    };
+                    ui.label(name);
+                    if ui
+                        .add(egui::DragValue::new(converge_below).prefix("stop below "))
+                        .on_hover_text("0 runs every iteration and only reports the changes")
+                        .changed()
+                    {
+                        changed.0 = true;
+                    }
+
+                    match convergence.stage(i) {
+                        Some(stage) if stage.stopped_early() => ui.colored_label(
+                            egui::Color32::LIGHT_GREEN,
+                            format!("converged after {} of {}", stage.ran, stage.changes.len()),
+                        ),
+                        Some(stage) => ui.label(format!(
+                            "ran all {}, the last changed {} texels",
+                            stage.changes.len(),
+                            stage.last_changes().unwrap_or_default()
+                        )),
+                        None => ui.label("not run yet"),
+                    };
+                    ui.end_row();
+                }
+            });
+
+            for stage in status.stages.iter().filter(|s| s.message.is_some()) {
+                ui.separator();
+                ui.label(&stage.name);
+                ui.label(egui::RichText::new(stage.message.as_deref().unwrap_or_default()).monospace());
+            }
+        });
+}
+
+fn chunk_ui_system(
+    mut contexts: EguiContexts,
+    mut settings: ResMut<ChunkSettings>,
+    cache: Res<ChunkCache>,
+    request: Res<ChunkRequest>,
+    params: Res<ParamsUniform>,
+    configs: Res<ShaderConfigHolder>,
+) {
+    egui::Window::new("Chunks")
+        .default_open(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.checkbox(&mut settings.enabled, "generate the planet in chunks");
+            ui.add(egui::Slider::new(&mut settings.chunks_per_side, 1..=16).text("chunks per side"));
+            ui.add(egui::Slider::new(&mut settings.halo, 0..=256).text("extra halo (texels)"));
+            let halo = settings.halo(&params, &configs);
+            ui.label(format!("halo of {} texels", halo.texels));
+            if halo.warp_limit > 0.0 {
+                ui.label(format!("warps held to {} texels an iteration", halo.warp_limit));
+            }
+            ui.add(egui::Slider::new(&mut settings.load_radius, 0..=4).text("load radius"));
+            ui.add(egui::Slider::new(&mut settings.max_cached, 1..=64).text("max cached"));
+
+            // each chunk keeps a full Rgba32Float texture
+            let mb = (BUFFER_LEN * BUFFER_LEN * 16 * cache.len()) as f64 / (1024.0 * 1024.0);
+            ui.label(format!("{} of {} chunks ready, {:.0} MB", cache.ready(), cache.len(), mb));
+            if let Some(target) = &request.target {
+                ui.label(format!("generating {}, {}", target.coord.x, target.coord.y));
+            }
+        });
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write_trace(trace: &str) -> String {
+    let path = "stage_trace.json";
+    match std::fs::write(path, trace) {
+        Ok(_) => format!("wrote {}", path),
+        Err(e) => format!("failed to write {}: {}", path, e),
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn write_trace(trace: &str) -> String {
+    info!("{}", trace);
+    "no filesystem on the web, the trace was written to the console".to_string()
 }
@@ -11,24 +11,56 @@ use bytemuck::{Pod, Zeroable};
 use cam_controller::CameraController;
 // use gradient_editor::update_gradient_texture;
 use constants::*;
-use parameters::ParamsUniform;
+use parameters::{ParamsUniform, ViewParams};
 use resources::*;
 
+mod ca_rules;
 mod cam_controller;
+mod chunks;
+mod colliders;
+mod compare;
 mod compute_node;
 mod compute_plugin;
 mod constants;
+mod contours;
+mod convergence;
+mod cpu_reference;
+mod edits;
+#[cfg(test)]
+mod golden;
 mod gradient_editor;
 mod gui;
+mod materials;
+mod ores;
 mod parameters;
 mod pipeline;
+mod pipeline_status;
+mod profiler;
 mod resources;
+#[cfg(test)]
+mod shader_validation;
+mod stage_cache;
 mod bind_groups;
 mod data_structures;
+mod strip_plot;
 
 fn main() {
+    // generate on the CPU and write a greyscale image instead of opening a window,
+    // --cpu-reference [out.pgm] [preset]
+    let mut args = std::env::args().skip(1);
+    if args.next().as_deref() == Some("--cpu-reference") {
+        let path = args.next().unwrap_or_else(|| "planet.pgm".to_string());
+        let preset = args.next().map(std::path::PathBuf::from);
+        if let Err(e) = cpu_reference::write_headless(path.as_ref(), preset.as_deref()) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     App::new()
         .insert_resource(ParamsUniform::default())
+        .insert_resource(ViewParams::default())
         .insert_resource(Gradients::default())
         .add_systems(Startup, setup)
         .add_plugins((
@@ -43,9 +75,28 @@ fn main() {
             }),
             cam_controller::CameraControllerPlugin,
             compute_plugin::ComputeShaderPlugin,
-            ExtractResourcePlugin::<Gradients>::default(),
-            ExtractResourcePlugin::<ImageBufferContainer>::default(),
-            ExtractResourcePlugin::<ParamsUniform>::default(),
+            compare::ComparePlugin,
+            strip_plot::StripPlotPlugin,
+            contours::ContourPlugin,
+            colliders::ColliderPlugin,
+            edits::EditPlugin,
+            chunks::ChunkPlugin,
+            materials::MaterialPlugin,
+            ores::OrePlugin,
+            (
+                profiler::ProfilerPlugin,
+                stage_cache::StageCachePlugin,
+                cpu_reference::CpuReferencePlugin,
+                pipeline_status::PipelineStatusPlugin,
+                convergence::ConvergencePlugin,
+                ca_rules::CaRulePlugin,
+            ),
+            (
+                ExtractResourcePlugin::<Gradients>::default(),
+                ExtractResourcePlugin::<ImageBufferContainer>::default(),
+                ExtractResourcePlugin::<ParamsUniform>::default(),
+                ExtractResourcePlugin::<ViewParams>::default(),
+            ),
             gui::GuiPlugin,
         ))
         .insert_resource(ClearColor(Color::BLACK))
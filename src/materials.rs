@@ -0,0 +1,95 @@
+use bevy::{
+    prelude::*,
+    render::{
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        render_resource::ShaderType,
+    },
+};
+
+use crate::constants::MAX_MATERIALS;
+
+// these need to match the MATERIAL_ constants in assets/shaders/common.wgsl
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u32)]
+pub enum Material {
+    Space = 0,
+    Crust = 1,
+    DeepRock = 2,
+    CaveAir = 3,
+    Water = 4,
+    Lava = 5,
+}
+
+impl Material {
+    pub const ALL: [Material; 6] = [
+        Material::Space,
+        Material::Crust,
+        Material::DeepRock,
+        Material::CaveAir,
+        Material::Water,
+        Material::Lava,
+    ];
+
+    pub fn id(&self) -> u32 {
+        *self as u32
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MaterialInfo {
+    pub name: String,
+    pub colour: Color,
+}
+
+// What the ids classify_materials, place_ores and the liquid stages write in to grid_a.ints mean, indexed by id.
+// The ores follow the base materials, see OreTable.
+#[derive(Resource, ExtractResource, Clone)]
+pub struct MaterialTable {
+    pub materials: Vec<MaterialInfo>,
+}
+
+impl Default for MaterialTable {
+    fn default() -> Self {
+        let info = |name: &str, colour: Color| MaterialInfo {
+            name: name.to_string(),
+            colour,
+        };
+
+        Self {
+            materials: vec![
+                info("space", Color::BLACK),
+                info("surface crust", Color::srgb(0.55, 0.42, 0.28)),
+                info("deep rock", Color::srgb(0.32, 0.3, 0.3)),
+                info("cave air", Color::srgb(0.08, 0.06, 0.1)),
+                info("water", Color::srgb(0.15, 0.35, 0.8)),
+                info("lava", Color::srgb(0.95, 0.35, 0.05)),
+            ],
+        }
+    }
+}
+
+impl MaterialTable {
+    pub fn colours(&self) -> MaterialColours {
+        let mut colours = [Vec4::ZERO; MAX_MATERIALS];
+        for (colour, info) in colours.iter_mut().zip(&self.materials) {
+            *colour = info.colour.to_linear().to_vec4();
+        }
+        MaterialColours { colours }
+    }
+}
+
+// matches MaterialColours in assets/shaders/common.wgsl
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, ShaderType)]
+#[repr(C)]
+pub struct MaterialColours {
+    pub colours: [Vec4; MAX_MATERIALS],
+}
+
+pub struct MaterialPlugin;
+
+impl Plugin for MaterialPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MaterialTable>();
+        app.add_plugins(ExtractResourcePlugin::<MaterialTable>::default());
+    }
+}
@@ -0,0 +1,263 @@
+use std::hash::{Hash, Hasher};
+
+use bevy::{
+    prelude::*,
+    render::{
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        gpu_readback::{Readback, ReadbackComplete},
+        render_asset::RenderAssets,
+        renderer::RenderQueue,
+        storage::GpuShaderStorageBuffer,
+        Render, RenderApp, RenderSet,
+    },
+};
+use bytemuck::{Pod, Zeroable};
+
+use crate::{
+    constants::*,
+    materials::{Material, MaterialInfo, MaterialTable},
+    ImageBufferContainer, ParamsChanged,
+};
+
+// ore definition i is material FIRST_ORE_MATERIAL + i
+pub const FIRST_ORE_MATERIAL: u32 = Material::Lava as u32 + 1;
+
+// these need to match the ORE_NOISE_ constants in assets/shaders/common.wgsl
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+#[repr(u32)]
+pub enum OreNoise {
+    Simplex = 0,
+    Fbm = 1,
+    Voronoi = 2,
+    Psrd = 3,
+}
+
+impl OreNoise {
+    pub const ALL: [OreNoise; 4] = [
+        OreNoise::Simplex,
+        OreNoise::Fbm,
+        OreNoise::Voronoi,
+        OreNoise::Psrd,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            OreNoise::Simplex => "simplex",
+            OreNoise::Fbm => "fbm",
+            OreNoise::Voronoi => "voronoi",
+            OreNoise::Psrd => "psrd",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OreDefinition {
+    pub name: String,
+    pub colour: Color,
+    // texels below the surface of the untiled planet
+    pub min_depth: f32,
+    pub max_depth: f32,
+    // rough fraction of the rock in the depth range that becomes this ore
+    pub rarity: f32,
+    // rough width of a vein in texels of the untiled planet
+    pub vein_size: f32,
+    pub noise: OreNoise,
+    pub seed: u32,
+}
+
+// The ores placed by place_ores, earlier ones win where they overlap
+#[derive(Resource, ExtractResource, Clone, PartialEq)]
+pub struct OreTable {
+    pub ores: Vec<OreDefinition>,
+}
+
+impl Default for OreTable {
+    fn default() -> Self {
+        let ore = |name: &str, colour, depth: (f32, f32), rarity, vein_size, noise, seed| {
+            OreDefinition {
+                name: name.to_string(),
+                colour,
+                min_depth: depth.0,
+                max_depth: depth.1,
+                rarity,
+                vein_size,
+                noise,
+                seed,
+            }
+        };
+
+        Self {
+            ores: vec![
+                ore("copper", Color::srgb(0.8, 0.45, 0.2), (4.0, 120.0), 0.12, 10.0, OreNoise::Simplex, 1),
+                ore("iron", Color::srgb(0.6, 0.3, 0.25), (20.0, 250.0), 0.1, 16.0, OreNoise::Fbm, 2),
+                ore("gold", Color::srgb(0.95, 0.8, 0.2), (120.0, 1000.0), 0.03, 6.0, OreNoise::Voronoi, 3),
+                ore("crystal", Color::srgb(0.5, 0.9, 0.95), (200.0, 1000.0), 0.02, 8.0, OreNoise::Psrd, 4),
+            ],
+        }
+    }
+}
+
+impl OreTable {
+    pub fn hash_into<H: Hasher>(&self, hasher: &mut H) {
+        for ore in &self.ores {
+            bytemuck::bytes_of(&ore.gpu()).hash(hasher);
+        }
+    }
+
+    // Lay the definitions out as Ores in assets/shaders/common.wgsl, the counts after them are
+    // left alone
+    fn pack(&self) -> Vec<u8> {
+        let count = self.ores.len().min(MAX_ORES) as u32;
+        let ores: Vec<GpuOre> = self.ores.iter().take(MAX_ORES).map(|o| o.gpu()).collect();
+
+        let mut bytes = bytemuck::bytes_of(&[count, 0, 0, 0]).to_vec();
+        bytes.extend_from_slice(bytemuck::cast_slice(&ores));
+        bytes
+    }
+}
+
+impl OreDefinition {
+    fn gpu(&self) -> GpuOre {
+        GpuOre {
+            min_depth: self.min_depth,
+            max_depth: self.max_depth,
+            // the noise is roughly uniform over -1..1
+            threshold: 1.0 - 2.0 * self.rarity.clamp(0.0, 1.0),
+            scale: BUFFER_LEN as f32 / self.vein_size.max(0.1),
+            noise: self.noise as u32,
+            seed: self.seed,
+            padding_a: 0,
+            padding_b: 0,
+        }
+    }
+}
+
+// matches OreDefinition in assets/shaders/common.wgsl
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct GpuOre {
+    min_depth: f32,
+    max_depth: f32,
+    threshold: f32,
+    scale: f32,
+    noise: u32,
+    seed: u32,
+    padding_a: u32,
+    padding_b: u32,
+}
+
+// the header is count and three padding words
+const COUNTS_OFFSET: usize = 16 + MAX_ORES * std::mem::size_of::<GpuOre>();
+
+pub fn ore_buffer_size() -> usize {
+    COUNTS_OFFSET + MAX_ORES * 4
+}
+
+// the bytes of the buffer the shaders count in to
+pub fn ore_counts_range() -> std::ops::Range<u64> {
+    COUNTS_OFFSET as u64..ore_buffer_size() as u64
+}
+
+// Texels of each ore in the last generated texture, in the order of the OreTable
+#[derive(Resource, Default)]
+pub struct OreCounts {
+    pub counts: Vec<(String, u32)>,
+}
+
+impl OreCounts {
+    pub fn count(&self, name: &str) -> Option<u32> {
+        self.counts.iter().find(|(n, _)| n == name).map(|(_, c)| *c)
+    }
+
+    pub fn total(&self) -> u32 {
+        self.counts.iter().map(|(_, c)| c).sum()
+    }
+}
+
+pub struct OrePlugin;
+
+impl Plugin for OrePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<OreTable>();
+        app.init_resource::<OreCounts>();
+        app.add_plugins(ExtractResourcePlugin::<OreTable>::default());
+        app.add_systems(Update, sync_ore_materials);
+        app.add_systems(PostUpdate, request_ore_count_readback);
+    }
+
+    fn finish(&self, app: &mut App) {
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app.add_systems(Render, upload_ores.in_set(RenderSet::PrepareResources));
+    }
+}
+
+// Keep the material table's ore entries in step with the definitions
+fn sync_ore_materials(
+    ores: Res<OreTable>,
+    mut materials: ResMut<MaterialTable>,
+    mut changed: ResMut<ParamsChanged>,
+) {
+    if !ores.is_changed() {
+        return;
+    }
+
+    materials.materials.truncate(FIRST_ORE_MATERIAL as usize);
+    materials
+        .materials
+        .extend(ores.ores.iter().take(MAX_ORES).map(|ore| MaterialInfo {
+            name: ore.name.clone(),
+            colour: ore.colour,
+        }));
+    changed.0 = true;
+}
+
+fn request_ore_count_readback(
+    mut commands: Commands,
+    changed: Res<ParamsChanged>,
+    buffers: Option<Res<ImageBufferContainer>>,
+) {
+    let Some(buffers) = buffers else {
+        return;
+    };
+
+    if !changed.is_changed() {
+        return;
+    }
+
+    commands.spawn(Readback::buffer(buffers.ore_buffer.clone())).observe(
+        |trigger: Trigger<ReadbackComplete>,
+         mut commands: Commands,
+         ores: Res<OreTable>,
+         mut counts: ResMut<OreCounts>| {
+            let raw: Vec<u32> = bytemuck::pod_collect_to_vec(&trigger.event().0[COUNTS_OFFSET..]);
+            counts.counts = ores
+                .ores
+                .iter()
+                .zip(raw)
+                .map(|(ore, count)| (ore.name.clone(), count))
+                .collect();
+            commands.entity(trigger.entity()).despawn();
+        },
+    );
+}
+
+fn upload_ores(
+    ores: Res<OreTable>,
+    textures: Option<Res<ImageBufferContainer>>,
+    buffers: Res<RenderAssets<GpuShaderStorageBuffer>>,
+    render_queue: Res<RenderQueue>,
+    mut pending: Local<bool>,
+) {
+    if ores.is_changed() {
+        *pending = true;
+    }
+    if !*pending {
+        return;
+    }
+    let Some(buffer) = textures.and_then(|t| buffers.get(&t.ore_buffer)) else {
+        return;
+    };
+
+    render_queue.write_buffer(&buffer.buffer, 0, &ores.pack());
+    *pending = false;
+}
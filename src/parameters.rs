@@ -7,14 +7,16 @@ pub struct ParamsUniform {
 
     // circle generator
     pub radius: f32,
-    pub noise_seed: u32,
+    // the master seed, every stage derives its own from it, see derive_seed in
+    // assets/shaders/utils/noise.wgsl
+    pub seed: u32,
     pub noise_freq: f32,
     pub noise_amplitude: f32,
     pub noise_offset: f32,
     pub noise_octaves: i32,
     pub noise_lacunarity:f32,
 
-    pub noise_params_1: NoiseParams,
+    pub noise_params: NoiseParams,
 
     pub power_bias: f32,
     pub flatness: f32,
@@ -46,6 +48,43 @@ pub struct ParamsUniform {
     pub misc_f: f32,
     pub misc_i: i32,
     pub botty: f32,
+
+    // jump flood step per pass, four to a vec4, see jfa_schedule
+    pub jfa_steps: [UVec4; JFA_STEP_VECS],
+    pub jfa_pass_count: u32,
+    pub jfa_refinements: u32,
+    pub jfa_padding_a: u32,
+    pub jfa_padding_b: u32,
+
+    // the part of the planet this texture covers, in planet uv, see src/chunks.rs
+    pub tile_origin: Vec2,
+    pub tile_scale: f32,
+    // texels a warp iteration can move a texel at most, 0 for no limit, so chunks agree
+    pub tile_warp_limit: f32,
+
+    // material classification, depths are in texels of the untiled planet
+    pub crust_depth: f32,
+    pub lava_depth: f32,
+    pub material_padding_a: u32,
+    pub material_padding_b: u32,
+
+    // liquids, viscosity is the chance a liquid cell doesn't spread sideways in a pass
+    pub liquid_fill: f32,
+    pub water_viscosity: f32,
+    pub lava_viscosity: f32,
+    pub liquid_padding: u32,
+
+    // erosion of the height profile, the talus angle is in radians
+    pub talus_angle: f32,
+    pub rain: f32,
+    pub evaporation: f32,
+    pub sediment_capacity: f32,
+
+    // the classic ca rule, bit n of each mask is n live neighbours, see src/ca_rules.rs
+    pub ca_birth: u32,
+    pub ca_survive: u32,
+    pub ca_padding_a: u32,
+    pub ca_padding_b: u32,
 }
 
 impl Default for ParamsUniform {
@@ -55,14 +94,14 @@ impl Default for ParamsUniform {
 
             // circle generator
             radius: 0.3,
-            noise_seed: 0,
+            seed: 0,
             noise_freq: 0.3,
             noise_amplitude: 0.8,
             noise_offset: 0.0,
             noise_octaves: 5,
             noise_lacunarity: 2.0,
             
-            noise_params_1: NoiseParams {
+            noise_params: NoiseParams {
                 seed: 0,
                 x: 0.0,
                 y: 0.0,
@@ -105,10 +144,157 @@ impl Default for ParamsUniform {
             misc_f: 0.0,
             misc_i: 0,
             botty: 0.0,
+
+            jfa_steps: [UVec4::ZERO; JFA_STEP_VECS],
+            jfa_pass_count: 0,
+            jfa_refinements: 0,
+            jfa_padding_a: 0,
+            jfa_padding_b: 0,
+
+            tile_origin: Vec2::ZERO,
+            tile_scale: 1.0,
+            tile_warp_limit: 0.0,
+
+            crust_depth: 12.0,
+            lava_depth: 250.0,
+            material_padding_a: 0,
+            material_padding_b: 0,
+
+            liquid_fill: 0.25,
+            water_viscosity: 0.1,
+            lava_viscosity: 0.7,
+            liquid_padding: 0,
+
+            talus_angle: 0.6,
+            rain: 0.01,
+            evaporation: 0.05,
+            sediment_capacity: 0.5,
+
+            ca_birth: 0,
+            ca_survive: 0,
+            ca_padding_a: 0,
+            ca_padding_b: 0,
+        }
+        .with_jfa_refinements(1)
+    }
+}
+
+// room for the steps of a 32k texture plus two refinement passes
+const JFA_STEP_VECS: usize = 4;
+
+// Halving steps from half the resolution down to 1, followed by the optional
+// JFA+1 (one extra step of 1) or JFA+2 (extra steps of 2 and 1) refinement passes
+pub fn jfa_schedule(dimensions: u32, refinements: u32) -> Vec<u32> {
+    let mut steps: Vec<u32> =
+        std::iter::successors(Some(dimensions.next_power_of_two() / 2), |step| {
+            (*step > 1).then_some(step / 2)
+        })
+        .filter(|step| *step > 0)
+        .collect();
+
+    match refinements {
+        0 => {}
+        1 => steps.push(1),
+        _ => steps.extend([2, 1]),
+    }
+
+    steps
+}
+
+impl ParamsUniform {
+    pub fn with_jfa_refinements(mut self, refinements: u32) -> Self {
+        self.set_jfa_refinements(refinements);
+        self
+    }
+
+    pub fn set_jfa_refinements(&mut self, refinements: u32) {
+        let steps = jfa_schedule(self.dimensions, refinements);
+
+        self.jfa_steps = [UVec4::ZERO; JFA_STEP_VECS];
+        for (i, step) in steps.iter().take(JFA_STEP_VECS * 4).enumerate() {
+            self.jfa_steps[i / 4][i % 4] = *step;
+        }
+        self.jfa_pass_count = steps.len().min(JFA_STEP_VECS * 4) as u32;
+        self.jfa_refinements = refinements;
+    }
+
+    // Override fields from a preset, one `field = value` per line with # comments. Only the
+    // scalar fields can be set, the rest keep their values.
+    pub fn apply_preset(&mut self, text: &str) -> Result<(), String> {
+        fn set<T: std::str::FromStr>(field: &mut T, value: &str) -> Result<(), String> {
+            *field = value.parse().map_err(|_| format!("bad value {}", value))?;
+            Ok(())
         }
+
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(format!("line {}: expected field = value", line_number + 1));
+            };
+            let value = value.trim();
+
+            match key.trim() {
+                "dimensions" => set(&mut self.dimensions, value),
+                "radius" => set(&mut self.radius, value),
+                "seed" => set(&mut self.seed, value),
+                "noise_freq" => set(&mut self.noise_freq, value),
+                "noise_amplitude" => set(&mut self.noise_amplitude, value),
+                "noise_offset" => set(&mut self.noise_offset, value),
+                "noise_octaves" => set(&mut self.noise_octaves, value),
+                "noise_lacunarity" => set(&mut self.noise_lacunarity, value),
+                "power_bias" => set(&mut self.power_bias, value),
+                "flatness" => set(&mut self.flatness, value),
+                "steepness" => set(&mut self.steepness, value),
+                "mix" => set(&mut self.mix, value),
+                "domain_warp_1_amount_a" => set(&mut self.domain_warp_1_amount_a, value),
+                "domain_warp_1_scale_a" => set(&mut self.domain_warp_1_scale_a, value),
+                "domain_warp_1_amount_b" => set(&mut self.domain_warp_1_amount_b, value),
+                "domain_warp_1_scale_b" => set(&mut self.domain_warp_1_scale_b, value),
+                "noise_weight" => set(&mut self.noise_weight, value),
+                "ca_thresh" => set(&mut self.ca_thresh, value),
+                "ca_search_radius" => set(&mut self.ca_search_radius, value),
+                "ca_edge_pow" => set(&mut self.ca_edge_pow, value),
+                "edge_suppress_mix" => set(&mut self.edge_suppress_mix, value),
+                "domain_warp_2_amount_a" => set(&mut self.domain_warp_2_amount_a, value),
+                "domain_warp_2_scale_a" => set(&mut self.domain_warp_2_scale_a, value),
+                "domain_warp_2_amount_b" => set(&mut self.domain_warp_2_amount_b, value),
+                "domain_warp_2_scale_b" => set(&mut self.domain_warp_2_scale_b, value),
+                "misc_f" => set(&mut self.misc_f, value),
+                "misc_i" => set(&mut self.misc_i, value),
+                "crust_depth" => set(&mut self.crust_depth, value),
+                "lava_depth" => set(&mut self.lava_depth, value),
+                "liquid_fill" => set(&mut self.liquid_fill, value),
+                "water_viscosity" => set(&mut self.water_viscosity, value),
+                "lava_viscosity" => set(&mut self.lava_viscosity, value),
+                "talus_angle" => set(&mut self.talus_angle, value),
+                "rain" => set(&mut self.rain, value),
+                "evaporation" => set(&mut self.evaporation, value),
+                "sediment_capacity" => set(&mut self.sediment_capacity, value),
+                "jfa_refinements" => set(&mut self.jfa_refinements, value),
+                other => Err(format!("unknown field {}", other)),
+            }
+            .map_err(|e| format!("line {}: {}", line_number + 1, e))?;
+        }
+
+        // the schedule depends on the resolution
+        self.set_jfa_refinements(self.jfa_refinements);
+        Ok(())
     }
 }
 
+// Which dispatch of the chain is running, PassInfo in assets/shaders/utils/pass.wgsl
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, ShaderType, PartialEq, Default)]
+pub struct PassInfo {
+    pub stage: u32,
+    pub iteration: u32,
+    pub iterations: u32,
+    pub converge_below: u32,
+}
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, ShaderType, PartialEq, Default)]
 pub struct NoiseParams {
@@ -129,4 +315,168 @@ pub struct DomainWarpParams{
     pub scale_a: f32,
     pub amount_b: f32,
     pub scale_b: f32
-}
\ No newline at end of file
+}
+// Controls what the extract pass displays. Kept separate from ParamsUniform so the
+// view can change without touching the generation parameters.
+#[derive(Resource, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, ExtractResource, ShaderType, PartialEq)]
+#[repr(C)]
+pub struct ViewParams {
+    // stage to halt the chain after, -1 runs the whole chain
+    pub stop_stage: i32,
+    pub stop_iteration: u32,
+
+    pub source: u32,
+    pub grid_channel: u32,
+    pub channel: u32,
+    pub false_colour: u32,
+    pub range_min: f32,
+    pub range_max: f32,
+
+    // lit view, distances are in texels of the untiled planet and angles in radians
+    pub light_angle: f32,
+    pub light_elevation: f32,
+    pub light_intensity: f32,
+    pub ambient: f32,
+    // height the rock is raised to for the normals and shadows, over bevel_width of its edge
+    pub relief: f32,
+    pub bevel_width: f32,
+    pub shadow_softness: f32,
+    pub shadow_length: f32,
+    pub ao_radius: f32,
+    pub ao_strength: f32,
+    pub atmosphere_width: f32,
+    pub atmosphere_strength: f32,
+    pub atmosphere_colour: Vec4,
+}
+
+impl Default for ViewParams {
+    fn default() -> Self {
+        Self {
+            stop_stage: -1,
+            stop_iteration: 0,
+            source: ViewSource::Texture1 as u32,
+            grid_channel: 0,
+            channel: ViewChannel::Rgb as u32,
+            false_colour: 0,
+            range_min: 0.0,
+            range_max: 1.0,
+
+            light_angle: -2.2,
+            light_elevation: 0.6,
+            light_intensity: 1.0,
+            ambient: 0.25,
+            relief: 6.0,
+            bevel_width: 6.0,
+            shadow_softness: 8.0,
+            shadow_length: 64.0,
+            ao_radius: 24.0,
+            ao_strength: 0.7,
+            atmosphere_width: 24.0,
+            atmosphere_strength: 0.8,
+            atmosphere_colour: Vec4::new(0.35, 0.6, 1.0, 1.0),
+        }
+    }
+}
+
+impl ViewParams {
+    // The number of iterations a stage actually runs once the stop point is taken into account
+    pub fn stage_iterations(&self, stage: usize, iterations: u32) -> u32 {
+        if self.stop_stage < 0 {
+            return iterations;
+        }
+
+        let stop_stage = self.stop_stage as usize;
+        if stage < stop_stage {
+            iterations
+        } else if stage == stop_stage {
+            iterations.min(self.stop_iteration + 1)
+        } else {
+            0
+        }
+    }
+}
+
+// these need to match the VIEW_ constants in assets/shaders/common.wgsl
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u32)]
+pub enum ViewSource {
+    Texture1 = 0,
+    Texture2 = 1,
+    Texture3 = 2,
+    GridAFloats = 3,
+    GridAInts = 4,
+    GridBFloats = 5,
+    GridBInts = 6,
+    Materials = 7,
+    Lit = 8,
+}
+
+impl ViewSource {
+    pub const ALL: [ViewSource; 9] = [
+        ViewSource::Texture1,
+        ViewSource::Texture2,
+        ViewSource::Texture3,
+        ViewSource::GridAFloats,
+        ViewSource::GridAInts,
+        ViewSource::GridBFloats,
+        ViewSource::GridBInts,
+        ViewSource::Materials,
+        ViewSource::Lit,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ViewSource::Texture1 => "texture 1",
+            ViewSource::Texture2 => "texture 2",
+            ViewSource::Texture3 => "texture 3",
+            ViewSource::GridAFloats => "grid a floats",
+            ViewSource::GridAInts => "grid a ints",
+            ViewSource::GridBFloats => "grid b floats",
+            ViewSource::GridBInts => "grid b ints",
+            ViewSource::Materials => "materials",
+            ViewSource::Lit => "lit",
+        }
+    }
+
+    pub fn is_grid(&self) -> bool {
+        !matches!(
+            self,
+            ViewSource::Texture1
+                | ViewSource::Texture2
+                | ViewSource::Texture3
+                | ViewSource::Materials
+                | ViewSource::Lit
+        )
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u32)]
+pub enum ViewChannel {
+    Rgb = 0,
+    R = 1,
+    G = 2,
+    B = 3,
+    A = 4,
+}
+
+impl ViewChannel {
+    pub const ALL: [ViewChannel; 5] = [
+        ViewChannel::Rgb,
+        ViewChannel::R,
+        ViewChannel::G,
+        ViewChannel::B,
+        ViewChannel::A,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ViewChannel::Rgb => "rgb",
+            ViewChannel::R => "r",
+            ViewChannel::G => "g",
+            ViewChannel::B => "b",
+            ViewChannel::A => "a",
+        }
+    }
+}
@@ -5,100 +5,216 @@ use bevy::{
         renderer::RenderDevice,
     },
 };
-use binding_types::{storage_buffer, uniform_buffer};
+use binding_types::{storage_buffer, storage_buffer_sized, uniform_buffer};
 
-use crate::{data_structures::{DataGrid, DataStrip}, parameters::ParamsUniform, ShaderConfigHolder, EXTRACT_HANDLE};
+use crate::{data_structures::{DataGrid, DataStrip, ShaderConfig}, materials::MaterialColours, parameters::{ParamsUniform, PassInfo, ViewParams}, CommonShaders, ShaderConfigHolder};
+
+// The bindings of every stage shader, the headers in assets/shaders have to match
+pub fn compute_layout_entries() -> BindGroupLayoutEntries<16> {
+    BindGroupLayoutEntries::sequential(
+        ShaderStages::COMPUTE,
+        (
+            uniform_buffer::<ParamsUniform>(false),
+            texture_storage_2d(TextureFormat::Rgba32Float, StorageTextureAccess::ReadOnly),
+            texture_storage_2d(TextureFormat::Rgba32Float, StorageTextureAccess::WriteOnly),
+            texture_storage_2d(TextureFormat::Rgba32Float, StorageTextureAccess::ReadOnly),
+            texture_storage_2d(TextureFormat::Rgba32Float, StorageTextureAccess::WriteOnly),
+            texture_storage_2d(TextureFormat::Rgba32Float, StorageTextureAccess::ReadOnly),
+            texture_storage_2d(TextureFormat::Rgba32Float, StorageTextureAccess::WriteOnly),
+            storage_buffer::<DataGrid>(false),
+            storage_buffer::<DataGrid>(false),
+            storage_buffer::<DataStrip>(false),
+            storage_buffer::<DataStrip>(false),
+            texture_storage_2d(TextureFormat::Rgba32Float, StorageTextureAccess::ReadOnly),
+            // rock mask, one f32 per texel, read back for contour extraction
+            storage_buffer_sized(false, None),
+            // TerrainEdits
+            storage_buffer_sized(false, None),
+            // Ores
+            storage_buffer_sized(false, None),
+            // Convergence, the live and comparison chains each get half
+            storage_buffer_sized(false, None),
+        ),
+    )
+}
+
+// The bindings of assets/shaders/extract.wgsl
+pub fn extract_layout_entries() -> BindGroupLayoutEntries<12> {
+    BindGroupLayoutEntries::sequential(
+        ShaderStages::COMPUTE,
+        (
+            uniform_buffer::<ParamsUniform>(false),
+            texture_storage_2d(TextureFormat::Rgba32Float, StorageTextureAccess::ReadOnly),
+            texture_storage_2d(TextureFormat::Rgba32Float, StorageTextureAccess::ReadOnly),
+            texture_storage_2d(TextureFormat::Rgba32Float, StorageTextureAccess::ReadOnly),
+            texture_storage_2d(TextureFormat::Rgba32Float, StorageTextureAccess::WriteOnly),
+            storage_buffer::<DataGrid>(false),
+            storage_buffer::<DataGrid>(false),
+            storage_buffer::<DataStrip>(false),
+            storage_buffer::<DataStrip>(false),
+            texture_storage_2d(TextureFormat::Rgba32Float, StorageTextureAccess::ReadOnly),
+            uniform_buffer::<ViewParams>(false),
+            uniform_buffer::<MaterialColours>(false),
+        ),
+    )
+}
+
+// The group 1 the stages read PassInfo from when there are no push constants
+pub fn pass_layout_entries() -> [BindGroupLayoutEntry; 1] {
+    BindGroupLayoutEntries::single(ShaderStages::COMPUTE, uniform_buffer::<PassInfo>(true))
+}
+
+pub fn pass_push_constant_range() -> PushConstantRange {
+    PushConstantRange {
+        stages: ShaderStages::COMPUTE,
+        range: 0..std::mem::size_of::<PassInfo>() as u32,
+    }
+}
+
+// How each dispatch gets its PassInfo, WebGPU has no push constants
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PassConstants {
+    PushConstant,
+    DynamicUniform,
+}
+
+impl PassConstants {
+    pub fn for_device(render_device: &RenderDevice) -> Self {
+        let size = pass_push_constant_range().range.end;
+        if render_device.features().contains(WgpuFeatures::PUSH_CONSTANTS)
+            && render_device.limits().max_push_constant_size >= size
+        {
+            PassConstants::PushConstant
+        } else {
+            PassConstants::DynamicUniform
+        }
+    }
+}
 
 #[derive(Resource)]
 pub struct ComputePipelines {
     pub compute_layout: BindGroupLayout,
     pub extract_layout: BindGroupLayout,
+    pub pass_layout: BindGroupLayout,
+    pub pass_constants: PassConstants,
+    // the shader of each stage, loaded once and specialized with the stage's defs
+    pub stage_shaders: Vec<Handle<Shader>>,
     pub pipeline_configs: Vec<CachedComputePipelineId>,
     pub final_pass: CachedComputePipelineId,
 }
 
-impl FromWorld for ComputePipelines {
-    fn from_world(world: &mut World) -> Self {
-        // let shader: Handle<Shader> = world.load_asset(SHADER_ASSET_PATH);
-        let shader_configurator = world.resource::<ShaderConfigHolder>();
-        let render_device = world.resource::<RenderDevice>();
-        let compute_layout = render_device.create_bind_group_layout(
-            None,
-            &BindGroupLayoutEntries::sequential(
-                ShaderStages::COMPUTE,
-                (
-                    uniform_buffer::<ParamsUniform>(false),
-                    texture_storage_2d(TextureFormat::Rgba32Float, StorageTextureAccess::ReadOnly),
-                    texture_storage_2d(TextureFormat::Rgba32Float, StorageTextureAccess::WriteOnly),
-                    texture_storage_2d(TextureFormat::Rgba32Float, StorageTextureAccess::ReadOnly),
-                    texture_storage_2d(TextureFormat::Rgba32Float, StorageTextureAccess::WriteOnly),
-                    texture_storage_2d(TextureFormat::Rgba32Float, StorageTextureAccess::ReadOnly),
-                    texture_storage_2d(TextureFormat::Rgba32Float, StorageTextureAccess::WriteOnly),
-                    storage_buffer::<DataGrid>(false),
-                    storage_buffer::<DataGrid>(false),
-                    storage_buffer::<DataStrip>(false),
-                    storage_buffer::<DataStrip>(false),
-                    texture_storage_2d(TextureFormat::Rgba32Float, StorageTextureAccess::ReadOnly),
-                ),
-            ),
-        );
-        let extract_layout = render_device.create_bind_group_layout(
-            None,
-            &BindGroupLayoutEntries::sequential(
-                ShaderStages::COMPUTE,
-                (
-                    uniform_buffer::<ParamsUniform>(false),
-                    texture_storage_2d(TextureFormat::Rgba32Float, StorageTextureAccess::ReadOnly),
-                    texture_storage_2d(TextureFormat::Rgba32Float, StorageTextureAccess::ReadOnly),
-                    texture_storage_2d(TextureFormat::Rgba32Float, StorageTextureAccess::ReadOnly),
-                    texture_storage_2d(TextureFormat::Rgba32Float, StorageTextureAccess::WriteOnly),
-                    storage_buffer::<DataGrid>(false),
-                    storage_buffer::<DataGrid>(false),
-                    storage_buffer::<DataStrip>(false),
-                    storage_buffer::<DataStrip>(false),
-                    texture_storage_2d(TextureFormat::Rgba32Float, StorageTextureAccess::ReadOnly),
-                ),
-            ),
-        );
+// A stage's variant, stages with the same shader and defs share a pipeline
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct StageKey {
+    pub shader: Handle<Shader>,
+    pub shader_defs: Vec<ShaderDefVal>,
+}
 
-        let pipeline_cache = world.resource::<PipelineCache>();
+impl SpecializedComputePipeline for ComputePipelines {
+    type Key = StageKey;
 
-        let shader_configs = shader_configurator.shader_configs.clone();
+    fn specialize(&self, key: StageKey) -> ComputePipelineDescriptor {
+        let mut shader_defs = key.shader_defs;
+        let (layout, push_constant_ranges) = match self.pass_constants {
+            PassConstants::PushConstant => {
+                shader_defs.push("PASS_PUSH_CONSTANT".into());
+                (vec![self.compute_layout.clone()], vec![pass_push_constant_range()])
+            }
+            PassConstants::DynamicUniform => (
+                vec![self.compute_layout.clone(), self.pass_layout.clone()],
+                Vec::new(),
+            ),
+        };
 
-        // Create pipeline for each shader with its iteration count
-        let mut pipeline_configs = Vec::new();
-        for config in shader_configs {
+        ComputePipelineDescriptor {
+            label: Some("compute".into()),
+            layout,
+            push_constant_ranges,
+            shader: key.shader,
+            shader_defs,
+            entry_point: "main".into(),
+            zero_initialize_workgroup_memory: false,
+        }
+    }
+}
 
-            let shader = world.load_asset(config.shader_path);
+impl ComputePipelines {
+    // The pipeline for each stage's current defs, queueing the variants not seen before
+    pub fn specialize_stages(
+        &self,
+        specialized: &mut SpecializedComputePipelines<ComputePipelines>,
+        pipeline_cache: &PipelineCache,
+        configs: &[ShaderConfig],
+    ) -> Vec<CachedComputePipelineId> {
+        self.stage_shaders
+            .iter()
+            .zip(configs)
+            .map(|(shader, config)| {
+                let key = StageKey {
+                    shader: shader.clone(),
+                    shader_defs: config.shader_defs.clone(),
+                };
+                specialized.specialize(pipeline_cache, self, key)
+            })
+            .collect()
+    }
+}
 
-            let pipeline_id = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
-                label: Some("compute".into()),
-                layout: vec![compute_layout.clone()],
-                push_constant_ranges: Vec::new(),
-                shader: shader,
-                shader_defs: Vec::new(),
-                entry_point: "main".into(),
-                zero_initialize_workgroup_memory: false,
-            });
+impl FromWorld for ComputePipelines {
+    fn from_world(world: &mut World) -> Self {
+        let shader_configs = world.resource::<ShaderConfigHolder>().shader_configs.clone();
+        let render_device = world.resource::<RenderDevice>();
+        let compute_layout = render_device.create_bind_group_layout(None, &compute_layout_entries());
+        let extract_layout = render_device.create_bind_group_layout(None, &extract_layout_entries());
+        let pass_layout = render_device.create_bind_group_layout(None, &pass_layout_entries());
+        let pass_constants = PassConstants::for_device(render_device);
 
-            pipeline_configs.push(pipeline_id);
-        }
+        let stage_shaders = shader_configs
+            .iter()
+            .map(|config| world.load_asset(config.shader_path))
+            .collect();
 
+        let pipeline_cache = world.resource::<PipelineCache>();
         let final_pass = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
             label: Some("Final pass".into()),
             layout: vec![extract_layout.clone()],
             push_constant_ranges: Vec::new(),
-            shader: EXTRACT_HANDLE,
+            shader: world.resource::<CommonShaders>().extract.clone(),
             shader_defs: Vec::new(),
             entry_point: "main".into(),
             zero_initialize_workgroup_memory: false,
         });
 
-        ComputePipelines {
+        let mut pipelines = ComputePipelines {
             compute_layout,
             extract_layout,
-            pipeline_configs,
+            pass_layout,
+            pass_constants,
+            stage_shaders,
+            pipeline_configs: Vec::new(),
             final_pass,
-        }
+        };
+
+        world.resource_scope(|world, mut specialized: Mut<SpecializedComputePipelines<Self>>| {
+            let pipeline_cache = world.resource::<PipelineCache>();
+            pipelines.pipeline_configs =
+                pipelines.specialize_stages(&mut specialized, pipeline_cache, &shader_configs);
+        });
+
+        pipelines
     }
-}
\ No newline at end of file
+}
+
+// Re-specialize the stages whose defs were changed in the gui
+pub fn respecialize_stages(
+    configs: Res<ShaderConfigHolder>,
+    mut pipelines: ResMut<ComputePipelines>,
+    mut specialized: ResMut<SpecializedComputePipelines<ComputePipelines>>,
+    pipeline_cache: Res<PipelineCache>,
+) {
+    if !configs.is_changed() {
+        return;
+    }
+    let ids = pipelines.specialize_stages(&mut specialized, &pipeline_cache, &configs.shader_configs);
+    pipelines.pipeline_configs = ids;
+}
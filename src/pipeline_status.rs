@@ -0,0 +1,194 @@
+use std::sync::{Arc, Mutex};
+
+use bevy::{
+    prelude::*,
+    render::{
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        render_resource::{
+            CachedComputePipelineId, CachedPipelineState, PipelineCache, PipelineCacheError,
+        },
+        Render, RenderApp, RenderSet,
+    },
+};
+
+use crate::{pipeline::ComputePipelines, ParamsChanged, ShaderConfigHolder};
+
+#[derive(Resource, ExtractResource, Clone, Default)]
+pub struct PipelineStatusSettings {
+    // skip every stage after the first one that failed to compile, and the final pass, so the
+    // last good result stays on screen instead of a chain with a hole in it
+    pub halt_on_error: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StageState {
+    Queued,
+    Compiling,
+    Ok,
+    Error,
+}
+
+impl StageState {
+    pub fn label(&self) -> &'static str {
+        match self {
+            StageState::Queued => "queued",
+            StageState::Compiling => "compiling",
+            StageState::Ok => "ok",
+            StageState::Error => "error",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct StageStatus {
+    pub name: String,
+    pub state: StageState,
+    // the shader error, or what a queued pipeline is waiting on
+    pub message: Option<String>,
+}
+
+#[derive(Default, Clone)]
+pub struct PipelineStatusInner {
+    // the stages in chain order then the final pass
+    pub stages: Vec<StageStatus>,
+    // the first stage that failed, nothing from it on runs when halting
+    pub halted_at: Option<usize>,
+    pub ready: usize,
+    // counts every time a stage becomes ready, a reloaded shader requeues its pipelines so this
+    // also counts reloads
+    pub compiled: u64,
+}
+
+// Compile state of each pipeline, written by the render world and shown in the gui
+#[derive(Resource, Clone, Default)]
+pub struct PipelineStatus {
+    inner: Arc<Mutex<PipelineStatusInner>>,
+}
+
+impl PipelineStatus {
+    pub fn get(&self) -> PipelineStatusInner {
+        self.inner.lock().unwrap().clone()
+    }
+
+    pub fn compiled(&self) -> u64 {
+        self.inner.lock().unwrap().compiled
+    }
+
+    // Whether a stage is skipped because an earlier one failed, the final pass is stage count
+    pub fn halts(&self, stage: usize) -> bool {
+        self.inner
+            .lock()
+            .unwrap()
+            .halted_at
+            .is_some_and(|failed| failed < stage)
+    }
+}
+
+pub struct PipelineStatusPlugin;
+
+impl Plugin for PipelineStatusPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PipelineStatusSettings>();
+        app.init_resource::<PipelineStatus>();
+        app.add_plugins(ExtractResourcePlugin::<PipelineStatusSettings>::default());
+        app.add_systems(Update, rerun_when_ready);
+    }
+
+    fn finish(&self, app: &mut App) {
+        let status = app.world().resource::<PipelineStatus>().clone();
+
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app.insert_resource(status);
+        render_app.add_systems(Render, update_pipeline_status.in_set(RenderSet::Prepare));
+    }
+}
+
+fn stage_status(
+    pipeline_cache: &PipelineCache,
+    id: CachedComputePipelineId,
+    name: &str,
+) -> StageStatus {
+    let (state, message) = match pipeline_cache.get_compute_pipeline_state(id) {
+        CachedPipelineState::Queued => (StageState::Queued, None),
+        CachedPipelineState::Creating(_) => (StageState::Compiling, None),
+        CachedPipelineState::Ok(_) => (StageState::Ok, None),
+        // the cache requeues these itself once the shader or its import arrives
+        CachedPipelineState::Err(
+            e @ (PipelineCacheError::ShaderNotLoaded(_)
+            | PipelineCacheError::ShaderImportNotYetAvailable),
+        ) => (StageState::Queued, Some(e.to_string())),
+        CachedPipelineState::Err(e) => (StageState::Error, Some(e.to_string())),
+    };
+
+    StageStatus {
+        name: name.to_string(),
+        state,
+        message,
+    }
+}
+
+fn update_pipeline_status(
+    status: Res<PipelineStatus>,
+    settings: Res<PipelineStatusSettings>,
+    pipelines: Option<Res<ComputePipelines>>,
+    pipeline_cache: Res<PipelineCache>,
+    configs: Res<ShaderConfigHolder>,
+) {
+    let Some(pipelines) = pipelines else {
+        return;
+    };
+
+    let mut stages: Vec<StageStatus> = pipelines
+        .pipeline_configs
+        .iter()
+        .zip(&configs.shader_configs)
+        .map(|(id, config)| stage_status(&pipeline_cache, *id, config.name()))
+        .collect();
+    stages.push(stage_status(
+        &pipeline_cache,
+        pipelines.final_pass,
+        "extract",
+    ));
+
+    let halted_at = stages
+        .iter()
+        .position(|s| s.state == StageState::Error)
+        .filter(|_| settings.halt_on_error);
+
+    let mut inner = status.inner.lock().unwrap();
+    let was_ok = |i: usize| {
+        inner
+            .stages
+            .get(i)
+            .is_some_and(|s| s.state == StageState::Ok)
+    };
+    let newly_ok = (0..stages.len())
+        .filter(|i| stages[*i].state == StageState::Ok && !was_ok(*i))
+        .count();
+    inner.compiled += newly_ok as u64;
+    inner.ready = stages.iter().filter(|s| s.state == StageState::Ok).count();
+    inner.stages = stages;
+    inner.halted_at = halted_at;
+}
+
+// A pipeline that finishes after the chain ran left its stage out, and one that was recompiled
+// after a shader reload makes the old output stale, so run the chain again once nothing is
+// still compiling
+fn rerun_when_ready(
+    status: Res<PipelineStatus>,
+    mut changed: ResMut<ParamsChanged>,
+    mut last_compiled: Local<u64>,
+) {
+    let inner = status.inner.lock().unwrap();
+    if inner
+        .stages
+        .iter()
+        .any(|s| s.state == StageState::Compiling)
+    {
+        return;
+    }
+    if inner.compiled > *last_compiled {
+        changed.0 = true;
+    }
+    *last_compiled = inner.compiled;
+}
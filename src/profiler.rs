@@ -0,0 +1,410 @@
+use std::sync::{
+    atomic::{AtomicBool, AtomicU32, Ordering},
+    Arc, Mutex,
+};
+
+use bevy::{
+    prelude::*,
+    render::{
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        render_resource::{
+            Buffer, BufferDescriptor, BufferUsages, CommandEncoderDescriptor, MapMode,
+            WgpuFeatures,
+        },
+        renderer::{render_system, RenderDevice, RenderQueue},
+        Render, RenderApp, RenderSet,
+    },
+};
+use wgpu::{
+    ComputePassTimestampWrites, QuerySet, QuerySetDescriptor, QueryType, QUERY_SET_MAX_QUERIES,
+};
+
+use crate::{data_structures::ShaderConfig, ParamsChanged, ShaderConfigHolder};
+
+// The passes one run can dispatch, every iteration of every stage and the extract pass, for both
+// the live and the comparison chain
+pub fn pass_count(configs: &[ShaderConfig]) -> u32 {
+    let chain = configs.iter().map(|c| c.iterations).sum::<u32>() + 1;
+    chain * 2
+}
+
+// the query set is sized to the chain, up to the most queries one set can hold
+fn query_capacity(configs: &[ShaderConfig]) -> u32 {
+    pass_count(configs).min(QUERY_SET_MAX_QUERIES / 2)
+}
+
+// each pass writes a begin and end timestamp
+const TIMESTAMP_SIZE: u64 = std::mem::size_of::<u64>() as u64;
+
+// Identifies the compute pass a pair of timestamps belongs to
+#[derive(Clone, Copy, Debug)]
+pub struct PassLabel {
+    // None for the extract pass
+    pub stage: Option<usize>,
+    pub iteration: u32,
+    pub compare: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PassTiming {
+    pub label: PassLabel,
+    // relative to the start of the first pass of the frame
+    pub start_ms: f64,
+    pub duration_ms: f64,
+}
+
+#[derive(Resource, ExtractResource, Clone, Default)]
+pub struct ProfilerSettings {
+    pub enabled: bool,
+    // re-run the chain every frame so the timings keep updating
+    pub continuous: bool,
+}
+
+#[derive(Default)]
+struct TimingsInner {
+    supported: bool,
+    passes: Vec<PassTiming>,
+    // passes past the end of the query set in the last run
+    untimed: u32,
+}
+
+// The most recent set of pass timings, shared between the main and render worlds
+#[derive(Resource, Clone, Default)]
+pub struct StageTimings {
+    inner: Arc<Mutex<TimingsInner>>,
+}
+
+impl StageTimings {
+    pub fn supported(&self) -> bool {
+        self.inner.lock().unwrap().supported
+    }
+
+    pub fn passes(&self) -> Vec<PassTiming> {
+        self.inner.lock().unwrap().passes.clone()
+    }
+
+    pub fn untimed(&self) -> u32 {
+        self.inner.lock().unwrap().untimed
+    }
+}
+
+// Per stage totals, in chain order with the extract pass last
+pub struct StageSummary {
+    pub name: String,
+    pub passes: u32,
+    pub total_ms: f64,
+}
+
+pub fn summarize(passes: &[PassTiming], configs: &[ShaderConfig]) -> Vec<StageSummary> {
+    let mut summaries: Vec<StageSummary> = Vec::new();
+
+    for pass in passes {
+        let name = pass_name(&pass.label, configs);
+        match summaries.iter_mut().find(|s| s.name == name) {
+            Some(summary) => {
+                summary.passes += 1;
+                summary.total_ms += pass.duration_ms;
+            }
+            None => summaries.push(StageSummary {
+                name,
+                passes: 1,
+                total_ms: pass.duration_ms,
+            }),
+        }
+    }
+
+    summaries
+}
+
+fn pass_name(label: &PassLabel, configs: &[ShaderConfig]) -> String {
+    let name = match label.stage {
+        Some(stage) => configs
+            .get(stage)
+            .map(|c| c.name().to_string())
+            .unwrap_or_else(|| format!("stage {}", stage)),
+        None => "extract".to_string(),
+    };
+
+    if label.compare {
+        format!("{} (compare)", name)
+    } else {
+        name
+    }
+}
+
+/// Chrome trace event format, load it in chrome://tracing or https://ui.perfetto.dev
+pub fn chrome_trace(passes: &[PassTiming], configs: &[ShaderConfig]) -> String {
+    let events: Vec<String> = passes
+        .iter()
+        .map(|pass| {
+            format!(
+                r#"{{"name":"{}","cat":"gpu","ph":"X","ts":{:.3},"dur":{:.3},"pid":0,"tid":{},"args":{{"iteration":{}}}}}"#,
+                pass_name(&pass.label, configs),
+                pass.start_ms * 1000.0,
+                pass.duration_ms * 1000.0,
+                pass.label.compare as u32,
+                pass.label.iteration,
+            )
+        })
+        .collect();
+
+    format!(r#"{{"traceEvents":[{}]}}"#, events.join(","))
+}
+
+#[derive(Resource)]
+pub struct StageProfiler {
+    query_set: QuerySet,
+    resolve_buffer: Buffer,
+    readback_buffer: Buffer,
+    period_ns: f32,
+    // passes the query set has room for
+    capacity: u32,
+    labels: Mutex<Vec<PassLabel>>,
+    untimed: AtomicU32,
+    // set while the readback buffer is mapped, no timestamps are written until it's released
+    mapping: Arc<AtomicBool>,
+}
+
+impl StageProfiler {
+    // Reserve a pair of timestamps for a compute pass
+    pub fn timestamp_writes(&self, label: PassLabel) -> Option<ComputePassTimestampWrites<'_>> {
+        if self.mapping.load(Ordering::Acquire) {
+            return None;
+        }
+
+        let mut labels = self.labels.lock().unwrap();
+        let index = labels.len() as u32;
+        if index >= self.capacity {
+            self.untimed.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        labels.push(label);
+
+        Some(ComputePassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(index * 2),
+            end_of_pass_write_index: Some(index * 2 + 1),
+        })
+    }
+}
+
+pub struct ProfilerPlugin;
+
+impl Plugin for ProfilerPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ProfilerSettings>();
+        app.init_resource::<StageTimings>();
+        app.add_plugins(ExtractResourcePlugin::<ProfilerSettings>::default());
+        app.add_systems(Update, rerun_continuously);
+    }
+
+    fn finish(&self, app: &mut App) {
+        let timings = app.world().resource::<StageTimings>().clone();
+        let capacity = query_capacity(&app.world().resource::<ShaderConfigHolder>().shader_configs);
+
+        let render_app = app.sub_app_mut(RenderApp);
+        let render_device = render_app.world().resource::<RenderDevice>();
+
+        // timestamp queries are optional, without them the profiler just reports as unsupported
+        if render_device.features().contains(WgpuFeatures::TIMESTAMP_QUERY) {
+            let period_ns = render_app.world().resource::<RenderQueue>().get_timestamp_period();
+            let profiler = create_profiler(render_device, period_ns, capacity);
+            render_app.insert_resource(profiler);
+            timings.inner.lock().unwrap().supported = true;
+        }
+
+        render_app.insert_resource(timings);
+        render_app.add_systems(
+            Render,
+            (
+                resize_profiler.in_set(RenderSet::PrepareResources),
+                resolve_timestamps
+                    .after(render_system)
+                    .in_set(RenderSet::Render),
+            ),
+        );
+    }
+}
+
+fn create_profiler(render_device: &RenderDevice, period_ns: f32, capacity: u32) -> StageProfiler {
+    let query_set = render_device
+        .wgpu_device()
+        .create_query_set(&QuerySetDescriptor {
+            label: Some("stage timestamps"),
+            ty: QueryType::Timestamp,
+            count: capacity * 2,
+        });
+
+    let size = capacity as u64 * 2 * TIMESTAMP_SIZE;
+
+    let resolve_buffer = render_device.create_buffer(&BufferDescriptor {
+        label: Some("stage timestamps resolve"),
+        size,
+        usage: BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+
+    let readback_buffer = render_device.create_buffer(&BufferDescriptor {
+        label: Some("stage timestamps readback"),
+        size,
+        usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    StageProfiler {
+        query_set,
+        resolve_buffer,
+        readback_buffer,
+        period_ns,
+        capacity,
+        labels: Mutex::new(Vec::new()),
+        untimed: AtomicU32::new(0),
+        mapping: Arc::new(AtomicBool::new(false)),
+    }
+}
+
+// Recreate the query set when the iteration counts change how many passes there are, a readback
+// still in flight keeps its own buffer
+fn resize_profiler(
+    profiler: Option<ResMut<StageProfiler>>,
+    configs: Res<ShaderConfigHolder>,
+    render_device: Res<RenderDevice>,
+) {
+    let Some(mut profiler) = profiler else {
+        return;
+    };
+
+    let capacity = query_capacity(&configs.shader_configs);
+    if capacity != profiler.capacity {
+        *profiler = create_profiler(&render_device, profiler.period_ns, capacity);
+    }
+}
+
+fn rerun_continuously(settings: Res<ProfilerSettings>, mut changed: ResMut<ParamsChanged>) {
+    if settings.enabled && settings.continuous {
+        changed.0 = true;
+    }
+}
+
+// Resolve the timestamps written by this frame's passes and read them back asynchronously
+fn resolve_timestamps(
+    profiler: Option<Res<StageProfiler>>,
+    timings: Res<StageTimings>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    let Some(profiler) = profiler else {
+        return;
+    };
+
+    let labels = std::mem::take(&mut *profiler.labels.lock().unwrap());
+    if labels.is_empty() {
+        return;
+    }
+    timings.inner.lock().unwrap().untimed = profiler.untimed.swap(0, Ordering::Relaxed);
+
+    let query_count = labels.len() as u32 * 2;
+    let size = query_count as u64 * TIMESTAMP_SIZE;
+
+    let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("resolve stage timestamps"),
+    });
+    encoder.resolve_query_set(&profiler.query_set, 0..query_count, &profiler.resolve_buffer, 0);
+    encoder.copy_buffer_to_buffer(&profiler.resolve_buffer, 0, &profiler.readback_buffer, 0, size);
+    render_queue.submit([encoder.finish()]);
+
+    profiler.mapping.store(true, Ordering::Release);
+
+    let buffer = profiler.readback_buffer.clone();
+    let mapping = profiler.mapping.clone();
+    let period_ns = profiler.period_ns as f64;
+    let timings = timings.inner.clone();
+
+    profiler
+        .readback_buffer
+        .slice(0..size)
+        .map_async(MapMode::Read, move |result| {
+            if result.is_ok() {
+                {
+                    let data = buffer.slice(0..size).get_mapped_range();
+                    let stamps: Vec<u64> = bytemuck::pod_collect_to_vec(&data);
+
+                    let origin = stamps[0];
+                    let to_ms = |ticks: u64| ticks as f64 * period_ns / 1_000_000.0;
+
+                    let passes = labels
+                        .iter()
+                        .zip(stamps.chunks_exact(2))
+                        .map(|(label, pair)| PassTiming {
+                            label: *label,
+                            start_ms: to_ms(pair[0].saturating_sub(origin)),
+                            duration_ms: to_ms(pair[1].saturating_sub(pair[0])),
+                        })
+                        .collect();
+
+                    timings.lock().unwrap().passes = passes;
+                }
+                buffer.unmap();
+            }
+            mapping.store(false, Ordering::Release);
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute_plugin::default_shader_configs;
+
+    fn pass(stage: Option<usize>, iteration: u32, compare: bool, start_ms: f64) -> PassTiming {
+        PassTiming {
+            label: PassLabel {
+                stage,
+                iteration,
+                compare,
+            },
+            start_ms,
+            duration_ms: 0.25,
+        }
+    }
+
+    #[test]
+    fn timings_are_summed_per_stage_and_exported() {
+        let configs = default_shader_configs();
+        let passes = [
+            pass(Some(1), 0, false, 0.0),
+            pass(Some(1), 1, false, 0.5),
+            pass(Some(6), 0, false, 1.0),
+            pass(None, 0, false, 1.5),
+            pass(Some(1), 0, true, 2.0),
+        ];
+
+        let summaries = summarize(&passes, &configs);
+        let rows: Vec<(&str, u32, f64)> = summaries
+            .iter()
+            .map(|s| (s.name.as_str(), s.passes, s.total_ms))
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                ("erode_thermal", 2, 0.5),
+                ("ca_run", 1, 0.25),
+                ("extract", 1, 0.25),
+                ("erode_thermal (compare)", 1, 0.25),
+            ]
+        );
+
+        let trace = chrome_trace(&passes[3..], &configs);
+        assert_eq!(
+            trace,
+            concat!(
+                r#"{"traceEvents":["#,
+                r#"{"name":"extract","cat":"gpu","ph":"X","ts":1500.000,"dur":250.000,"pid":0,"tid":0,"args":{"iteration":0}},"#,
+                r#"{"name":"erode_thermal (compare)","cat":"gpu","ph":"X","ts":2000.000,"dur":250.000,"pid":0,"tid":1,"args":{"iteration":0}}"#,
+                r#"]}"#
+            )
+        );
+
+        // 266 stage passes and the extract pass, for each chain
+        assert_eq!(pass_count(&configs), 534);
+    }
+}
@@ -7,7 +7,7 @@ use bevy::{
 };
 
 use bevy_egui::egui::Color32;
-use crate::{data_structures::ShaderConfig, gradient_editor};
+use crate::{data_structures::ShaderConfig, gradient_editor, parameters::{ParamsUniform, PassInfo}};
 
 #[derive(Resource, ExtractResource, Clone)]
 pub struct ParamsChanged(pub bool);
@@ -37,6 +37,31 @@ impl Default for Gradients {
     }
 }
 
+// The shaders the stages import and the final pass. They're loaded as assets so the file watcher
+// reloads them, and the handles are kept here so the imports stay loaded
+#[derive(Resource, Clone)]
+pub struct CommonShaders {
+    pub common: Handle<Shader>,
+    pub noise: Handle<Shader>,
+    pub utils: Handle<Shader>,
+    pub anal_noise: Handle<Shader>,
+    pub pass: Handle<Shader>,
+    pub extract: Handle<Shader>,
+}
+
+impl CommonShaders {
+    // the shaders the stages import, by the path they define with #define_import_path
+    pub fn imports(&self) -> [(&'static str, &Handle<Shader>); 5] {
+        [
+            ("compute::common", &self.common),
+            ("compute::noise", &self.noise),
+            ("compute::utils", &self.utils),
+            ("compute::anoise", &self.anal_noise),
+            ("compute::pass", &self.pass),
+        ]
+    }
+}
+
 #[derive(Resource, ExtractResource, Clone)]
 pub struct ImageBufferContainer {
     pub tex_buffer_a1: Handle<Image>,
@@ -46,10 +71,15 @@ pub struct ImageBufferContainer {
     pub tex_buffer_a3: Handle<Image>,
     pub tex_buffer_b3: Handle<Image>,
     pub result: Handle<Image>,
+    pub compare: Handle<Image>,
     pub grid_buffer_a: Handle<ShaderStorageBuffer>,
     pub grid_buffer_b: Handle<ShaderStorageBuffer>,
     pub strip_buffer_a: Handle<ShaderStorageBuffer>,
     pub strip_buffer_b: Handle<ShaderStorageBuffer>,
+    pub rock_mask: Handle<ShaderStorageBuffer>,
+    pub edit_buffer: Handle<ShaderStorageBuffer>,
+    pub ore_buffer: Handle<ShaderStorageBuffer>,
+    pub convergence: Handle<ShaderStorageBuffer>,
     pub grad_texture: Handle<Image>,
 }
 
@@ -58,7 +88,23 @@ pub struct GpuBufferBindGroups {
     pub bind_groups: Vec<BindGroup>,
     pub final_pass_a: BindGroup,
     pub final_pass_b: BindGroup,
+    pub compare_bind_groups: Vec<BindGroup>,
+    pub compare_final_pass_a: BindGroup,
+    pub compare_final_pass_b: BindGroup,
     pub uniform_buffer: Buffer,
+    pub compare_uniform_buffer: Buffer,
+    pub view_uniform_buffer: Buffer,
+    pub material_uniform_buffer: Buffer,
+}
+
+// The PassInfo of every dispatch when the device has no push constants, bound in group 1 with
+// the dispatch's offset
+#[derive(Resource, Default)]
+pub struct PassUniforms {
+    pub buffer: DynamicUniformBuffer<PassInfo>,
+    // by stage then iteration
+    pub offsets: Vec<Vec<u32>>,
+    pub bind_group: Option<BindGroup>,
 }
 
 #[derive(Resource)]
@@ -71,4 +117,53 @@ pub struct BindGroupSelection {
 #[derive(Resource, Clone, ExtractResource)]
 pub struct ShaderConfigHolder {
     pub shader_configs: Vec<ShaderConfig>,
-}
\ No newline at end of file
+}
+
+impl ShaderConfigHolder {
+    // look a stage up by its shader's file stem
+    pub fn stage(&self, name: &str) -> Option<&ShaderConfig> {
+        self.shader_configs.iter().find(|c| c.name() == name)
+    }
+
+    pub fn stage_mut(&mut self, name: &str) -> Option<&mut ShaderConfig> {
+        self.shader_configs.iter_mut().find(|c| c.name() == name)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CompareMode {
+    Off,
+    SideBySide,
+    Wipe,
+}
+
+#[derive(Resource, ExtractResource, Clone)]
+pub struct Comparison {
+    pub mode: CompareMode,
+    // position of the wipe across the image, 0 shows only the live result
+    pub split: f32,
+    // parameters the snapshot was taken with
+    pub snapshot: Option<ParamsUniform>,
+    // run the snapshot parameters through the pipeline alongside the live ones
+    pub rerun_snapshot: bool,
+    // bumped to request a copy of the current result into the compare texture
+    pub snapshot_generation: u32,
+}
+
+impl Default for Comparison {
+    fn default() -> Self {
+        Self {
+            mode: CompareMode::Off,
+            split: 0.5,
+            snapshot: None,
+            rerun_snapshot: false,
+            snapshot_generation: 0,
+        }
+    }
+}
+
+impl Comparison {
+    pub fn runs_snapshot(&self) -> bool {
+        self.rerun_snapshot && self.snapshot.is_some()
+    }
+}
@@ -0,0 +1,370 @@
+/*
+Shader validation tests. Each stage shader and extract.wgsl is composed with naga_oil the way
+bevy's pipeline cache does it, with the shaders from load_common_shaders resolving the compute::
+imports, then validated with naga and its bindings checked against the bind group layouts in
+pipeline.rs. At runtime a shader that fails any of these shows up as an error in the Pipelines
+window, so run
+
+    cargo test shaders
+
+after touching a shader, a binding or one of the uniform structs.
+*/
+
+use std::{collections::HashMap, path::Path};
+
+use bevy::render::render_resource::{
+    BindGroupLayoutEntry, BindingType, BufferBindingType, Shader, ShaderDefVal, ShaderImport,
+    StorageTextureAccess, TextureFormat,
+};
+use naga::{
+    valid::{Capabilities, ValidationFlags, Validator},
+    AddressSpace, ImageClass, Module, ShaderStage, StorageAccess, StorageFormat, TypeInner,
+};
+use naga_oil::compose::{Composer, NagaModuleDescriptor, ShaderDefValue};
+
+use crate::{
+    ca_rules::{CaRule, Neighbourhood},
+    compute_plugin::default_shader_configs,
+    data_structures::format_shader_defs,
+    pipeline::{
+        compute_layout_entries, extract_layout_entries, pass_layout_entries,
+        pass_push_constant_range,
+    },
+};
+
+// A shader from assets/ by the path the asset server loads it with
+fn load_shader(path: &str) -> Shader {
+    let full_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("assets")
+        .join(path);
+    let source = std::fs::read_to_string(&full_path)
+        .unwrap_or_else(|e| panic!("{}: {}", full_path.display(), e));
+    Shader::from_wgsl(source, path.to_string())
+}
+
+// the shaders load_common_shaders loads for the stages to import
+fn common_shaders() -> Vec<Shader> {
+    [
+        "shaders/common.wgsl",
+        "shaders/utils/noise.wgsl",
+        "shaders/utils/utils.wgsl",
+        "shaders/utils/anal_noise.wgsl",
+        "shaders/utils/pass.wgsl",
+    ]
+    .into_iter()
+    .map(load_shader)
+    .collect()
+}
+
+const CAPABILITIES: Capabilities = Capabilities::PUSH_CONSTANT;
+
+struct ShaderCompiler {
+    composer: Composer,
+    imports: HashMap<ShaderImport, Shader>,
+}
+
+impl ShaderCompiler {
+    fn new() -> Self {
+        Self {
+            // bevy validates while composing in debug builds, with the capabilities of the
+            // device's features, push constants are one of them on native
+            composer: Composer::default().with_capabilities(CAPABILITIES),
+            imports: common_shaders()
+                .into_iter()
+                .map(|shader| (shader.import_path().clone(), shader))
+                .collect(),
+        }
+    }
+
+    // Add an import and everything it imports, as PipelineCache's add_import_to_composer does
+    fn add_import(&mut self, import: &ShaderImport) -> Result<(), String> {
+        if self.composer.contains_module(&import.module_name()) {
+            return Ok(());
+        }
+        // a missing import is reported by the composer when the stage is composed
+        let Some(shader) = self.imports.get(import).cloned() else {
+            return Ok(());
+        };
+        for nested in shader.imports() {
+            self.add_import(nested)?;
+        }
+        self.composer
+            .add_composable_module((&shader).into())
+            .map(|_| ())
+            .map_err(|e| e.emit_to_string(&self.composer))
+    }
+
+    // Compose a variant of the shader, with its defs converted as the pipeline cache does
+    fn compile(&mut self, shader: &Shader, shader_defs: &[ShaderDefVal]) -> Result<Module, String> {
+        for import in shader.imports() {
+            self.add_import(import)?;
+        }
+        let mut descriptor = NagaModuleDescriptor::from(shader);
+        descriptor.shader_defs = shader_defs
+            .iter()
+            .map(|def| match def.clone() {
+                ShaderDefVal::Bool(name, v) => (name, ShaderDefValue::Bool(v)),
+                ShaderDefVal::Int(name, v) => (name, ShaderDefValue::Int(v)),
+                ShaderDefVal::UInt(name, v) => (name, ShaderDefValue::UInt(v)),
+            })
+            .collect();
+        let module = self
+            .composer
+            .make_naga_module(descriptor)
+            .map_err(|e| e.emit_to_string(&self.composer))?;
+
+        Validator::new(ValidationFlags::all(), CAPABILITIES)
+            .validate(&module)
+            .map_err(|e| format!("{:?}", e.into_inner()))?;
+        Ok(module)
+    }
+}
+
+// A stage shader composed without defs, for tests that check what it reads
+pub fn compile_stage(path: &str) -> Module {
+    ShaderCompiler::new()
+        .compile(&load_shader(path), &[])
+        .unwrap_or_else(|e| panic!("{}: {}", path, e))
+}
+
+fn storage_format(format: TextureFormat) -> Option<StorageFormat> {
+    match format {
+        TextureFormat::Rgba32Float => Some(StorageFormat::Rgba32Float),
+        TextureFormat::Rgba16Float => Some(StorageFormat::Rgba16Float),
+        TextureFormat::Rgba8Unorm => Some(StorageFormat::Rgba8Unorm),
+        TextureFormat::R32Float => Some(StorageFormat::R32Float),
+        TextureFormat::R32Uint => Some(StorageFormat::R32Uint),
+        _ => None,
+    }
+}
+
+fn texture_access(access: StorageTextureAccess) -> StorageAccess {
+    match access {
+        StorageTextureAccess::ReadOnly => StorageAccess::LOAD,
+        StorageTextureAccess::WriteOnly => StorageAccess::STORE,
+        StorageTextureAccess::ReadWrite => StorageAccess::LOAD | StorageAccess::STORE,
+    }
+}
+
+// Everything wgpu would reject when creating the pipeline with these layouts, one per group.
+// Bindings the entry point doesn't use are checked too so the headers stay interchangeable
+fn check_bindings(module: &Module, layouts: &[&[BindGroupLayoutEntry]]) -> Vec<String> {
+    let mut errors = Vec::new();
+
+    let has_main = module
+        .entry_points
+        .iter()
+        .any(|e| e.name == "main" && e.stage == ShaderStage::Compute);
+    if !has_main {
+        errors.push("no compute entry point called main".to_string());
+    }
+
+    for (_, global) in module.global_variables.iter() {
+        let name = global.name.as_deref().unwrap_or("?");
+        let size = module.types[global.ty].inner.size(module.to_ctx()) as u64;
+
+        if global.space == AddressSpace::PushConstant {
+            let range = pass_push_constant_range().range;
+            if size > range.end as u64 {
+                errors.push(format!(
+                    "{} is {} bytes of push constants, the range is {}",
+                    name, size, range.end
+                ));
+            }
+            continue;
+        }
+        let Some(binding) = &global.binding else {
+            continue;
+        };
+        let at = format!(
+            "{} @group({}) @binding({})",
+            name, binding.group, binding.binding
+        );
+
+        let Some(entry) = layouts
+            .get(binding.group as usize)
+            .and_then(|layout| layout.iter().find(|e| e.binding == binding.binding))
+        else {
+            errors.push(format!("{} isn't in the layout", at));
+            continue;
+        };
+
+        let inner = &module.types[global.ty].inner;
+        let min_size = |min_binding_size: Option<std::num::NonZeroU64>| {
+            min_binding_size.map_or(0, |s| s.get())
+        };
+
+        match (global.space, entry.ty) {
+            (
+                AddressSpace::Uniform,
+                BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    min_binding_size,
+                    ..
+                },
+            ) => {
+                if min_size(min_binding_size) != size {
+                    errors.push(format!(
+                        "{} is {} bytes, the layout's uniform is {}",
+                        at,
+                        size,
+                        min_size(min_binding_size)
+                    ));
+                }
+            }
+            (
+                AddressSpace::Storage { access },
+                BindingType::Buffer {
+                    ty: BufferBindingType::Storage { read_only },
+                    min_binding_size,
+                    ..
+                },
+            ) => {
+                if read_only && access.contains(StorageAccess::STORE) {
+                    errors.push(format!("{} writes to a read only buffer", at));
+                }
+                // an unsized binding only has to be as long as the shader's fixed part
+                if min_binding_size.is_some() && min_size(min_binding_size) < size {
+                    errors.push(format!(
+                        "{} is {} bytes, the layout's buffer is {}",
+                        at,
+                        size,
+                        min_size(min_binding_size)
+                    ));
+                }
+            }
+            (AddressSpace::Handle, BindingType::StorageTexture { access, format, .. }) => {
+                let TypeInner::Image {
+                    class:
+                        ImageClass::Storage {
+                            format: shader_format,
+                            access: shader_access,
+                        },
+                    ..
+                } = inner
+                else {
+                    errors.push(format!("{} isn't a storage texture", at));
+                    continue;
+                };
+                if storage_format(format) != Some(*shader_format) {
+                    errors.push(format!(
+                        "{} is {:?}, the layout's texture is {:?}",
+                        at, shader_format, format
+                    ));
+                }
+                if texture_access(access) != *shader_access {
+                    errors.push(format!(
+                        "{} is {:?}, the layout's texture is {:?}",
+                        at, shader_access, access
+                    ));
+                }
+            }
+            (space, ty) => errors.push(format!("{} is {:?}, the layout has {:?}", at, space, ty)),
+        }
+    }
+    errors
+}
+
+fn check_shader(
+    compiler: &mut ShaderCompiler,
+    shader: &Shader,
+    shader_defs: &[ShaderDefVal],
+    layouts: &[&[BindGroupLayoutEntry]],
+) -> Result<(), String> {
+    let module = compiler.compile(shader, shader_defs)?;
+    let errors = check_bindings(&module, layouts);
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join("\n  "))
+    }
+}
+
+#[test]
+fn shaders_compose_validate_and_match_the_layouts() {
+    let mut compiler = ShaderCompiler::new();
+    let compute_layout = compute_layout_entries();
+    let pass_layout = pass_layout_entries();
+    let mut failures = Vec::new();
+
+    for config in default_shader_configs() {
+        let shader = load_shader(config.shader_path);
+        // the stage's variant, and the bare shader the gui gets when every def is removed
+        let mut variants = vec![config.shader_defs.clone()];
+        if !config.shader_defs.is_empty() {
+            variants.push(Vec::new());
+        }
+        // and every rule ca_run can be set to
+        if config.name() == "ca_run" {
+            for neighbourhood in Neighbourhood::ALL {
+                let classic = CaRule::Classic {
+                    birth: 0,
+                    survive: 0,
+                    neighbourhood,
+                };
+                variants.push(classic.shader_defs());
+                variants.push(CaRule::Smoothing { neighbourhood }.shader_defs());
+            }
+        }
+        // each with PassInfo in group 1 as on the web, and as a push constant as on native
+        let with_push_constant: Vec<Vec<ShaderDefVal>> = variants
+            .iter()
+            .map(|defs| [defs.clone(), vec!["PASS_PUSH_CONSTANT".into()]].concat())
+            .collect();
+        let layouts = variants
+            .into_iter()
+            .map(|defs| (defs, vec![&compute_layout[..], &pass_layout[..]]))
+            .chain(
+                with_push_constant
+                    .into_iter()
+                    .map(|defs| (defs, vec![&compute_layout[..]])),
+            );
+
+        for (defs, layouts) in layouts {
+            if let Err(e) = check_shader(&mut compiler, &shader, &defs, &layouts) {
+                failures.push(format!(
+                    "{} [{}]:\n  {}",
+                    config.shader_path,
+                    format_shader_defs(&defs),
+                    e
+                ));
+            }
+        }
+    }
+
+    let extract = load_shader("shaders/extract.wgsl");
+    if let Err(e) = check_shader(&mut compiler, &extract, &[], &[&extract_layout_entries()]) {
+        failures.push(format!("shaders/extract.wgsl:\n  {}", e));
+    }
+
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[test]
+fn shaders_with_bad_bindings_name_them() {
+    let mut compiler = ShaderCompiler::new();
+    let shader = Shader::from_wgsl(
+        "#import compute::common::Params
+        @group(0) @binding(0) var<uniform> params: Params;
+        @group(0) @binding(1) var itex: texture_storage_2d<rgba32float, write>;
+        @group(0) @binding(16) var<uniform> extra: vec4f;
+
+        @compute @workgroup_size(8, 8, 1)
+        fn main(@builtin(global_invocation_id) id: vec3u) {
+            textureStore(itex, id.xy, vec4f(params.radius) + extra);
+        }",
+        "broken.wgsl",
+    );
+    let error =
+        check_shader(&mut compiler, &shader, &[], &[&compute_layout_entries()]).unwrap_err();
+    assert!(error.contains("itex @group(0) @binding(1)"), "{}", error);
+    assert!(
+        error.contains("extra @group(0) @binding(16) isn't in the layout"),
+        "{}",
+        error
+    );
+    assert!(!error.contains("params"), "{}", error);
+
+    let syntax = Shader::from_wgsl("fn main( {", "syntax.wgsl");
+    assert!(check_shader(&mut compiler, &syntax, &[], &[&compute_layout_entries()]).is_err());
+}
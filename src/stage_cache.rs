@@ -0,0 +1,908 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use bevy::{
+    prelude::*,
+    render::{
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        render_asset::RenderAssets,
+        render_resource::{
+            Buffer, BufferDescriptor, BufferUsages, CommandEncoder, Extent3d, PipelineCache,
+            Texture, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+        },
+        renderer::RenderDevice,
+        storage::{GpuShaderStorageBuffer, ShaderStorageBuffer},
+        texture::GpuImage,
+        Render, RenderApp, RenderSet,
+    },
+};
+
+use crate::{
+    constants::*,
+    edits::TerrainEdits,
+    materials::MaterialTable,
+    ores::{ore_counts_range, OreTable},
+    parameters::{ParamsUniform, ViewParams},
+    pipeline::ComputePipelines,
+    pipeline_status::PipelineStatus,
+    profiler::ProfilerSettings,
+    Gradients, ImageBufferContainer, ParamsChanged, ShaderConfigHolder,
+};
+
+const MEGABYTE: u64 = 1024 * 1024;
+
+// Each stage is cached under a running hash of the parameters and configs it and the stages
+// before it depend on, along with copies of what it wrote. A run copies back the entries before
+// the first stage that has none and restarts the chain there. The extract pass is cached the
+// same way on top of the last stage, it's the only one the view and the gradient go in to
+#[derive(Resource, ExtractResource, Clone)]
+pub struct StageCacheSettings {
+    pub enabled: bool,
+    pub budget_mb: u32,
+    // one per stage, in chain order
+    pub keys: Vec<u64>,
+    pub extract_key: u64,
+}
+
+impl Default for StageCacheSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            // a little over what every stage of the default chain takes at once
+            budget_mb: 2048,
+            keys: Vec::new(),
+            extract_key: 0,
+        }
+    }
+}
+
+#[derive(Default, Clone, Copy)]
+pub struct CacheStatsInner {
+    pub entries: usize,
+    pub bytes: u64,
+    // stages, and extract passes, copied back from an entry and ones that ran
+    pub hits: u32,
+    pub misses: u32,
+    // the stage the last run restarted at, the stage count when only the extract pass ran
+    pub restart: Option<usize>,
+}
+
+// Cache occupancy, written by the render world and shown in the gui
+#[derive(Resource, Clone, Default)]
+pub struct StageCacheStats {
+    inner: Arc<Mutex<CacheStatsInner>>,
+}
+
+impl StageCacheStats {
+    pub fn get(&self) -> CacheStatsInner {
+        *self.inner.lock().unwrap()
+    }
+}
+
+// The state of the live chain a stage can leave behind for the ones after it
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ChainOutput {
+    // both sides of ping-pong texture 1, 2 or 3
+    Texture(usize),
+    GridA,
+    // both strips
+    Strips,
+    RockMask,
+    // the counts of the ore buffer, the definitions are uploaded every frame
+    OreCounts,
+    // the stage's own part of the live change counts
+    Convergence,
+}
+
+// What each stage's shader writes. A stage this doesn't know writes everything
+pub fn stage_outputs(stage: &str) -> &'static [ChainOutput] {
+    use ChainOutput::*;
+
+    match stage {
+        "init_generate_heights" | "erode_thermal" | "erode_hydraulic" => &[Strips],
+        "init_generate_circle" => &[Texture(1), GridA],
+        "domain_warp_1" => &[Texture(1)],
+        "ca_prepare" | "domain_warp_2" => &[Texture(2)],
+        "ca_run" => &[Texture(2), Convergence],
+        "subtract_caves" => &[Texture(1), Texture(2), GridA],
+        "apply_edits" => &[Texture(1), Texture(2), Texture(3), GridA, RockMask],
+        "jump_flood_prepare" => &[Texture(1), Texture(2), Texture(3)],
+        "jump_flood_run" | "liquid_prepare" => &[Texture(1), Texture(2), Texture(3), GridA],
+        "classify_materials" | "place_ores" => {
+            &[Texture(1), Texture(2), Texture(3), GridA, OreCounts]
+        }
+        "liquid_run" => &[Texture(1), Texture(2), Texture(3), GridA, Convergence],
+        _ => &[
+            Texture(1),
+            Texture(2),
+            Texture(3),
+            GridA,
+            Strips,
+            RockMask,
+            OreCounts,
+            Convergence,
+        ],
+    }
+}
+
+// the named fields of the uniform as bytes to hash
+macro_rules! fields {
+    ($params:ident: $($field:ident),* $(,)?) => {
+        Some(vec![$((stringify!($field), bytemuck::bytes_of(&$params.$field))),*])
+    };
+}
+
+// The fields of the uniform each stage's shader reads, None for a stage this doesn't know,
+// which depends on all of them
+pub fn stage_params<'a>(
+    stage: &str,
+    params: &'a ParamsUniform,
+) -> Option<Vec<(&'static str, &'a [u8])>> {
+    let p = params;
+    match stage {
+        "init_generate_heights" => fields!(p: seed, noise_freq, noise_octaves, noise_lacunarity,
+            flatness, steepness, mix, misc_f),
+        "erode_thermal" => fields!(p: noise_amplitude, radius, talus_angle),
+        "erode_hydraulic" => fields!(p: rain, evaporation, sediment_capacity),
+        "init_generate_circle" => fields!(p: dimensions, radius, noise_amplitude, power_bias,
+            flatness, steepness, tile_origin, tile_scale),
+        "domain_warp_1" => fields!(p: dimensions, seed, misc_f, domain_warp_1_amount_a,
+            domain_warp_1_scale_a, domain_warp_1_amount_b, domain_warp_1_scale_b, tile_origin,
+            tile_scale, tile_warp_limit),
+        "ca_prepare" => fields!(p: dimensions, seed, noise_weight, tile_origin, tile_scale),
+        "ca_run" => fields!(p: dimensions, ca_thresh, ca_search_radius, ca_edge_pow,
+            edge_suppress_mix, ca_birth, ca_survive),
+        "domain_warp_2" => fields!(p: dimensions, seed, domain_warp_2_amount_a,
+            domain_warp_2_scale_a, domain_warp_2_amount_b, domain_warp_2_scale_b, tile_origin,
+            tile_scale, tile_warp_limit),
+        "subtract_caves" | "jump_flood_prepare" => fields!(p: dimensions),
+        "apply_edits" => fields!(p: dimensions, tile_origin, tile_scale),
+        "jump_flood_run" => fields!(p: dimensions, jfa_steps, jfa_pass_count),
+        "classify_materials" => fields!(p: dimensions, tile_scale, crust_depth),
+        "place_ores" => fields!(p: dimensions, seed, tile_origin, tile_scale),
+        "liquid_prepare" => fields!(p: dimensions, seed, tile_origin, tile_scale, liquid_fill,
+            lava_depth),
+        "liquid_run" => fields!(p: dimensions, seed, tile_origin, tile_scale, water_viscosity,
+            lava_viscosity),
+        "extract" => fields!(p: dimensions, tile_scale),
+        _ => None,
+    }
+}
+
+// Where an output lives in the live chain
+#[derive(Clone, Copy)]
+enum Region<'a> {
+    Texture(&'a Texture),
+    Buffer {
+        buffer: &'a Buffer,
+        offset: u64,
+        size: u64,
+    },
+}
+
+// A copy of a region held by an entry
+enum Stored {
+    Texture(Texture),
+    Buffer(Buffer),
+}
+
+impl Stored {
+    fn new(render_device: &RenderDevice, region: Region) -> Self {
+        match region {
+            Region::Texture(_) => {
+                Stored::Texture(render_device.create_texture(&TextureDescriptor {
+                    label: Some("stage cache texture"),
+                    size: texture_extent(),
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: TextureDimension::D2,
+                    format: TextureFormat::Rgba32Float,
+                    usage: TextureUsages::COPY_SRC | TextureUsages::COPY_DST,
+                    view_formats: &[],
+                }))
+            }
+            Region::Buffer { size, .. } => {
+                Stored::Buffer(render_device.create_buffer(&BufferDescriptor {
+                    label: Some("stage cache buffer"),
+                    size,
+                    usage: BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }))
+            }
+        }
+    }
+
+    fn size(&self) -> u64 {
+        match self {
+            Stored::Texture(_) => texture_size(),
+            Stored::Buffer(buffer) => buffer.size(),
+        }
+    }
+
+    fn fits(&self, region: &Region) -> bool {
+        match (self, region) {
+            (Stored::Texture(_), Region::Texture(_)) => true,
+            (Stored::Buffer(buffer), Region::Buffer { size, .. }) => buffer.size() == *size,
+            _ => false,
+        }
+    }
+
+    fn encode(&self, encoder: &mut CommandEncoder, region: &Region, restore: bool) {
+        match (self, region) {
+            (Stored::Texture(stored), Region::Texture(texture)) => {
+                let (from, to) = if restore {
+                    (stored, *texture)
+                } else {
+                    (*texture, stored)
+                };
+                encoder.copy_texture_to_texture(
+                    from.as_image_copy(),
+                    to.as_image_copy(),
+                    texture_extent(),
+                );
+            }
+            (
+                Stored::Buffer(stored),
+                Region::Buffer {
+                    buffer,
+                    offset,
+                    size,
+                },
+            ) => {
+                if restore {
+                    encoder.copy_buffer_to_buffer(stored, 0, buffer, *offset, *size);
+                } else {
+                    encoder.copy_buffer_to_buffer(buffer, *offset, stored, 0, *size);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn texture_extent() -> Extent3d {
+    Extent3d {
+        width: BUFFER_LEN as u32,
+        height: BUFFER_LEN as u32,
+        depth_or_array_layers: 1,
+    }
+}
+
+fn texture_size() -> u64 {
+    // Rgba32Float
+    (BUFFER_LEN * BUFFER_LEN * 16) as u64
+}
+
+// The live chain's textures and buffers
+struct ChainResources<'a> {
+    textures: &'a ImageBufferContainer,
+    images: &'a RenderAssets<GpuImage>,
+    buffers: &'a RenderAssets<GpuShaderStorageBuffer>,
+}
+
+impl<'a> ChainResources<'a> {
+    fn from_world(world: &'a World) -> Option<Self> {
+        Some(Self {
+            textures: world.get_resource::<ImageBufferContainer>()?,
+            images: world.resource::<RenderAssets<GpuImage>>(),
+            buffers: world.resource::<RenderAssets<GpuShaderStorageBuffer>>(),
+        })
+    }
+
+    fn texture(&self, handle: &Handle<Image>) -> Option<Region<'a>> {
+        Some(Region::Texture(&self.images.get(handle)?.texture))
+    }
+
+    fn buffer(
+        &self,
+        handle: &Handle<ShaderStorageBuffer>,
+        offset: u64,
+        size: u64,
+    ) -> Option<Region<'a>> {
+        let buffer = &self.buffers.get(handle)?.buffer;
+        (offset + size <= buffer.size()).then_some(Region::Buffer {
+            buffer,
+            offset,
+            size,
+        })
+    }
+
+    fn whole_buffer(&self, handle: &Handle<ShaderStorageBuffer>) -> Option<Region<'a>> {
+        let size = self.buffers.get(handle)?.buffer.size();
+        self.buffer(handle, 0, size)
+    }
+
+    fn output(&self, output: ChainOutput, stage: usize) -> Option<Vec<Region<'a>>> {
+        let t = self.textures;
+        match output {
+            ChainOutput::Texture(n) => {
+                let (a, b) = match n {
+                    1 => (&t.tex_buffer_a1, &t.tex_buffer_b1),
+                    2 => (&t.tex_buffer_a2, &t.tex_buffer_b2),
+                    _ => (&t.tex_buffer_a3, &t.tex_buffer_b3),
+                };
+                Some(vec![self.texture(a)?, self.texture(b)?])
+            }
+            ChainOutput::GridA => Some(vec![self.whole_buffer(&t.grid_buffer_a)?]),
+            ChainOutput::Strips => Some(vec![
+                self.whole_buffer(&t.strip_buffer_a)?,
+                self.whole_buffer(&t.strip_buffer_b)?,
+            ]),
+            ChainOutput::RockMask => Some(vec![self.whole_buffer(&t.rock_mask)?]),
+            ChainOutput::OreCounts => {
+                let range = ore_counts_range();
+                Some(vec![self.buffer(
+                    &t.ore_buffer,
+                    range.start,
+                    range.end - range.start,
+                )?])
+            }
+            ChainOutput::Convergence => {
+                let size = (MAX_CONVERGENCE_ITERATIONS * std::mem::size_of::<u32>()) as u64;
+                Some(vec![self.buffer(
+                    &t.convergence,
+                    stage as u64 * size,
+                    size,
+                )?])
+            }
+        }
+    }
+
+    fn stage(&self, stage: usize, name: &str) -> Option<Vec<Region<'a>>> {
+        let regions = stage_outputs(name)
+            .iter()
+            .map(|output| self.output(*output, stage))
+            .collect::<Option<Vec<_>>>()?;
+        Some(regions.concat())
+    }
+
+    fn result(&self) -> Option<Vec<Region<'a>>> {
+        Some(vec![self.texture(&self.textures.result)?])
+    }
+}
+
+struct CacheEntry {
+    key: u64,
+    // the stage it belongs to, the stage count for the extract pass
+    stage: usize,
+    // copies of the regions the stage writes, in the order ChainResources gives them
+    stored: Vec<Stored>,
+    last_used: u64,
+}
+
+impl CacheEntry {
+    fn size(&self) -> u64 {
+        self.stored.iter().map(Stored::size).sum()
+    }
+
+    fn fits(&self, regions: &[Region]) -> bool {
+        self.stored.len() == regions.len()
+            && self.stored.iter().zip(regions).all(|(s, r)| s.fits(r))
+    }
+
+    fn encode(&self, encoder: &mut CommandEncoder, regions: &[Region], restore: bool) {
+        for (stored, region) in self.stored.iter().zip(regions) {
+            stored.encode(encoder, region, restore);
+        }
+    }
+}
+
+// What the live chain does with the cache this frame
+#[derive(Default)]
+struct CachePlan {
+    // the first stage that runs, the ones before it are copied back
+    restart: usize,
+    // by stage, the entry copied back for stages before restart and the one stored into after
+    entries: Vec<Option<u64>>,
+    // the extract pass's entry and whether it's copied back rather than stored
+    result: Option<(u64, bool)>,
+}
+
+#[derive(Resource, Default)]
+pub struct StageCache {
+    entries: Vec<CacheEntry>,
+    frame: u64,
+    plan: CachePlan,
+    // entries the nodes couldn't fill because a dispatch wasn't ready, dropped next frame
+    discarded: Mutex<Vec<u64>>,
+    // set once a stage couldn't dispatch, everything after it saw the wrong inputs
+    spoiled: AtomicBool,
+}
+
+impl StageCache {
+    // whether the live chain dispatches the stage, the ones before restart come from the cache
+    pub fn runs(&self, stage: usize) -> bool {
+        stage >= self.plan.restart
+    }
+
+    pub fn restores_result(&self) -> bool {
+        matches!(self.plan.result, Some((_, true)))
+    }
+
+    fn entry(&self, key: u64) -> Option<&CacheEntry> {
+        self.entries.iter().find(|e| e.key == key)
+    }
+
+    fn bytes(&self) -> u64 {
+        self.entries.iter().map(|e| e.size()).sum()
+    }
+
+    // Drop the least recently used entry not used this frame, later stages first since they're
+    // no use without the ones before them
+    fn evict_lru(&mut self, frame: u64) -> Option<CacheEntry> {
+        let (index, _) = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| e.last_used < frame)
+            .min_by_key(|(_, e)| (e.last_used, std::cmp::Reverse(e.stage)))?;
+        Some(self.entries.swap_remove(index))
+    }
+
+    // Make room for an entry for these regions within the budget, reusing an evicted one that
+    // has the same layout
+    fn allocate(
+        &mut self,
+        render_device: &RenderDevice,
+        key: u64,
+        stage: usize,
+        regions: &[Region],
+        budget: u64,
+    ) -> bool {
+        let frame = self.frame;
+        let size = regions
+            .iter()
+            .map(|region| match region {
+                Region::Texture(_) => texture_size(),
+                Region::Buffer { size, .. } => *size,
+            })
+            .sum::<u64>();
+
+        let mut recycled = None;
+        while self.bytes() + size > budget {
+            let Some(evicted) = self.evict_lru(frame) else {
+                return false;
+            };
+            if evicted.fits(regions) {
+                recycled = Some(evicted);
+            }
+        }
+
+        let stored = match recycled {
+            Some(entry) => entry.stored,
+            None => regions
+                .iter()
+                .map(|region| Stored::new(render_device, *region))
+                .collect(),
+        };
+        self.entries.push(CacheEntry {
+            key,
+            stage,
+            stored,
+            last_used: frame,
+        });
+        true
+    }
+
+    // Called by the first live node, copies back what the stages before the restart wrote,
+    // each output from the last of them to write it
+    pub fn encode_restores(&self, encoder: &mut CommandEncoder, world: &World) {
+        let Some(resources) = ChainResources::from_world(world) else {
+            return;
+        };
+        let configs = &world.resource::<ShaderConfigHolder>().shader_configs;
+
+        let mut restored = Vec::new();
+        for stage in (0..self.plan.restart).rev() {
+            let Some(entry) = self.plan.entries[stage].and_then(|key| self.entry(key)) else {
+                continue;
+            };
+
+            let mut regions = Vec::new();
+            let mut keep = Vec::new();
+            for output in stage_outputs(configs[stage].name()) {
+                let Some(output_regions) = resources.output(*output, stage) else {
+                    return;
+                };
+                // each stage has its own part of the change counts
+                let latest = *output == ChainOutput::Convergence || !restored.contains(output);
+                keep.extend(std::iter::repeat_n(latest, output_regions.len()));
+                regions.extend(output_regions);
+                if latest {
+                    restored.push(*output);
+                }
+            }
+
+            for ((stored, region), keep) in entry.stored.iter().zip(&regions).zip(keep) {
+                if keep {
+                    stored.encode(encoder, region, true);
+                }
+            }
+        }
+    }
+
+    // Called by each live stage node after its dispatches, copies what it wrote into its entry,
+    // or gives the entry up when not every dispatch was ready
+    pub fn encode_store(
+        &self,
+        encoder: &mut CommandEncoder,
+        world: &World,
+        stage: usize,
+        ready: bool,
+    ) {
+        let Some(key) = self.plan.entries.get(stage).copied().flatten() else {
+            return;
+        };
+        if !self.runs(stage) {
+            return;
+        }
+
+        let configs = &world.resource::<ShaderConfigHolder>().shader_configs;
+        let entry = self.entry(key);
+        let regions = ChainResources::from_world(world)
+            .and_then(|resources| resources.stage(stage, configs[stage].name()));
+        if !ready {
+            self.spoiled.store(true, Ordering::Relaxed);
+        }
+        match (self.spoiled.load(Ordering::Relaxed), entry, regions) {
+            (false, Some(entry), Some(regions)) => entry.encode(encoder, &regions, false),
+            _ => self.discarded.lock().unwrap().push(key),
+        }
+    }
+
+    // Called by the final live node, copies the result back or into its entry
+    pub fn encode_result(&self, encoder: &mut CommandEncoder, world: &World) {
+        let Some((key, restore)) = self.plan.result else {
+            return;
+        };
+        if !restore && self.spoiled.load(Ordering::Relaxed) {
+            self.discarded.lock().unwrap().push(key);
+            return;
+        }
+        let entry = self.entry(key);
+        let regions = ChainResources::from_world(world).and_then(|resources| resources.result());
+        if let (Some(entry), Some(regions)) = (entry, regions) {
+            entry.encode(encoder, &regions, restore);
+        }
+    }
+}
+
+pub struct StageCachePlugin;
+
+impl Plugin for StageCachePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<StageCacheSettings>();
+        app.init_resource::<StageCacheStats>();
+        app.add_plugins(ExtractResourcePlugin::<StageCacheSettings>::default());
+        app.add_systems(PostUpdate, update_cache_keys);
+    }
+
+    fn finish(&self, app: &mut App) {
+        let stats = app.world().resource::<StageCacheStats>().clone();
+
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app.insert_resource(stats);
+        render_app.init_resource::<StageCache>();
+        render_app.add_systems(
+            Render,
+            prepare_stage_cache.in_set(RenderSet::PrepareResources),
+        );
+    }
+}
+
+// Chain each stage's key on from the one before it with what the stage itself reads, so a
+// change only misses from the first stage it reaches
+#[allow(clippy::too_many_arguments)]
+fn update_cache_keys(
+    params: Res<ParamsUniform>,
+    view: Res<ViewParams>,
+    configs: Res<ShaderConfigHolder>,
+    gradients: Res<Gradients>,
+    edits: Res<TerrainEdits>,
+    materials: Res<MaterialTable>,
+    ores: Res<OreTable>,
+    status: Res<PipelineStatus>,
+    mut settings: ResMut<StageCacheSettings>,
+) {
+    // a reloaded shader makes every stored output stale
+    let mut running = {
+        let mut hasher = DefaultHasher::new();
+        status.compiled().hash(&mut hasher);
+        hasher.finish()
+    };
+
+    let mut keys = Vec::with_capacity(configs.shader_configs.len());
+    for (stage, config) in configs.shader_configs.iter().enumerate() {
+        let mut hasher = DefaultHasher::new();
+        running.hash(&mut hasher);
+        stage.hash(&mut hasher);
+        config.shader_path.hash(&mut hasher);
+        view.stage_iterations(stage, config.iterations)
+            .hash(&mut hasher);
+        config.shader_defs.hash(&mut hasher);
+        config.converge_below.hash(&mut hasher);
+
+        match stage_params(config.name(), &params) {
+            Some(fields) => {
+                for (_, bytes) in fields {
+                    bytes.hash(&mut hasher);
+                }
+            }
+            None => {
+                bytemuck::bytes_of(&*params).hash(&mut hasher);
+                edits.hash_into(&mut hasher);
+                ores.hash_into(&mut hasher);
+            }
+        }
+        match config.name() {
+            "apply_edits" => edits.hash_into(&mut hasher),
+            "place_ores" => ores.hash_into(&mut hasher),
+            _ => {}
+        }
+
+        running = hasher.finish();
+        keys.push(running);
+    }
+
+    // the view, the gradient and the material colours only go in to the extract pass
+    let mut hasher = DefaultHasher::new();
+    running.hash(&mut hasher);
+    for (_, bytes) in stage_params("extract", &params).unwrap_or_default() {
+        bytes.hash(&mut hasher);
+    }
+    bytemuck::bytes_of(&*view).hash(&mut hasher);
+    bytemuck::bytes_of(&materials.colours()).hash(&mut hasher);
+    // the gradient only matters when it's used for false colour but it's cheap to include
+    for (t, colour) in &gradients.gradient.stops {
+        [t, &colour.h, &colour.s, &colour.v, &colour.a]
+            .map(|f| f.to_bits())
+            .hash(&mut hasher);
+    }
+    (gradients.gradient.interpolation_method as u8).hash(&mut hasher);
+    let extract_key = hasher.finish();
+
+    if settings.keys != keys || settings.extract_key != extract_key {
+        settings.keys = keys;
+        settings.extract_key = extract_key;
+    }
+}
+
+// Find the first stage without an entry, the live chain restarts there, and make entries for
+// it and every stage after it
+#[allow(clippy::too_many_arguments)]
+fn prepare_stage_cache(
+    mut cache: ResMut<StageCache>,
+    settings: Res<StageCacheSettings>,
+    profiler: Res<ProfilerSettings>,
+    changed: Res<ParamsChanged>,
+    stats: Res<StageCacheStats>,
+    pipelines: Option<Res<ComputePipelines>>,
+    pipeline_cache: Res<PipelineCache>,
+    textures: Option<Res<ImageBufferContainer>>,
+    images: Res<RenderAssets<GpuImage>>,
+    buffers: Res<RenderAssets<GpuShaderStorageBuffer>>,
+    configs: Res<ShaderConfigHolder>,
+    view: Res<ViewParams>,
+    render_device: Res<RenderDevice>,
+) {
+    cache.plan = CachePlan::default();
+    cache.spoiled = AtomicBool::new(false);
+    cache.frame += 1;
+    let frame = cache.frame;
+
+    let discarded = std::mem::take(&mut *cache.discarded.lock().unwrap());
+    cache.entries.retain(|e| !discarded.contains(&e.key));
+
+    let budget = settings.budget_mb as u64 * MEGABYTE;
+    if !settings.enabled {
+        cache.entries.clear();
+    }
+    while cache.bytes() > budget && cache.evict_lru(frame).is_some() {}
+
+    // profiling needs the passes to actually run
+    let stages = configs.shader_configs.len();
+    if settings.enabled && changed.0 && !profiler.enabled && settings.keys.len() == stages {
+        if let (Some(pipelines), Some(textures)) = (pipelines, textures) {
+            let resources = ChainResources {
+                textures: &textures,
+                images: &images,
+                buffers: &buffers,
+            };
+            let iterations = |stage: usize| {
+                view.stage_iterations(stage, configs.shader_configs[stage].iterations)
+            };
+
+            let mut plan = CachePlan {
+                restart: stages,
+                entries: vec![None; stages],
+                result: None,
+            };
+            // stages that don't run have nothing to copy back
+            for stage in (0..stages).filter(|stage| iterations(*stage) > 0) {
+                let key = settings.keys[stage];
+                if cache.entry(key).is_none() {
+                    plan.restart = stage;
+                    break;
+                }
+                plan.entries[stage] = Some(key);
+            }
+            for key in plan.entries.iter().flatten() {
+                if let Some(entry) = cache.entries.iter_mut().find(|e| e.key == *key) {
+                    entry.last_used = frame;
+                }
+            }
+            let restored = plan.entries.iter().flatten().count() as u32;
+            let result = cache
+                .entries
+                .iter_mut()
+                .find(|e| e.key == settings.extract_key);
+            if let Some(entry) = result {
+                entry.last_used = frame;
+                plan.result = Some((settings.extract_key, true));
+            }
+
+            // don't cache the outputs of a chain that's still waiting on pipelines
+            let ready = pipelines
+                .pipeline_configs
+                .iter()
+                .chain(std::iter::once(&pipelines.final_pass))
+                .all(|id| pipeline_cache.get_compute_pipeline(*id).is_some());
+            if ready {
+                for stage in (plan.restart..stages).filter(|stage| iterations(*stage) > 0) {
+                    let key = settings.keys[stage];
+                    let Some(regions) =
+                        resources.stage(stage, configs.shader_configs[stage].name())
+                    else {
+                        break;
+                    };
+                    if !cache.allocate(&render_device, key, stage, &regions, budget) {
+                        break;
+                    }
+                    plan.entries[stage] = Some(key);
+                }
+
+                if plan.result.is_none() {
+                    if let Some(regions) = resources.result() {
+                        let key = settings.extract_key;
+                        if cache.allocate(&render_device, key, stages, &regions, budget) {
+                            plan.result = Some((key, false));
+                        }
+                    }
+                }
+            }
+
+            let ran = (plan.restart..stages)
+                .filter(|stage| iterations(*stage) > 0)
+                .count() as u32;
+            let mut inner = stats.inner.lock().unwrap();
+            inner.restart = Some(plan.restart);
+            if plan.result.is_some_and(|(_, restore)| restore) {
+                inner.hits += restored + 1;
+                inner.misses += ran;
+            } else {
+                inner.hits += restored;
+                inner.misses += ran + 1;
+            }
+            drop(inner);
+
+            cache.plan = plan;
+        }
+    }
+
+    let mut inner = stats.inner.lock().unwrap();
+    inner.entries = cache.entries.len();
+    inner.bytes = cache.bytes();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute_plugin::default_shader_configs;
+    use std::{collections::BTreeSet, path::Path};
+
+    fn shader_source(path: &str) -> String {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("assets")
+            .join(path);
+        let source =
+            std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+        // commented out code doesn't count
+        source
+            .lines()
+            .map(|line| line.split("//").next().unwrap())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    // the identifiers after each params. in the source
+    fn params_read(source: &str) -> BTreeSet<String> {
+        source
+            .match_indices("params.")
+            .filter(|(i, _)| !source[..*i].ends_with(|c: char| c.is_alphanumeric() || c == '_'))
+            .map(|(i, m)| {
+                source[i + m.len()..]
+                    .chars()
+                    .take_while(|c| c.is_alphanumeric() || *c == '_')
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn outputs_written(source: &str) -> Vec<ChainOutput> {
+        let mut outputs = Vec::new();
+        let mut add = |output| {
+            if !outputs.contains(&output) {
+                outputs.push(output);
+            }
+        };
+        for line in source.lines().map(str::trim) {
+            let assigns =
+                line.contains(" = ") && !line.starts_with("let ") && !line.starts_with("var ");
+            for n in 1..=3 {
+                if line.contains(&format!("textureStore(otex_{}", n)) {
+                    add(ChainOutput::Texture(n));
+                }
+            }
+            if assigns && line.starts_with("grid_a.") {
+                add(ChainOutput::GridA);
+            }
+            if assigns && (line.starts_with("strip_a.") || line.starts_with("strip_b.")) {
+                add(ChainOutput::Strips);
+            }
+            if assigns && line.starts_with("rock_mask[") {
+                add(ChainOutput::RockMask);
+            }
+            if line.contains("(&ores.counts") {
+                add(ChainOutput::OreCounts);
+            }
+            if line.contains("count_change()") {
+                add(ChainOutput::Convergence);
+            }
+        }
+        outputs
+    }
+
+    #[test]
+    fn stages_declare_what_their_shaders_read_and_write() {
+        let params = ParamsUniform::default();
+
+        let mut stages = default_shader_configs()
+            .iter()
+            .map(|config| (config.name(), config.shader_path.to_string()))
+            .collect::<Vec<_>>();
+        stages.push(("extract", "shaders/extract.wgsl".to_string()));
+
+        for (name, path) in stages {
+            let source = shader_source(&path);
+
+            let declared = stage_params(name, &params)
+                .unwrap_or_else(|| panic!("{} has no fields listed", name))
+                .into_iter()
+                .map(|(field, _)| field.to_string())
+                .collect::<BTreeSet<_>>();
+            // function arguments named params shadow the uniform, they only read their own fields
+            let read = params_read(&source)
+                .into_iter()
+                .filter(|field| {
+                    !matches!(field.as_str(), "amount" | "scale" | "offset_x" | "offset_y")
+                })
+                .collect::<BTreeSet<_>>();
+            assert_eq!(read, declared, "{} reads different params", name);
+
+            if name != "extract" {
+                let mut written = outputs_written(&source);
+                let mut declared = stage_outputs(name).to_vec();
+                let order = |o: &ChainOutput| format!("{:?}", o);
+                written.sort_by_key(order);
+                declared.sort_by_key(order);
+                assert_eq!(written, declared, "{} writes different outputs", name);
+            }
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use bevy::{
+    prelude::*,
+    render::gpu_readback::{Readback, ReadbackComplete},
+};
+use bevy_egui::egui::{self, epaint::PathStroke, pos2, vec2, Color32, Sense, Stroke, Ui};
+
+use crate::{constants::*, ImageBufferContainer, ParamsChanged};
+
+// number of points drawn per strip, the strips are decimated down to this
+const PLOT_POINTS: usize = 1024;
+
+const STRIP_COLOURS: [Color32; STRIP_COUNT] = [Color32::LIGHT_BLUE, Color32::LIGHT_GREEN, Color32::LIGHT_RED];
+
+// CPU copy of the float strips, refreshed whenever the parameters change
+#[derive(Resource, Default)]
+pub struct StripData {
+    pub floats_a: Vec<f32>,
+    pub floats_b: Vec<f32>,
+}
+
+impl StripData {
+    pub fn strip(&self, buffer_b: bool, index: usize) -> Option<&[f32]> {
+        let floats = if buffer_b { &self.floats_b } else { &self.floats_a };
+        floats.get(index * STRIP_SIZE..(index + 1) * STRIP_SIZE)
+    }
+}
+
+#[derive(Resource)]
+pub struct StripPlotSettings {
+    pub polar: bool,
+    // which strips are drawn, a then b
+    pub visible: [[bool; STRIP_COUNT]; 2],
+    // polar only, the radius the zero line is drawn at as a fraction of the plot
+    pub polar_base: f32,
+    pub polar_scale: f32,
+    pub refresh_requested: bool,
+}
+
+impl Default for StripPlotSettings {
+    fn default() -> Self {
+        let mut visible = [[false; STRIP_COUNT]; 2];
+        // the height profile lives in strip_b.floats[0], or strip_a.floats[0] after an odd
+        // number of erosion passes
+        visible[1][0] = true;
+        Self {
+            polar: false,
+            visible,
+            polar_base: 0.6,
+            polar_scale: 0.1,
+            refresh_requested: false,
+        }
+    }
+}
+
+pub struct StripPlotPlugin;
+
+impl Plugin for StripPlotPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<StripData>();
+        app.init_resource::<StripPlotSettings>();
+        app.add_systems(PostUpdate, request_strip_readback);
+    }
+}
+
+// Read both strip buffers back once after the chain has been re-run
+fn request_strip_readback(
+    mut commands: Commands,
+    changed: Res<ParamsChanged>,
+    mut settings: ResMut<StripPlotSettings>,
+    buffers: Option<Res<ImageBufferContainer>>,
+) {
+    let Some(buffers) = buffers else {
+        return;
+    };
+
+    if !changed.is_changed() && !settings.refresh_requested {
+        return;
+    }
+    settings.refresh_requested = false;
+
+    for (handle, buffer_b) in [
+        (buffers.strip_buffer_a.clone(), false),
+        (buffers.strip_buffer_b.clone(), true),
+    ] {
+        commands.spawn(Readback::buffer(handle)).observe(
+            move |trigger: Trigger<ReadbackComplete>,
+                  mut commands: Commands,
+                  mut data: ResMut<StripData>| {
+                // the floats come first in DataStrip, the ints are not plotted
+                let mut floats: Vec<f32> = bytemuck::pod_collect_to_vec(&trigger.event().0);
+                floats.truncate(STRIP_SIZE * STRIP_COUNT);
+
+                if buffer_b {
+                    data.floats_b = floats;
+                } else {
+                    data.floats_a = floats;
+                }
+
+                commands.entity(trigger.entity()).despawn();
+            },
+        );
+    }
+}
+
+/// Line graph of the strips, either unrolled or wrapped around a circle
+pub fn strip_plot(ui: &mut Ui, data: &StripData, settings: &mut StripPlotSettings) {
+    ui.horizontal(|ui| {
+        ui.radio_value(&mut settings.polar, false, "linear");
+        ui.radio_value(&mut settings.polar, true, "polar");
+        if ui.button("refresh").clicked() {
+            settings.refresh_requested = true;
+        }
+    });
+
+    for (b, name) in [(0, "a"), (1, "b")] {
+        ui.horizontal(|ui| {
+            for i in 0..STRIP_COUNT {
+                ui.checkbox(&mut settings.visible[b][i], format!("strip {} {}", name, i));
+            }
+        });
+    }
+
+    if settings.polar {
+        ui.add(egui::Slider::new(&mut settings.polar_base, 0.0..=1.0).text("base radius"));
+        ui.add(egui::Slider::new(&mut settings.polar_scale, 0.0..=1.0).text("scale"));
+    }
+
+    let visible: Vec<(&[f32], Color32)> = (0..2)
+        .flat_map(|b| (0..STRIP_COUNT).map(move |i| (b, i)))
+        .filter(|(b, i)| settings.visible[*b][*i])
+        .filter_map(|(b, i)| {
+            let colour = if b == 0 {
+                STRIP_COLOURS[i]
+            } else {
+                STRIP_COLOURS[i].gamma_multiply(0.6)
+            };
+            data.strip(b == 1, i).map(|s| (s, colour))
+        })
+        .collect();
+
+    let width = ui.available_width();
+    let height = if settings.polar { width } else { width * 0.5 };
+    let (response, painter) = ui.allocate_painter(vec2(width, height), Sense::hover());
+    let rect = response.rect;
+    painter.rect_filled(rect, 0.0, Color32::from_gray(20));
+
+    if visible.is_empty() {
+        return;
+    }
+
+    // shared value range so the strips can be compared
+    let (min, max) = visible
+        .iter()
+        .flat_map(|(s, _)| s.iter())
+        .fold((f32::MAX, f32::MIN), |(lo, hi), v| (lo.min(*v), hi.max(*v)));
+    let range = (max - min).max(f32::EPSILON);
+
+    let step = (STRIP_SIZE / PLOT_POINTS).max(1);
+
+    if settings.polar {
+        let centre = rect.center();
+        let radius = rect.width().min(rect.height()) * 0.5;
+        painter.circle_stroke(
+            centre,
+            radius * settings.polar_base,
+            Stroke::new(1.0, Color32::from_gray(60)),
+        );
+
+        for (strip, colour) in &visible {
+            // same mapping as init_generate_circle, index 0 sits at angle -PI
+            let points: Vec<_> = (0..STRIP_SIZE)
+                .step_by(step)
+                .map(|i| {
+                    let angle = i as f32 / STRIP_SIZE as f32 * std::f32::consts::TAU
+                        - std::f32::consts::PI;
+                    let normalized = (strip[i] - min) / range * 2.0 - 1.0;
+                    let r = radius * (settings.polar_base + normalized * settings.polar_scale);
+                    // y is flipped so the plot matches the texture orientation
+                    pos2(centre.x + angle.cos() * r, centre.y - angle.sin() * r)
+                })
+                .collect();
+            painter.add(egui::Shape::closed_line(points, PathStroke::new(1.0, *colour)));
+        }
+    } else {
+        for (strip, colour) in &visible {
+            let points: Vec<_> = (0..STRIP_SIZE)
+                .step_by(step)
+                .map(|i| {
+                    let x = rect.left() + i as f32 / STRIP_SIZE as f32 * rect.width();
+                    let y = rect.bottom() - (strip[i] - min) / range * rect.height();
+                    pos2(x, y)
+                })
+                .collect();
+            painter.add(egui::Shape::line(points, PathStroke::new(1.0, *colour)));
+        }
+
+        painter.text(
+            rect.left_top(),
+            egui::Align2::LEFT_TOP,
+            format!("{:.3}", max),
+            egui::FontId::monospace(10.0),
+            Color32::GRAY,
+        );
+        painter.text(
+            rect.left_bottom(),
+            egui::Align2::LEFT_BOTTOM,
+            format!("{:.3}", min),
+            egui::FontId::monospace(10.0),
+            Color32::GRAY,
+        );
+    }
+}